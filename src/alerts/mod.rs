@@ -0,0 +1,378 @@
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::collector::Snapshot;
+use crate::config::{AlertSettings, WebhookSettings};
+use crate::metrics::exposition::{format_value, series_labels};
+
+mod notify;
+pub mod rule;
+
+use rule::Rule;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertState {
+    Pending,
+    Firing,
+    Resolved,
+}
+
+/// One series of a rule whose condition holds, or held until `resolved_at`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alert {
+    pub name: String,
+    pub state: AlertState,
+    /// Series labels, rule labels and `alertname`
+    pub labels: BTreeMap<String, String>,
+    pub annotations: BTreeMap<String, String>,
+    pub value: f64,
+    #[serde(serialize_with = "serialize_time")]
+    pub active_at: SystemTime,
+    #[serde(
+        serialize_with = "serialize_optional_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fired_at: Option<SystemTime>,
+    #[serde(
+        serialize_with = "serialize_optional_time",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub resolved_at: Option<SystemTime>,
+}
+
+fn serialize_time<S: Serializer>(time: &SystemTime, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&humantime::format_rfc3339_millis(*time).to_string())
+}
+
+fn serialize_optional_time<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_time(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Result of evaluating every rule against one snapshot
+#[derive(Debug)]
+pub struct Evaluation {
+    /// Alerts that started firing or resolved in this evaluation
+    pub changed: Vec<Alert>,
+    /// Every alert currently firing
+    pub firing: Vec<Alert>,
+    pub time: SystemTime,
+}
+
+type AlertKey = (usize, BTreeMap<String, String>);
+
+/// Evaluates alert rules after each background collection and keeps the
+/// pending and firing alerts in memory
+pub struct Evaluator {
+    rules: Vec<Rule>,
+    active: RwLock<BTreeMap<AlertKey, Alert>>,
+    template: Regex,
+}
+
+impl Evaluator {
+    pub fn from_settings(settings: &AlertSettings) -> anyhow::Result<Self> {
+        let rules = settings
+            .rules
+            .iter()
+            .map(Rule::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(anyhow::Error::msg)?;
+
+        Ok(Self {
+            rules,
+            active: RwLock::new(BTreeMap::new()),
+            template: Regex::new(r"\{\{\s*\$(value|labels\.([A-Za-z_][A-Za-z0-9_]*))\s*\}\}")
+                .expect("annotation template pattern is valid"),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.rules.is_empty()
+    }
+
+    /// Pending and firing alerts, ordered by rule then labels
+    pub fn active(&self) -> Vec<Alert> {
+        self.active.read().unwrap().values().cloned().collect()
+    }
+
+    /// Evaluate every snapshot broadcast by the collector and forward the
+    /// result to the configured webhooks
+    pub fn spawn(
+        self: &Arc<Self>,
+        webhooks: Vec<WebhookSettings>,
+        mut snapshots: broadcast::Receiver<Arc<Snapshot>>,
+    ) -> anyhow::Result<()> {
+        info!(
+            "Evaluating {} alert rule(s) with {} webhook(s)",
+            self.rules.len(),
+            webhooks.len()
+        );
+
+        let notifiers = webhooks
+            .into_iter()
+            .map(notify::spawn)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let evaluator = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let snapshot = match snapshots.recv().await {
+                    Ok(snapshot) => snapshot,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Alert evaluation skipped {} snapshot(s)", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+
+                let evaluation = Arc::new(evaluator.evaluate(&snapshot));
+                for notifier in &notifiers {
+                    notifier.notify(evaluation.clone());
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    fn evaluate(&self, snapshot: &Snapshot) -> Evaluation {
+        let now = snapshot.timestamp;
+        let mut active = self.active.write().unwrap();
+        let mut seen = BTreeSet::new();
+        let mut changed = Vec::new();
+        // Rules see every metric, not only those the selector lets through
+        let families = snapshot.metrics.families();
+
+        for (index, rule) in self.rules.iter().enumerate() {
+            let Some(family) = families.iter().find(|f| f.name == rule.metric) else {
+                continue;
+            };
+
+            for sample in &family.samples {
+                let labels = series_labels(sample, &snapshot.metrics.labels);
+                if !rule.matches(&labels, sample.value) {
+                    continue;
+                }
+
+                let series: BTreeMap<String, String> = labels
+                    .iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect();
+                let key = (index, series);
+                seen.insert(key.clone());
+
+                let alert = active.entry(key).or_insert_with_key(|(_, series)| Alert {
+                    name: rule.name.clone(),
+                    state: AlertState::Pending,
+                    labels: alert_labels(rule, series),
+                    annotations: BTreeMap::new(),
+                    value: sample.value,
+                    active_at: now,
+                    fired_at: None,
+                    resolved_at: None,
+                });
+                alert.value = sample.value;
+                alert.annotations = self.annotations(rule, &alert.labels, sample.value);
+
+                let held = now.duration_since(alert.active_at).unwrap_or_default();
+                if alert.state == AlertState::Pending && held >= rule.for_duration {
+                    alert.state = AlertState::Firing;
+                    alert.fired_at = Some(now);
+                    info!("Alert {} firing: {:?}", rule.name, alert.labels);
+                    changed.push(alert.clone());
+                }
+            }
+        }
+
+        // Conditions that no longer hold, including series that disappeared
+        let stale: Vec<AlertKey> = active
+            .keys()
+            .filter(|key| !seen.contains(key))
+            .cloned()
+            .collect();
+        for key in stale {
+            if let Some(mut alert) = active.remove(&key) {
+                if alert.state == AlertState::Firing {
+                    alert.state = AlertState::Resolved;
+                    alert.resolved_at = Some(now);
+                    info!("Alert {} resolved: {:?}", alert.name, alert.labels);
+                    changed.push(alert);
+                }
+            }
+        }
+
+        let firing = active
+            .values()
+            .filter(|alert| alert.state == AlertState::Firing)
+            .cloned()
+            .collect();
+
+        Evaluation {
+            changed,
+            firing,
+            time: now,
+        }
+    }
+
+    /// Expand `{{ $value }}` and `{{ $labels.<name> }}` in rule annotations
+    fn annotations(
+        &self,
+        rule: &Rule,
+        labels: &BTreeMap<String, String>,
+        value: f64,
+    ) -> BTreeMap<String, String> {
+        rule.settings
+            .annotations
+            .iter()
+            .map(|(name, template)| {
+                let expanded = self
+                    .template
+                    .replace_all(template, |caps: &regex::Captures| match caps.get(2) {
+                        Some(label) => labels.get(label.as_str()).cloned().unwrap_or_default(),
+                        None => format_value(value),
+                    });
+                (name.clone(), expanded.into_owned())
+            })
+            .collect()
+    }
+}
+
+fn alert_labels(rule: &Rule, series: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+    let mut labels = series.clone();
+    labels.extend(rule.settings.labels.clone());
+    labels.insert("alertname".to_string(), rule.name.clone());
+    labels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AlertRuleSettings;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn evaluator(expr: &str, for_duration: Option<&str>) -> Evaluator {
+        Evaluator::from_settings(&AlertSettings {
+            rules: vec![AlertRuleSettings {
+                name: "NodeUp".to_string(),
+                expr: expr.to_string(),
+                for_duration: for_duration.map(str::to_string),
+                labels: BTreeMap::from([("severity".to_string(), "info".to_string())]),
+                annotations: BTreeMap::from([(
+                    "summary".to_string(),
+                    "{{ $labels.cluster }} up for {{$value}}s".to_string(),
+                )]),
+            }],
+            webhooks: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    /// Snapshot taken `secs` after the first one, in cluster `cluster`
+    fn snapshot(cluster: &str, secs: u64) -> Snapshot {
+        let mut snapshot = Snapshot::with_families(Vec::new(), &[("cluster", cluster)]);
+        snapshot.timestamp = UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs);
+        snapshot
+    }
+
+    fn states(evaluator: &Evaluator) -> Vec<AlertState> {
+        evaluator.active().iter().map(|alert| alert.state).collect()
+    }
+
+    #[test]
+    fn evaluates_metrics_left_out_by_the_selector() {
+        let settings = AlertSettings {
+            rules: vec![AlertRuleSettings {
+                name: "NodeUp".to_string(),
+                expr: "hw_node_uptime_seconds >= 0".to_string(),
+                for_duration: None,
+                labels: BTreeMap::new(),
+                annotations: BTreeMap::new(),
+            }],
+            webhooks: Vec::new(),
+        };
+        let evaluator = Evaluator::from_settings(&settings).unwrap();
+
+        let evaluation = evaluator.evaluate(&Snapshot::with_families(Vec::new(), &[]));
+        assert_eq!(evaluation.firing.len(), 1);
+        assert_eq!(evaluation.firing[0].labels["alertname"], "NodeUp");
+    }
+
+    #[test]
+    fn fires_once_the_condition_held_for_the_duration() {
+        let evaluator = evaluator(r#"hw_node_uptime_seconds{cluster="c1"} >= 0 for 1m"#, None);
+
+        let evaluation = evaluator.evaluate(&snapshot("c1", 0));
+        assert!(evaluation.changed.is_empty());
+        assert!(evaluation.firing.is_empty());
+        assert_eq!(states(&evaluator), [AlertState::Pending]);
+
+        let evaluation = evaluator.evaluate(&snapshot("c1", 30));
+        assert!(evaluation.changed.is_empty());
+        assert_eq!(states(&evaluator), [AlertState::Pending]);
+
+        let evaluation = evaluator.evaluate(&snapshot("c1", 60));
+        assert_eq!(evaluation.changed.len(), 1);
+        let alert = &evaluation.changed[0];
+        assert_eq!(alert.state, AlertState::Firing);
+        assert_eq!(
+            alert.active_at,
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000)
+        );
+        assert_eq!(alert.fired_at, Some(evaluation.time));
+        assert_eq!(alert.labels["alertname"], "NodeUp");
+        assert_eq!(alert.labels["severity"], "info");
+        assert_eq!(alert.labels["cluster"], "c1");
+        assert_eq!(
+            alert.annotations["summary"],
+            format!("c1 up for {}s", format_value(alert.value))
+        );
+        assert_eq!(evaluation.firing.len(), 1);
+
+        // Already firing alerts are not reported as changed again
+        let evaluation = evaluator.evaluate(&snapshot("c1", 90));
+        assert!(evaluation.changed.is_empty());
+        assert_eq!(evaluation.firing.len(), 1);
+    }
+
+    #[test]
+    fn resolves_firing_alerts_once_the_condition_stops() {
+        let evaluator = evaluator(r#"hw_node_uptime_seconds{cluster="c1"} >= 0"#, None);
+        assert_eq!(evaluator.evaluate(&snapshot("c1", 0)).changed.len(), 1);
+
+        let evaluation = evaluator.evaluate(&snapshot("c2", 15));
+        assert_eq!(evaluation.changed.len(), 1);
+        let alert = &evaluation.changed[0];
+        assert_eq!(alert.state, AlertState::Resolved);
+        assert_eq!(alert.resolved_at, Some(evaluation.time));
+        assert!(evaluation.firing.is_empty());
+        assert!(evaluator.active().is_empty());
+
+        // A new occurrence starts over
+        let evaluation = evaluator.evaluate(&snapshot("c1", 30));
+        assert_eq!(evaluation.changed[0].active_at, evaluation.time);
+    }
+
+    #[test]
+    fn pending_alerts_are_dropped_without_resolving() {
+        let evaluator = evaluator(r#"hw_node_uptime_seconds{cluster="c1"} >= 0"#, Some("5m"));
+        evaluator.evaluate(&snapshot("c1", 0));
+        assert_eq!(states(&evaluator), [AlertState::Pending]);
+
+        let evaluation = evaluator.evaluate(&snapshot("c2", 60));
+        assert!(evaluation.changed.is_empty());
+        assert!(evaluator.active().is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+use super::{Alert, AlertState, Evaluation};
+use crate::config::{WebhookFormat, WebhookSettings};
+use crate::push::{apply_auth, build_client, is_retryable, Backoff};
+
+/// Evaluations a slow webhook may fall behind before new ones are dropped
+const NOTIFY_QUEUE_CAPACITY: usize = 16;
+const MAX_ATTEMPTS: u32 = 3;
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+pub struct Notifier {
+    url: String,
+    sender: mpsc::Sender<Arc<Evaluation>>,
+}
+
+impl Notifier {
+    pub fn notify(&self, evaluation: Arc<Evaluation>) {
+        if self.sender.try_send(evaluation).is_err() {
+            warn!(
+                "Alert webhook {} is falling behind, dropping update",
+                self.url
+            );
+        }
+    }
+}
+
+/// Alertmanager `PostableAlert`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PostableAlert<'a> {
+    labels: &'a BTreeMap<String, String>,
+    annotations: &'a BTreeMap<String, String>,
+    starts_at: String,
+    ends_at: String,
+}
+
+#[derive(Serialize)]
+struct GenericPayload<'a> {
+    version: &'static str,
+    status: AlertState,
+    alerts: &'a [Alert],
+}
+
+fn rfc3339(time: SystemTime) -> String {
+    humantime::format_rfc3339_millis(time).to_string()
+}
+
+/// Build the request body for an evaluation, or `None` when nothing is due
+/// Alertmanager expects firing alerts to be re-sent before their `endsAt`
+/// passes, the generic format only reports state changes
+fn payload(
+    settings: &WebhookSettings,
+    evaluation: &Evaluation,
+    last_sent: &mut Option<SystemTime>,
+) -> Option<Vec<u8>> {
+    match settings.format {
+        WebhookFormat::Generic => {
+            if evaluation.changed.is_empty() {
+                return None;
+            }
+            let status = if evaluation
+                .changed
+                .iter()
+                .any(|alert| alert.state == AlertState::Firing)
+            {
+                AlertState::Firing
+            } else {
+                AlertState::Resolved
+            };
+            serde_json::to_vec(&GenericPayload {
+                version: "1",
+                status,
+                alerts: &evaluation.changed,
+            })
+            .ok()
+        }
+        WebhookFormat::Alertmanager => {
+            let resend = Duration::from_secs(settings.resend_interval_secs.max(1));
+            let resend_due = last_sent
+                .and_then(|sent| evaluation.time.duration_since(sent).ok())
+                .is_none_or(|elapsed| elapsed >= resend);
+            let has_firing = !evaluation.firing.is_empty();
+            if evaluation.changed.is_empty() && !(resend_due && has_firing) {
+                return None;
+            }
+            *last_sent = Some(evaluation.time);
+
+            let ends_at = rfc3339(evaluation.time + resend * 4);
+            let resolved = evaluation
+                .changed
+                .iter()
+                .filter(|alert| alert.state == AlertState::Resolved);
+            let alerts: Vec<PostableAlert> = evaluation
+                .firing
+                .iter()
+                .chain(resolved)
+                .map(|alert| PostableAlert {
+                    labels: &alert.labels,
+                    annotations: &alert.annotations,
+                    starts_at: rfc3339(alert.fired_at.unwrap_or(alert.active_at)),
+                    ends_at: match alert.resolved_at {
+                        Some(resolved_at) => rfc3339(resolved_at),
+                        None => ends_at.clone(),
+                    },
+                })
+                .collect();
+            serde_json::to_vec(&alerts).ok()
+        }
+    }
+}
+
+/// Start delivering evaluations to one webhook
+pub fn spawn(settings: WebhookSettings) -> anyhow::Result<Notifier> {
+    let client = build_client(settings.timeout_secs)?;
+    let (sender, mut receiver) = mpsc::channel::<Arc<Evaluation>>(NOTIFY_QUEUE_CAPACITY);
+    let url = settings.url.clone();
+
+    tokio::spawn(async move {
+        let mut last_sent = None;
+
+        while let Some(evaluation) = receiver.recv().await {
+            let Some(body) = payload(&settings, &evaluation, &mut last_sent) else {
+                continue;
+            };
+
+            let mut backoff = Backoff::new(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
+            for attempt in 1..=MAX_ATTEMPTS {
+                let request = apply_auth(client.post(&settings.url), &settings.auth)
+                    .header("Content-Type", "application/json")
+                    .body(body.clone());
+
+                match request.send().await {
+                    Ok(response) if response.status().is_success() => break,
+                    Ok(response) if !is_retryable(response.status()) => {
+                        error!(
+                            "Alert webhook {} rejected notification with {}",
+                            settings.url,
+                            response.status()
+                        );
+                        break;
+                    }
+                    Ok(response) => warn!(
+                        "Alert webhook {} failed with {} (attempt {}/{})",
+                        settings.url,
+                        response.status(),
+                        attempt,
+                        MAX_ATTEMPTS
+                    ),
+                    Err(e) => warn!(
+                        "Alert webhook {} failed: {} (attempt {}/{})",
+                        settings.url, e, attempt, MAX_ATTEMPTS
+                    ),
+                }
+
+                if attempt < MAX_ATTEMPTS {
+                    tokio::time::sleep(backoff.next_delay()).await;
+                }
+            }
+        }
+    });
+
+    Ok(Notifier { url, sender })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::routing::post;
+    use axum::Router;
+    use serde_json::{json, Value};
+    use std::time::UNIX_EPOCH;
+
+    /// Sink standing in for Alertmanager, forwards each request body
+    async fn sink() -> (String, mpsc::UnboundedReceiver<Value>) {
+        let (sender, bodies) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/api/v2/alerts",
+            post(move |body: Bytes| async move {
+                let _ = sender.send(serde_json::from_slice(&body).unwrap());
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v2/alerts", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, bodies)
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(1_700_000_000 + secs)
+    }
+
+    fn alert(state: AlertState, resolved_at: Option<SystemTime>) -> Alert {
+        Alert {
+            name: "GpuHot".to_string(),
+            state,
+            labels: BTreeMap::from([
+                ("alertname".to_string(), "GpuHot".to_string()),
+                ("gpu".to_string(), "0".to_string()),
+            ]),
+            annotations: BTreeMap::from([("summary".to_string(), "GPU 0 at 90".to_string())]),
+            value: 90.0,
+            active_at: at(0),
+            fired_at: Some(at(60)),
+            resolved_at,
+        }
+    }
+
+    fn postable(ends_at: &str) -> Value {
+        json!([{
+            "labels": {"alertname": "GpuHot", "gpu": "0"},
+            "annotations": {"summary": "GPU 0 at 90"},
+            "startsAt": "2023-11-14T22:14:20.000Z",
+            "endsAt": ends_at,
+        }])
+    }
+
+    #[tokio::test]
+    async fn alertmanager_resends_firing_alerts_before_ends_at() {
+        let (url, mut bodies) = sink().await;
+        let notifier = spawn(WebhookSettings {
+            url,
+            format: WebhookFormat::Alertmanager,
+            resend_interval_secs: 60,
+            timeout_secs: 5,
+            auth: Default::default(),
+        })
+        .unwrap();
+
+        let firing = alert(AlertState::Firing, None);
+        let evaluations = [
+            // Starts firing
+            Evaluation {
+                changed: vec![firing.clone()],
+                firing: vec![firing.clone()],
+                time: at(60),
+            },
+            // Unchanged within the resend interval, nothing is sent
+            Evaluation {
+                changed: Vec::new(),
+                firing: vec![firing.clone()],
+                time: at(90),
+            },
+            // Resent with a later endsAt
+            Evaluation {
+                changed: Vec::new(),
+                firing: vec![firing.clone()],
+                time: at(120),
+            },
+            Evaluation {
+                changed: vec![alert(AlertState::Resolved, Some(at(150)))],
+                firing: Vec::new(),
+                time: at(150),
+            },
+        ];
+        for evaluation in evaluations {
+            notifier.notify(Arc::new(evaluation));
+        }
+
+        let mut received = Vec::new();
+        for _ in 0..3 {
+            let body = tokio::time::timeout(Duration::from_secs(10), bodies.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            received.push(body);
+        }
+        assert_eq!(
+            received,
+            vec![
+                // endsAt is four resend intervals after the evaluation
+                postable("2023-11-14T22:18:20.000Z"),
+                postable("2023-11-14T22:19:20.000Z"),
+                postable("2023-11-14T22:15:50.000Z"),
+            ]
+        );
+        assert!(
+            tokio::time::timeout(Duration::from_millis(200), bodies.recv())
+                .await
+                .is_err()
+        );
+    }
+}
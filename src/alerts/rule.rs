@@ -0,0 +1,295 @@
+use std::time::Duration;
+
+use crate::config::{parse_duration, AlertRuleSettings};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Eq,
+    Ne,
+}
+
+impl Op {
+    pub fn apply(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Op::Gt => value > threshold,
+            Op::Ge => value >= threshold,
+            Op::Lt => value < threshold,
+            Op::Le => value <= threshold,
+            Op::Eq => value == threshold,
+            Op::Ne => value != threshold,
+        }
+    }
+}
+
+/// `name="value"` or `name!="value"` label selector
+#[derive(Debug, Clone)]
+pub struct Matcher {
+    pub name: String,
+    pub value: String,
+    pub negate: bool,
+}
+
+impl Matcher {
+    pub fn matches(&self, labels: &[(&str, &str)]) -> bool {
+        let value = labels
+            .iter()
+            .find(|(name, _)| *name == self.name)
+            .map(|(_, value)| *value)
+            .unwrap_or("");
+        (value == self.value) != self.negate
+    }
+}
+
+/// A parsed alert rule
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub metric: String,
+    pub matchers: Vec<Matcher>,
+    pub op: Op,
+    pub threshold: f64,
+    pub for_duration: Duration,
+    pub settings: AlertRuleSettings,
+}
+
+impl Rule {
+    /// Parse `<metric>{<matchers>} <op> <threshold> [for <duration>]`
+    /// A `for` field in the rule settings overrides the one in the expression
+    pub fn parse(settings: &AlertRuleSettings) -> Result<Self, String> {
+        let invalid = |reason: &str| format!("alert rule {}: {}", settings.name, reason);
+        if settings.name.is_empty() {
+            return Err("alert rule without a name".to_string());
+        }
+
+        let expr = settings.expr.trim();
+        let name_end = expr
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+            .unwrap_or(expr.len());
+        let metric = &expr[..name_end];
+        if metric.is_empty() {
+            return Err(invalid("expression must start with a metric name"));
+        }
+        let mut rest = expr[name_end..].trim_start();
+
+        let mut matchers = Vec::new();
+        if let Some(body) = rest.strip_prefix('{') {
+            let end = find_closing_brace(body).ok_or_else(|| invalid("unclosed {"))?;
+            matchers = parse_matchers(&body[..end]).map_err(|e| invalid(&e))?;
+            rest = body[end + 1..].trim_start();
+        }
+
+        // Only looked for after the matchers, whose values may contain " for "
+        let mut for_duration = Duration::ZERO;
+        if let Some(pos) = rest.rfind(" for ") {
+            let duration = rest[pos + 5..].trim();
+            for_duration = parse_duration(duration)
+                .ok_or_else(|| invalid(&format!("invalid for duration {}", duration)))?;
+            rest = rest[..pos].trim_end();
+        }
+        if let Some(duration) = &settings.for_duration {
+            for_duration = parse_duration(duration)
+                .ok_or_else(|| invalid(&format!("invalid for duration {}", duration)))?;
+        }
+
+        const OPS: [(&str, Op); 6] = [
+            (">=", Op::Ge),
+            ("<=", Op::Le),
+            ("==", Op::Eq),
+            ("!=", Op::Ne),
+            (">", Op::Gt),
+            ("<", Op::Lt),
+        ];
+        let (op_str, op) = OPS
+            .iter()
+            .find(|(s, _)| rest.starts_with(s))
+            .ok_or_else(|| invalid("expected a comparison operator"))?;
+        let threshold_str = rest[op_str.len()..].trim();
+        let threshold = threshold_str
+            .parse::<f64>()
+            .map_err(|_| invalid(&format!("invalid threshold {}", threshold_str)))?;
+
+        Ok(Self {
+            name: settings.name.clone(),
+            metric: metric.to_string(),
+            matchers,
+            op: *op,
+            threshold,
+            for_duration,
+            settings: settings.clone(),
+        })
+    }
+
+    pub fn matches(&self, labels: &[(&str, &str)], value: f64) -> bool {
+        self.matchers.iter().all(|m| m.matches(labels)) && self.op.apply(value, self.threshold)
+    }
+}
+
+/// Index of the `}` closing a matcher list, skipping quoted values
+fn find_closing_brace(s: &str) -> Option<usize> {
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' => in_quotes = !in_quotes,
+            '}' if !in_quotes => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_matchers(s: &str) -> Result<Vec<Matcher>, String> {
+    let mut matchers = Vec::new();
+    let mut rest = s.trim();
+
+    while !rest.is_empty() {
+        let name_end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .ok_or_else(|| format!("invalid matcher {}", rest))?;
+        let name = &rest[..name_end];
+        if name.is_empty() {
+            return Err(format!("invalid matcher {}", rest));
+        }
+        let after_name = rest[name_end..].trim_start();
+        let (negate, after_op) = if let Some(r) = after_name.strip_prefix("!=") {
+            (true, r)
+        } else if let Some(r) = after_name.strip_prefix('=') {
+            (false, r)
+        } else {
+            return Err(format!("invalid matcher {}", rest));
+        };
+
+        let quoted = after_op
+            .trim_start()
+            .strip_prefix('"')
+            .ok_or_else(|| format!("matcher value for {} must be quoted", name))?;
+        let mut value = String::new();
+        let mut escaped = false;
+        let mut end = None;
+        for (i, c) in quoted.char_indices() {
+            match c {
+                _ if escaped => {
+                    value.push(c);
+                    escaped = false;
+                }
+                '\\' => escaped = true,
+                '"' => {
+                    end = Some(i);
+                    break;
+                }
+                _ => value.push(c),
+            }
+        }
+        let end = end.ok_or_else(|| format!("unterminated value for {}", name))?;
+        matchers.push(Matcher {
+            name: name.to_string(),
+            value,
+            negate,
+        });
+
+        rest = quoted[end + 1..].trim_start();
+        rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+    }
+
+    Ok(matchers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn rule(expr: &str, for_duration: Option<&str>) -> Result<Rule, String> {
+        Rule::parse(&AlertRuleSettings {
+            name: "Test".to_string(),
+            expr: expr.to_string(),
+            for_duration: for_duration.map(str::to_string),
+            labels: BTreeMap::new(),
+            annotations: BTreeMap::new(),
+        })
+    }
+
+    #[test]
+    fn parses_metric_op_threshold_and_for() {
+        let rule = rule("hw_gpu_temperature_celsius > 85 for 2m", None).unwrap();
+        assert_eq!(rule.metric, "hw_gpu_temperature_celsius");
+        assert_eq!(rule.op, Op::Gt);
+        assert_eq!(rule.threshold, 85.0);
+        assert_eq!(rule.for_duration, Duration::from_secs(120));
+        assert!(rule.matchers.is_empty());
+
+        let rule = self::rule("hw_gpu_count<=0", None).unwrap();
+        assert_eq!(rule.op, Op::Le);
+        assert_eq!(rule.threshold, 0.0);
+        assert_eq!(rule.for_duration, Duration::ZERO);
+    }
+
+    #[test]
+    fn for_setting_overrides_the_expression() {
+        let rule = rule("hw_gpu_count < 8 for 10m", Some("30s")).unwrap();
+        assert_eq!(rule.threshold, 8.0);
+        assert_eq!(rule.for_duration, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn for_inside_matcher_values_is_not_a_duration() {
+        let rule = rule(
+            r#"hw_inventory_mismatch{expected="ready for use"} == 1"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(rule.matchers[0].value, "ready for use");
+        assert_eq!(rule.op, Op::Eq);
+        assert_eq!(rule.threshold, 1.0);
+        assert_eq!(rule.for_duration, Duration::ZERO);
+
+        let rule = self::rule(
+            r#"hw_inventory_mismatch{expected="ready for use", actual!="x for 5m"} == 1 for 5m"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(rule.matchers[0].value, "ready for use");
+        assert_eq!(rule.matchers[1].value, "x for 5m");
+        assert!(rule.matchers[1].negate);
+        assert_eq!(rule.for_duration, Duration::from_secs(300));
+    }
+
+    #[test]
+    fn parses_matchers_with_escapes() {
+        let rule = rule(r#"hw_gpu_info{name="A\"B}", gpu != "1",} >= 1"#, None).unwrap();
+        assert_eq!(rule.matchers.len(), 2);
+        assert_eq!(rule.matchers[0].name, "name");
+        assert_eq!(rule.matchers[0].value, r#"A"B}"#);
+        assert!(!rule.matchers[0].negate);
+        assert_eq!(rule.matchers[1].name, "gpu");
+        assert!(rule.matchers[1].negate);
+
+        assert!(rule.matches(&[("name", r#"A"B}"#), ("gpu", "0")], 1.0));
+        assert!(!rule.matches(&[("name", r#"A"B}"#), ("gpu", "1")], 1.0));
+        assert!(!rule.matches(&[("name", r#"A"B}"#), ("gpu", "0")], 0.5));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        for expr in [
+            "",
+            "> 5",
+            "hw_gpu_count",
+            "hw_gpu_count >",
+            "hw_gpu_count > many",
+            "hw_gpu_count > 1 for ever",
+            r#"hw_gpu_info{name="x" > 1"#,
+            r#"hw_gpu_info{name=x} > 1"#,
+            r#"hw_gpu_info{name="x} > 1"#,
+        ] {
+            assert!(rule(expr, None).is_err(), "{expr}");
+        }
+        assert!(rule("hw_gpu_count > 1", Some("later")).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::header::AUTHORIZATION;
+use axum::middleware::Next;
+use axum::response::Response;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use lazy_static::lazy_static;
+use ring::digest::{digest, SHA256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+use super::AppState;
+use crate::config::AuthSettings;
+use crate::error::AppError;
+
+/// How long a verified basic auth user and password skip bcrypt
+const VERIFIED_TTL: Duration = Duration::from_secs(60);
+
+lazy_static! {
+    /// Recently verified `(user, SHA-256 of password)` pairs, bcrypt would
+    /// otherwise run on every scrape
+    static ref VERIFIED: Mutex<HashMap<(String, Vec<u8>), Instant>> = Mutex::new(HashMap::new());
+}
+
+/// Hash verified for unknown users, so they take as long as known ones
+static DUMMY_HASH: OnceLock<String> = OnceLock::new();
+
+/// Middleware enforcing the `[auth]` settings on every non-public route
+/// The client address must be in `allowed_cidrs` (when set) and, if any
+/// credentials are configured, the request must carry a valid bearer token
+/// or basic auth user
+pub async fn require_auth(
+    State(state): State<AppState>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let auth = &state.settings.auth;
+    let path = request.uri().path();
+
+    if !auth.is_enabled() || auth.public_routes.iter().any(|route| route == path) {
+        return Ok(next.run(request).await);
+    }
+
+    if !auth.allowed_cidrs.is_empty()
+        && !auth
+            .allowed_cidrs
+            .iter()
+            .any(|net| net.contains(&peer.ip()))
+    {
+        warn!(
+            "Rejected request to {} from {}: address not allowed",
+            path, peer
+        );
+        return Err(AppError::Forbidden(
+            "client address not allowed".to_string(),
+        ));
+    }
+
+    if auth.requires_credentials() {
+        let header = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+
+        let authorized = match header {
+            Some(value) => check_authorization(auth, &value).await,
+            None => false,
+        };
+
+        if !authorized {
+            warn!(
+                "Rejected request to {} from {}: invalid credentials",
+                path, peer
+            );
+            return Err(AppError::Unauthorized(
+                "invalid or missing credentials".to_string(),
+            ));
+        }
+    }
+
+    Ok(next.run(request).await)
+}
+
+/// Validate an `Authorization` header value against the configured credentials
+/// The scheme is case-insensitive (RFC 7235)
+async fn check_authorization(auth: &AuthSettings, header: &str) -> bool {
+    let Some((scheme, credentials)) = header.trim().split_once(' ') else {
+        return false;
+    };
+    let credentials = credentials.trim();
+
+    if scheme.eq_ignore_ascii_case("Bearer") {
+        return auth
+            .bearer_tokens
+            .iter()
+            .any(|expected| constant_time_eq(expected.as_bytes(), credentials.as_bytes()));
+    }
+
+    if scheme.eq_ignore_ascii_case("Basic") {
+        let Some((username, password)) = decode_basic_credentials(credentials) else {
+            return false;
+        };
+        return check_basic(auth, username, password).await;
+    }
+
+    false
+}
+
+async fn check_basic(auth: &AuthSettings, username: String, password: String) -> bool {
+    let key = (
+        username,
+        digest(&SHA256, password.as_bytes()).as_ref().to_vec(),
+    );
+    if let Some(verified) = VERIFIED.lock().unwrap().get(&key) {
+        if verified.elapsed() < VERIFIED_TTL {
+            return true;
+        }
+    }
+
+    let hash = auth.basic_auth_users.get(&key.0).cloned();
+    let known = hash.is_some();
+    let cost = dummy_cost(auth);
+
+    // bcrypt is deliberately slow, keep it off the async workers
+    let valid = tokio::task::spawn_blocking(move || {
+        let hash = hash.unwrap_or_else(|| dummy_hash(cost).to_string());
+        bcrypt::verify(password, &hash).unwrap_or(false)
+    })
+    .await
+    .unwrap_or(false);
+    if !(known && valid) {
+        return false;
+    }
+
+    let mut verified = VERIFIED.lock().unwrap();
+    verified.retain(|_, at| at.elapsed() < VERIFIED_TTL);
+    verified.insert(key, Instant::now());
+    true
+}
+
+/// Cost of the configured hashes, `$2y$<cost>$...`
+fn dummy_cost(auth: &AuthSettings) -> u32 {
+    auth.basic_auth_users
+        .values()
+        .find_map(|hash| hash.split('$').nth(2)?.parse().ok())
+        .unwrap_or(bcrypt::DEFAULT_COST)
+}
+
+/// A hash with the cost of the configured ones, which never matches
+/// Hashing is slow, call it from a blocking thread
+fn dummy_hash(cost: u32) -> &'static str {
+    DUMMY_HASH.get_or_init(|| {
+        bcrypt::hash(digest(&SHA256, b"unknown user").as_ref(), cost)
+            .expect("bcrypt cost of a configured hash is valid")
+    })
+}
+
+/// Decode `base64(username:password)` from a basic auth header
+fn decode_basic_credentials(encoded: &str) -> Option<(String, String)> {
+    let decoded = STANDARD.decode(encoded).ok()?;
+    let decoded = String::from_utf8(decoded).ok()?;
+    let (username, password) = decoded.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Compare two byte strings without short-circuiting on the first mismatch
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::serve_local;
+    use crate::config::Settings;
+    use reqwest::StatusCode;
+
+    fn basic(username: &str, password: &str) -> String {
+        format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", username, password))
+        )
+    }
+
+    /// Serve the API with `auth`, returning its base URL
+    async fn server(auth: AuthSettings) -> String {
+        let settings = Settings {
+            auth,
+            ..Default::default()
+        };
+        let addr = serve_local(AppState::from_settings(settings)).await;
+        format!("http://{}", addr)
+    }
+
+    async fn status(url: &str, authorization: Option<&str>) -> StatusCode {
+        let mut request = reqwest::Client::new().get(url);
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        request.send().await.unwrap().status()
+    }
+
+    fn credentials(users: &[(&str, &str)]) -> AuthSettings {
+        AuthSettings {
+            bearer_tokens: vec!["token-1".to_string()],
+            basic_auth_users: users
+                .iter()
+                .map(|(user, password)| (user.to_string(), bcrypt::hash(password, 4).unwrap()))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn bearer_tokens() {
+        let base = server(credentials(&[])).await;
+        let url = format!("{}/api/v1/alerts", base);
+
+        assert_eq!(status(&url, Some("Bearer token-1")).await, StatusCode::OK);
+        assert_eq!(status(&url, Some("bearer token-1")).await, StatusCode::OK);
+        assert_eq!(status(&url, Some("BEARER  token-1 ")).await, StatusCode::OK);
+        assert_eq!(
+            status(&url, Some("Bearer token-2")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&url, Some("Token token-1")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(status(&url, None).await, StatusCode::UNAUTHORIZED);
+        // Public routes skip authentication
+        assert_eq!(
+            status(&format!("{}/healthz", base), None).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn basic_auth_users() {
+        let base = server(credentials(&[("alice", "s3cret")])).await;
+        let url = format!("{}/api/v1/alerts", base);
+
+        assert_eq!(
+            status(&url, Some(&basic("alice", "s3cret"))).await,
+            StatusCode::OK
+        );
+        let lowercase = basic("alice", "s3cret").replacen("Basic", "basic", 1);
+        assert_eq!(status(&url, Some(&lowercase)).await, StatusCode::OK);
+        assert_eq!(
+            status(&url, Some(&basic("alice", "wrong"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&url, Some(&basic("mallory", "s3cret"))).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            status(&url, Some("Basic not-base64")).await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn allowed_cidrs() {
+        let denied = server(AuthSettings {
+            allowed_cidrs: vec!["10.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            status(&format!("{}/api/v1/alerts", denied), None).await,
+            StatusCode::FORBIDDEN
+        );
+
+        let allowed = server(AuthSettings {
+            allowed_cidrs: vec!["127.0.0.0/8".parse().unwrap()],
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(
+            status(&format!("{}/api/v1/alerts", allowed), None).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn verified_credentials_expire() {
+        let auth = credentials(&[("cache-user", "current")]);
+        let key = |password: &str| {
+            (
+                "cache-user".to_string(),
+                digest(&SHA256, password.as_bytes()).as_ref().to_vec(),
+            )
+        };
+
+        // A cached password is accepted without bcrypt until the TTL passes
+        VERIFIED
+            .lock()
+            .unwrap()
+            .insert(key("previous"), Instant::now());
+        assert!(check_basic(&auth, "cache-user".to_string(), "previous".to_string()).await);
+
+        let Some(expired) = Instant::now().checked_sub(VERIFIED_TTL + Duration::from_secs(1))
+        else {
+            return;
+        };
+        VERIFIED.lock().unwrap().insert(key("previous"), expired);
+        assert!(!check_basic(&auth, "cache-user".to_string(), "previous".to_string()).await);
+
+        assert!(check_basic(&auth, "cache-user".to_string(), "current".to_string()).await);
+        let verified = VERIFIED.lock().unwrap()[&key("current")];
+        assert!(verified.elapsed() < VERIFIED_TTL);
+    }
+}
@@ -1,26 +1,405 @@
-use axum::extract::State;
+use axum::extract::{Query, State};
 use axum::http::header::CONTENT_TYPE;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use super::AppState;
-use crate::metrics::{self, NodeMetrics, SystemMetrics};
+use crate::alerts::Alert;
+use crate::config::parse_duration;
+use crate::error::AppError;
+use crate::health::Condition;
+use crate::history::Series;
+use crate::inventory::{ChangeEvent, Inventory, InventoryReport};
+use crate::metrics::exposition::{encode_text, MetricFamily};
+use crate::metrics::filter::ScrapeFilter;
+use crate::metrics::{graphite, influx, NodeMetrics, SystemMetrics};
 
-pub async fn get_metrics() -> Json<SystemMetrics> {
-    Json(metrics::collect())
+pub async fn get_metrics(State(state): State<AppState>) -> Result<Json<SystemMetrics>, AppError> {
+    Ok(Json(
+        NodeMetrics::collect_shared()
+            .await?
+            .with_labels(&state.settings.labels)
+            .into(),
+    ))
 }
 
-pub async fn get_node_metrics() -> Json<NodeMetrics> {
-    Json(NodeMetrics::collect())
+pub async fn get_node_metrics(
+    State(state): State<AppState>,
+) -> Result<Json<NodeMetrics>, AppError> {
+    Ok(Json(
+        NodeMetrics::collect_shared()
+            .await?
+            .with_labels(&state.settings.labels)
+            .with_inventory_checks(&state.inventory)
+            .with_inventory_changes(&state.changes),
+    ))
 }
 
-pub async fn get_prometheus_metrics(State(state): State<AppState>) -> Response {
-    let metrics = NodeMetrics::collect();
-    let body = metrics.to_prometheus(&state.settings.metrics.enabled);
+/// Prometheus endpoint, accepting node_exporter style `name[]` and `collect[]`
+/// parameters to select a subset of the configured metrics
+pub async fn get_prometheus_metrics(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Response, AppError> {
+    let (metrics, families) = scrape(&state, &params).await?;
+    let body = encode_text(&families, &metrics.labels);
 
-    (
+    Ok((
         [(CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
         body,
     )
-        .into_response()
+        .into_response())
+}
+
+/// InfluxDB line protocol endpoint, accepting the same filters as `/metrics`
+pub async fn get_influx_metrics(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Response, AppError> {
+    let (metrics, families) = scrape(&state, &params).await?;
+    let body = influx::encode(&families, &metrics.labels, now_ms());
+
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+/// Graphite plaintext endpoint, accepting the same filters as `/metrics`
+pub async fn get_graphite_metrics(
+    State(state): State<AppState>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Response, AppError> {
+    let (metrics, families) = scrape(&state, &params).await?;
+    let body = graphite::encode(
+        &families,
+        &metrics.labels,
+        &state.settings.graphite.prefix,
+        now_ms(),
+    );
+
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], body).into_response())
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    metric: Option<String>,
+    /// Look-back window such as `10m`, defaults to the whole retention
+    since: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct HistoryResponse {
+    metric: String,
+    /// Start of the window in milliseconds since the epoch
+    since: i64,
+    series: Vec<Series>,
+}
+
+/// Recorded values of one metric from the in-memory history
+pub async fn get_history(
+    State(state): State<AppState>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryResponse>, AppError> {
+    if !state.history.is_enabled() {
+        return Err(AppError::NotFound("history is disabled".to_string()));
+    }
+
+    let metric = query
+        .metric
+        .filter(|metric| !metric.is_empty())
+        .ok_or_else(|| AppError::BadRequest("missing metric parameter".to_string()))?;
+    let window = match &query.since {
+        Some(since) => parse_duration(since)
+            .ok_or_else(|| AppError::BadRequest(format!("invalid since: {}", since)))?,
+        None => state.history.retention(),
+    };
+    let since = now_ms() - window.as_millis() as i64;
+
+    let series = state.history.query(&metric, since);
+    Ok(Json(HistoryResponse {
+        metric,
+        since,
+        series,
+    }))
+}
+
+#[derive(Serialize)]
+pub struct AlertsResponse {
+    alerts: Vec<Alert>,
+}
+
+/// Pending and firing alerts of the local alert rules
+pub async fn get_alerts(State(state): State<AppState>) -> Json<AlertsResponse> {
+    Json(AlertsResponse {
+        alerts: state.alerts.active(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct HealthConditionsResponse {
+    conditions: Vec<Condition>,
+}
+
+/// Health conditions from the latest background collection
+pub async fn get_health(State(state): State<AppState>) -> Json<HealthConditionsResponse> {
+    Json(HealthConditionsResponse {
+        conditions: state.health.conditions(),
+    })
+}
+
+/// DMI, CPU, memory module, PCI, NIC and disk inventory of the node
+pub async fn get_inventory() -> Result<Json<Inventory>, AppError> {
+    tokio::task::spawn_blocking(Inventory::collect)
+        .await
+        .map(Json)
+        .map_err(|e| AppError::Internal(e.to_string()))
+}
+
+/// Differences between the collected and the expected hardware
+pub async fn get_inventory_check(
+    State(state): State<AppState>,
+) -> Result<Json<InventoryReport>, AppError> {
+    if !state.inventory.is_enabled() {
+        return Err(AppError::NotFound(
+            "no expected hardware configured".to_string(),
+        ));
+    }
+
+    let metrics = NodeMetrics::collect_shared().await?;
+    Ok(Json(state.inventory.check(&metrics)))
+}
+
+#[derive(Serialize)]
+pub struct EventsResponse {
+    events: Vec<ChangeEvent>,
+}
+
+/// Hardware changes detected by the background collection, oldest first
+pub async fn get_events(State(state): State<AppState>) -> Json<EventsResponse> {
+    Json(EventsResponse {
+        events: state.changes.events(),
+    })
+}
+
+/// Collect metrics and select the families requested by the scrape
+async fn scrape(
+    state: &AppState,
+    params: &[(String, String)],
+) -> Result<(NodeMetrics, Vec<MetricFamily>), AppError> {
+    let filter = ScrapeFilter::from_query(params).map_err(AppError::BadRequest)?;
+
+    let metrics = NodeMetrics::collect_shared()
+        .await?
+        .with_labels(&state.settings.labels)
+        .with_inventory_checks(&state.inventory)
+        .with_inventory_changes(&state.changes);
+    let families = metrics
+        .selected_families(&state.selector)
+        .into_iter()
+        .filter(|family| filter.matches(family))
+        .collect();
+
+    Ok((metrics, families))
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::api::{serve_local, AppState};
+    use crate::collector::Snapshot;
+    use crate::config::Settings;
+    use crate::metrics::exposition::MetricFamily;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tokio::sync::broadcast;
+
+    async fn scrape(settings: Settings, query: &[(&str, &str)]) -> (u16, String) {
+        let addr = serve_local(AppState::from_settings(settings)).await;
+        let response = reqwest::Client::new()
+            .get(format!("http://{addr}/metrics"))
+            .query(query)
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.text().await.unwrap())
+    }
+
+    async fn families(settings: Settings, query: &[(&str, &str)]) -> Vec<String> {
+        let (status, body) = scrape(settings, query).await;
+        assert_eq!(status, 200, "{body}");
+        body.lines()
+            .filter_map(|line| line.strip_prefix("# TYPE "))
+            .filter_map(|line| line.split(' ').next())
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn returns_every_family_without_a_filter() {
+        let names = families(Settings::default(), &[]).await;
+        for name in [
+            "hw_node_uptime_seconds",
+            "hw_cpu_usage_percent",
+            "hw_memory_total_bytes",
+        ] {
+            assert!(
+                names.iter().any(|n| n == name),
+                "{name} missing from {names:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn collect_keeps_the_named_collectors() {
+        let names = families(Settings::default(), &[("collect[]", "memory")]).await;
+        assert!(!names.is_empty());
+        assert!(
+            names.iter().all(|n| n.starts_with("hw_memory_")),
+            "{names:?}"
+        );
+
+        let names = families(
+            Settings::default(),
+            &[("collect[]", "cpu"), ("collect", "memory")],
+        )
+        .await;
+        assert!(names.iter().any(|n| n.starts_with("hw_cpu_")));
+        assert!(names.iter().any(|n| n.starts_with("hw_memory_")));
+        assert!(
+            !names.iter().any(|n| n.starts_with("hw_node_")),
+            "{names:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn name_keeps_matching_families() {
+        let names = families(
+            Settings::default(),
+            &[
+                ("name[]", "hw_cpu_usage_percent"),
+                ("name", "hw_memory_*_bytes"),
+            ],
+        )
+        .await;
+        assert_eq!(
+            names,
+            [
+                "hw_cpu_usage_percent",
+                "hw_memory_total_bytes",
+                "hw_memory_used_bytes",
+                "hw_memory_available_bytes"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn name_and_collect_must_both_match() {
+        let names = families(
+            Settings::default(),
+            &[("collect[]", "cpu"), ("name[]", "hw_*_usage_percent")],
+        )
+        .await;
+        assert_eq!(names, ["hw_cpu_usage_percent"]);
+    }
+
+    #[tokio::test]
+    async fn filter_cannot_bring_back_excluded_families() {
+        let mut settings = Settings::default();
+        settings.metrics.exclude = vec!["hw_cpu_usage_percent".to_string()];
+        let names = families(settings, &[("name[]", "hw_cpu_*")]).await;
+        assert!(!names.is_empty());
+        assert!(
+            !names.iter().any(|n| n == "hw_cpu_usage_percent"),
+            "{names:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_collector_is_a_bad_request() {
+        let (status, body) = scrape(Settings::default(), &[("collect[]", "disk")]).await;
+        assert_eq!(status, 400);
+        assert!(body.contains("disk"), "{body}");
+    }
+
+    async fn history(query: &str) -> (u16, serde_json::Value) {
+        let mut settings = Settings::default();
+        settings.history.retention_secs = 300;
+        let state = AppState::from_settings(settings);
+
+        let (snapshots, receiver) = broadcast::channel(4);
+        state.history.spawn(receiver);
+        for (age_secs, value) in [(120, 40.0), (30, 60.0)] {
+            let family = MetricFamily::gauge("hw_gpu_utilization_percent", "Utilization", "gpu")
+                .with_sample(vec![("gpu".to_string(), "0".to_string())], value);
+            let mut snapshot = Snapshot::with_families(vec![family], &[("cluster", "c1")]);
+            snapshot.timestamp = SystemTime::now() - Duration::from_secs(age_secs);
+            snapshots.send(Arc::new(snapshot)).unwrap();
+        }
+        let recorded = |state: &AppState| {
+            let series = state.history.query("hw_gpu_utilization_percent", 0);
+            series.iter().map(|s| s.points.len()).sum::<usize>()
+        };
+        while recorded(&state) < 2 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let addr = serve_local(state).await;
+        let response = reqwest::get(format!("http://{addr}/api/v1/history?{query}"))
+            .await
+            .unwrap();
+        (
+            response.status().as_u16(),
+            response.json().await.unwrap_or_default(),
+        )
+    }
+
+    fn values(body: &serde_json::Value) -> Vec<f64> {
+        body["series"][0]["points"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|point| point[1].as_f64().unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn history_returns_points_within_since() {
+        let (status, body) = history("metric=hw_gpu_utilization_percent").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["metric"], "hw_gpu_utilization_percent");
+        assert_eq!(body["series"].as_array().unwrap().len(), 1);
+        assert_eq!(body["series"][0]["labels"]["cluster"], "c1");
+        assert_eq!(body["series"][0]["labels"]["gpu"], "0");
+        assert_eq!(values(&body), [40.0, 60.0]);
+
+        let (status, body) = history("metric=hw_gpu_utilization_percent&since=1m").await;
+        assert_eq!(status, 200);
+        assert_eq!(values(&body), [60.0]);
+
+        let (status, body) = history("metric=hw_gpu_count").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["series"], serde_json::json!([]));
+    }
+
+    #[tokio::test]
+    async fn history_rejects_bad_queries() {
+        assert_eq!(history("").await.0, 400);
+        assert_eq!(history("metric=").await.0, 400);
+        assert_eq!(history("metric=hw_gpu_count&since=soon").await.0, 400);
+    }
+
+    #[tokio::test]
+    async fn history_is_not_found_when_disabled() {
+        let addr = serve_local(AppState::from_settings(Settings::default())).await;
+        let response = reqwest::get(format!("http://{addr}/api/v1/history?metric=hw_gpu_count"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 404);
+    }
 }
@@ -1,12 +1,19 @@
-use axum::{routing::get, Json, Router};
+use axum::{middleware, routing::get, Json, Router};
 use serde::Serialize;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::info;
 
+use crate::alerts::Evaluator;
 use crate::config::Settings;
+use crate::health::HealthMonitor;
+use crate::history::History;
+use crate::inventory::{ChangeDetector, InventoryChecker};
+use crate::metrics::filter::MetricSelector;
 
+mod auth;
 mod handlers;
+mod tls;
 
 #[derive(Serialize)]
 struct HealthResponse {
@@ -18,33 +25,95 @@ struct HealthResponse {
 #[derive(Clone)]
 pub struct AppState {
     pub settings: Arc<Settings>,
+    pub selector: Arc<MetricSelector>,
+    pub history: Arc<History>,
+    pub alerts: Arc<Evaluator>,
+    pub health: Arc<HealthMonitor>,
+    pub inventory: Arc<InventoryChecker>,
+    pub changes: Arc<ChangeDetector>,
 }
 
-pub async fn serve(settings: Settings) -> anyhow::Result<()> {
+pub async fn serve(
+    settings: Settings,
+    selector: Arc<MetricSelector>,
+    history: Arc<History>,
+    alerts: Arc<Evaluator>,
+    health_monitor: Arc<HealthMonitor>,
+    inventory: Arc<InventoryChecker>,
+    changes: Arc<ChangeDetector>,
+) -> anyhow::Result<()> {
     let state = AppState {
         settings: Arc::new(settings.clone()),
+        selector,
+        history,
+        alerts,
+        health: health_monitor,
+        inventory,
+        changes,
     };
+    let app = router(state);
 
-    let app = Router::new()
+    let addr = SocketAddr::new(settings.server.host.parse()?, settings.server.port);
+
+    match &settings.server.tls {
+        Some(tls_settings) => {
+            let tls_config = tls::load(tls_settings)?;
+            tokio::spawn(tls::watch(tls_settings.clone(), tls_config.clone()));
+
+            info!(
+                "Server listening on {} (TLS{})",
+                addr,
+                if tls_settings.client_ca_path.is_some() {
+                    ", client certificates required"
+                } else {
+                    ""
+                }
+            );
+
+            axum_server::bind_rustls(addr, tls_config)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await?;
+        }
+        None => {
+            info!("Server listening on {}", addr);
+
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// API routes behind the auth middleware
+fn router(state: AppState) -> Router {
+    Router::new()
         .route("/health", get(health))
         .route("/healthz", get(health))
         .route("/ready", get(health))
         .route("/metrics", get(handlers::get_prometheus_metrics))
         .route("/metrics/json", get(handlers::get_metrics))
+        .route("/metrics/influx", get(handlers::get_influx_metrics))
+        .route("/metrics/graphite", get(handlers::get_graphite_metrics))
         .route("/node", get(handlers::get_node_metrics))
-        .with_state(state);
-
-    let addr = SocketAddr::new(
-        settings.server.host.parse()?,
-        settings.server.port,
-    );
-
-    info!("Server listening on {}", addr);
-
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
-
-    Ok(())
+        .route("/api/v1/history", get(handlers::get_history))
+        .route("/api/v1/alerts", get(handlers::get_alerts))
+        .route("/api/v1/health", get(handlers::get_health))
+        .route("/api/v1/inventory", get(handlers::get_inventory))
+        .route("/api/v1/events", get(handlers::get_events))
+        .route(
+            "/api/v1/inventory/check",
+            get(handlers::get_inventory_check),
+        )
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_auth,
+        ))
+        .with_state(state)
 }
 
 async fn health() -> Json<HealthResponse> {
@@ -53,3 +122,39 @@ async fn health() -> Json<HealthResponse> {
         version: env!("CARGO_PKG_VERSION").to_string(),
     })
 }
+
+#[cfg(test)]
+impl AppState {
+    /// State built from `settings` as main does, without background tasks
+    pub fn from_settings(settings: Settings) -> Self {
+        Self {
+            selector: Arc::new(MetricSelector::from_settings(&settings.metrics).unwrap()),
+            history: Arc::new(History::new(&settings)),
+            alerts: Arc::new(Evaluator::from_settings(&settings.alerts).unwrap()),
+            health: Arc::new(HealthMonitor::new(
+                settings.health.clone(),
+                settings.kubernetes.clone(),
+            )),
+            inventory: Arc::new(
+                InventoryChecker::from_settings(&settings.inventory, &settings.kubernetes).unwrap(),
+            ),
+            changes: Arc::new(ChangeDetector::new(settings.state_dir.as_deref())),
+            settings: Arc::new(settings),
+        }
+    }
+}
+
+/// Serve the API on a local port
+#[cfg(test)]
+pub async fn serve_local(state: AppState) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(
+            listener,
+            router(state).into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .await
+    });
+    addr
+}
@@ -0,0 +1,255 @@
+use anyhow::{anyhow, Context};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{info, warn};
+
+use crate::config::TlsSettings;
+
+/// Build the initial rustls configuration for the server
+pub fn load(settings: &TlsSettings) -> anyhow::Result<RustlsConfig> {
+    let config = build_server_config(settings)?;
+    Ok(RustlsConfig::from_config(Arc::new(config)))
+}
+
+/// Build a rustls `ServerConfig` from the configured PEM files
+/// Client certificates are required when `client_ca_path` is set
+pub fn build_server_config(settings: &TlsSettings) -> anyhow::Result<ServerConfig> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let certs = load_certs(&settings.cert_path)?;
+    let key = load_private_key(&settings.key_path)?;
+
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?;
+
+    let builder = match &settings.client_ca_path {
+        Some(ca_path) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_path)? {
+                roots
+                    .add(cert)
+                    .with_context(|| format!("invalid CA certificate in {}", ca_path))?;
+            }
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider)
+                .build()
+                .context("failed to build client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        }
+        None => builder.with_no_client_auth(),
+    };
+
+    let mut config = builder
+        .with_single_cert(certs, key)
+        .context("invalid server certificate or key")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(config)
+}
+
+fn load_certs(path: &str) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to parse certificates in {}", path))?;
+
+    if certs.is_empty() {
+        return Err(anyhow!("no certificates found in {}", path));
+    }
+    Ok(certs)
+}
+
+fn load_private_key(path: &str) -> anyhow::Result<PrivateKeyDer<'static>> {
+    let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .with_context(|| format!("failed to parse private key in {}", path))?
+        .ok_or_else(|| anyhow!("no private key found in {}", path))
+}
+
+/// Modification times of all configured TLS files
+/// Secret volume updates swap symlinks, so metadata follows links to the new target
+fn file_mtimes(settings: &TlsSettings) -> Vec<Option<SystemTime>> {
+    std::iter::once(&settings.cert_path)
+        .chain(std::iter::once(&settings.key_path))
+        .chain(settings.client_ca_path.iter())
+        .map(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .collect()
+}
+
+/// Periodically check the certificate files and reload them when they change
+/// A failed reload keeps serving with the previous certificates
+pub async fn watch(settings: TlsSettings, config: RustlsConfig) {
+    let interval = Duration::from_secs(settings.reload_interval_secs.max(1));
+    let mut last_mtimes = file_mtimes(&settings);
+
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let mtimes = file_mtimes(&settings);
+        if mtimes == last_mtimes {
+            continue;
+        }
+
+        match build_server_config(&settings) {
+            Ok(server_config) => {
+                config.reload_from_config(Arc::new(server_config));
+                last_mtimes = mtimes;
+                info!("Reloaded TLS certificate from {}", settings.cert_path);
+            }
+            Err(e) => {
+                // Files may be mid-rotation, retry on the next tick
+                warn!("Failed to reload TLS certificate: {:#}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use axum::Router;
+    use rcgen::{
+        BasicConstraints, Certificate, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair,
+    };
+    use std::net::SocketAddr;
+    use std::path::Path;
+
+    struct Ca {
+        pem: String,
+        cert: Certificate,
+        key: KeyPair,
+    }
+
+    fn ca() -> Ca {
+        let mut params = CertificateParams::new(Vec::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        let key = KeyPair::generate().unwrap();
+        let cert = params.self_signed(&key).unwrap();
+        Ca {
+            pem: cert.pem(),
+            cert,
+            key,
+        }
+    }
+
+    /// Certificate and key PEM for `localhost` signed by `ca`
+    fn leaf(ca: &Ca, usage: ExtendedKeyUsagePurpose) -> (String, String) {
+        let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+        params.extended_key_usages = vec![usage];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &ca.cert, &ca.key).unwrap();
+        (cert.pem(), key.serialize_pem())
+    }
+
+    fn write_server_files(dir: &Path, ca: &Ca) {
+        let (cert, key) = leaf(ca, ExtendedKeyUsagePurpose::ServerAuth);
+        fs::write(dir.join("tls.crt"), cert).unwrap();
+        fs::write(dir.join("tls.key"), key).unwrap();
+    }
+
+    fn settings(dir: &Path, client_ca: Option<&Ca>) -> TlsSettings {
+        let client_ca_path = client_ca.map(|ca| {
+            let path = dir.join("client-ca.crt");
+            fs::write(&path, &ca.pem).unwrap();
+            path.display().to_string()
+        });
+        TlsSettings {
+            cert_path: dir.join("tls.crt").display().to_string(),
+            key_path: dir.join("tls.key").display().to_string(),
+            client_ca_path,
+            reload_interval_secs: 1,
+        }
+    }
+
+    async fn serve(config: RustlsConfig) -> SocketAddr {
+        let handle = axum_server::Handle::new();
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        let server = axum_server::bind_rustls("127.0.0.1:0".parse().unwrap(), config)
+            .handle(handle.clone())
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        handle.listening().await.unwrap()
+    }
+
+    fn client(addr: SocketAddr, ca: &Ca, identity: Option<(String, String)>) -> reqwest::Client {
+        let mut builder = reqwest::Client::builder()
+            .tls_built_in_root_certs(false)
+            .add_root_certificate(reqwest::Certificate::from_pem(ca.pem.as_bytes()).unwrap())
+            .resolve("localhost", addr);
+        if let Some((cert, key)) = identity {
+            let pem = format!("{}{}", cert, key);
+            builder = builder.identity(reqwest::Identity::from_pem(pem.as_bytes()).unwrap());
+        }
+        builder.build().unwrap()
+    }
+
+    async fn get_ok(client: &reqwest::Client, addr: SocketAddr) -> bool {
+        let url = format!("https://localhost:{}/", addr.port());
+        match client.get(url).send().await {
+            Ok(response) => response.status().is_success(),
+            Err(_) => false,
+        }
+    }
+
+    #[tokio::test]
+    async fn load_serves_generated_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let server_ca = ca();
+        write_server_files(dir.path(), &server_ca);
+
+        let addr = serve(load(&settings(dir.path(), None)).unwrap()).await;
+
+        assert!(get_ok(&client(addr, &server_ca, None), addr).await);
+    }
+
+    #[tokio::test]
+    async fn client_certificate_required_with_client_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let server_ca = ca();
+        let client_ca = ca();
+        write_server_files(dir.path(), &server_ca);
+
+        let addr = serve(load(&settings(dir.path(), Some(&client_ca))).unwrap()).await;
+
+        assert!(!get_ok(&client(addr, &server_ca, None), addr).await);
+        let identity = leaf(&client_ca, ExtendedKeyUsagePurpose::ClientAuth);
+        assert!(get_ok(&client(addr, &server_ca, Some(identity)), addr).await);
+
+        // Signed by a CA the server does not trust
+        let untrusted = leaf(&ca(), ExtendedKeyUsagePurpose::ClientAuth);
+        assert!(!get_ok(&client(addr, &server_ca, Some(untrusted)), addr).await);
+    }
+
+    #[tokio::test]
+    async fn watch_reloads_rewritten_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let old_ca = ca();
+        let new_ca = ca();
+        write_server_files(dir.path(), &old_ca);
+
+        let settings = settings(dir.path(), None);
+        let config = load(&settings).unwrap();
+        tokio::spawn(watch(settings, config.clone()));
+        let addr = serve(config).await;
+
+        let new_client = client(addr, &new_ca, None);
+        assert!(get_ok(&client(addr, &old_ca, None), addr).await);
+        assert!(!get_ok(&new_client, addr).await);
+
+        write_server_files(dir.path(), &new_ca);
+        let mut reloaded = false;
+        for _ in 0..30 {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            if get_ok(&new_client, addr).await {
+                reloaded = true;
+                break;
+            }
+        }
+        assert!(reloaded, "rewritten certificate was not picked up");
+    }
+}
@@ -0,0 +1,79 @@
+use clap::{Args, Parser, Subcommand};
+
+use crate::config::{PushMethod, PushgatewaySettings};
+use crate::health::ConditionType;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Kubernetes node hardware exporter")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Serve the HTTP API and run the configured push sinks (default)
+    Serve,
+    /// Collect once or several times, push to a Prometheus Pushgateway and exit
+    Push(PushArgs),
+    /// Evaluate health conditions once, as a node-problem-detector custom
+    /// plugin: exits 0 when OK, 1 when a problem is found and 2 when unknown
+    Check(CheckArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct CheckArgs {
+    /// Condition to check, every applicable condition when omitted
+    #[arg(long, value_enum)]
+    pub condition: Option<ConditionType>,
+}
+
+/// Flags override the `[pushgateway]` config section
+#[derive(Debug, Args)]
+pub struct PushArgs {
+    /// Pushgateway base URL, e.g. http://pushgateway:9091
+    #[arg(long)]
+    pub url: Option<String>,
+    /// Value of the `job` grouping label
+    #[arg(long)]
+    pub job: Option<String>,
+    /// Extra grouping key label as NAME=VALUE, may be repeated
+    #[arg(long = "label", value_name = "NAME=VALUE", value_parser = parse_label)]
+    pub labels: Vec<(String, String)>,
+    /// Number of collections to push
+    #[arg(long, default_value_t = 1)]
+    pub count: u32,
+    /// Seconds between collections, defaults to `collect_interval_secs`
+    #[arg(long)]
+    pub interval: Option<u64>,
+    #[arg(long, value_enum)]
+    pub method: Option<PushMethod>,
+    /// Delete the group from the Pushgateway before exiting
+    #[arg(long)]
+    pub delete_on_exit: bool,
+}
+
+impl PushArgs {
+    pub fn apply(&self, settings: &mut PushgatewaySettings) {
+        if let Some(url) = &self.url {
+            settings.url = Some(url.clone());
+        }
+        if let Some(job) = &self.job {
+            settings.job = job.clone();
+        }
+        for (name, value) in &self.labels {
+            settings.grouping_labels.insert(name.clone(), value.clone());
+        }
+        if let Some(method) = self.method {
+            settings.method = method;
+        }
+        settings.delete_on_exit |= self.delete_on_exit;
+    }
+}
+
+fn parse_label(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, got {}", s)),
+    }
+}
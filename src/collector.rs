@@ -0,0 +1,135 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+use tokio::time::MissedTickBehavior;
+use tracing::{info, warn};
+
+use crate::config::Settings;
+use crate::inventory::{ChangeDetector, InventoryChecker};
+use crate::metrics::exposition::MetricFamily;
+use crate::metrics::filter::MetricSelector;
+use crate::metrics::NodeMetrics;
+
+/// Number of snapshots a slow subscriber may fall behind before skipping ahead
+const SNAPSHOT_CHANNEL_CAPACITY: usize = 16;
+
+/// Result of one background collection, shared by all push sinks
+#[derive(Debug)]
+pub struct Snapshot {
+    pub metrics: NodeMetrics,
+    /// Families allowed by the configured metric selector
+    pub families: Vec<MetricFamily>,
+    pub timestamp: SystemTime,
+}
+
+impl Snapshot {
+    pub fn timestamp_ms(&self) -> i64 {
+        self.timestamp
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as i64)
+            .unwrap_or(0)
+    }
+}
+
+/// Periodically collects node metrics every `collect_interval_secs` and
+/// broadcasts the snapshot to subscribers
+pub struct Collector {
+    sender: broadcast::Sender<Arc<Snapshot>>,
+    interval: Duration,
+    selector: Arc<MetricSelector>,
+    inventory: Arc<InventoryChecker>,
+    changes: Arc<ChangeDetector>,
+    labels: BTreeMap<String, String>,
+}
+
+impl Collector {
+    pub fn new(
+        settings: &Settings,
+        selector: Arc<MetricSelector>,
+        inventory: Arc<InventoryChecker>,
+        changes: Arc<ChangeDetector>,
+    ) -> Self {
+        let (sender, _) = broadcast::channel(SNAPSHOT_CHANNEL_CAPACITY);
+        Self {
+            sender,
+            interval: Duration::from_secs(settings.metrics.collect_interval_secs.max(1)),
+            selector,
+            inventory,
+            changes,
+            labels: settings.labels.clone(),
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Snapshot>> {
+        self.sender.subscribe()
+    }
+
+    /// Collect a single snapshot without broadcasting it
+    pub async fn collect_once(&self) -> anyhow::Result<Snapshot> {
+        let timestamp = SystemTime::now();
+        let metrics = NodeMetrics::collect_shared()
+            .await?
+            .with_labels(&self.labels)
+            .with_inventory_checks(&self.inventory)
+            .with_inventory_changes(&self.changes);
+        let families = metrics.selected_families(&self.selector);
+
+        Ok(Snapshot {
+            metrics,
+            families,
+            timestamp,
+        })
+    }
+
+    /// Start the collection loop, unless nothing has subscribed
+    pub fn spawn(self) {
+        if self.sender.receiver_count() == 0 {
+            return;
+        }
+
+        info!(
+            "Starting background collection every {}s",
+            self.interval.as_secs()
+        );
+        tokio::spawn(self.run());
+    }
+
+    async fn run(self) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+
+            let snapshot = match self.collect_once().await {
+                Ok(snapshot) => Arc::new(snapshot),
+                Err(e) => {
+                    warn!("Background collection failed: {}", e);
+                    continue;
+                }
+            };
+
+            if self.sender.send(snapshot).is_err() {
+                warn!("No subscribers left, stopping background collection");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+impl Snapshot {
+    /// Snapshot of the given families, `labels` become the constant labels
+    pub fn with_families(families: Vec<MetricFamily>, labels: &[(&str, &str)]) -> Self {
+        let labels: BTreeMap<String, String> = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        Snapshot {
+            metrics: NodeMetrics::collect().with_labels(&labels),
+            families,
+            timestamp: UNIX_EPOCH + Duration::from_millis(1_700_000_000_123),
+        }
+    }
+}
@@ -1,119 +1,669 @@
 use config::{Config, Environment, File};
+use ipnet::IpNet;
 use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 
+/// Every table falls back to its default, so the exporter also starts
+/// without a config file
 #[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
 pub struct Settings {
     pub server: ServerSettings,
     pub metrics: MetricsSettings,
+    #[serde(default)]
+    pub auth: AuthSettings,
+    /// Constant labels added to every exported series, e.g. cluster or region
+    /// Values may reference environment variables as `${VAR}` or `${VAR:-default}`
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Push each background collection to a Prometheus remote-write endpoint
+    #[serde(default)]
+    pub remote_write: Option<RemoteWriteSettings>,
+    /// Export each background collection to an OpenTelemetry collector
+    #[serde(default)]
+    pub otlp: Option<OtlpSettings>,
+    /// InfluxDB line protocol output (`/metrics/influx`) and optional push
+    #[serde(default)]
+    pub influx: InfluxSettings,
+    /// Graphite plaintext output (`/metrics/graphite`) and optional push
+    #[serde(default)]
+    pub graphite: GraphiteSettings,
+    /// Emit each background collection to a StatsD / DogStatsD agent
+    #[serde(default)]
+    pub statsd: Option<StatsdSettings>,
+    /// Target of the `push` subcommand for batch and one-shot runs
+    #[serde(default)]
+    pub pushgateway: PushgatewaySettings,
+    /// In-memory history served by `/api/v1/history`
+    #[serde(default)]
+    pub history: HistorySettings,
+    /// Node-local alert rules evaluated after each background collection
+    #[serde(default)]
+    pub alerts: AlertSettings,
+    /// Node health conditions for node-problem-detector and NodeConditions,
+    /// evaluated in the background only when configured
+    #[serde(default)]
+    pub health: Option<HealthSettings>,
+    /// Expected hardware checked on every collection
+    #[serde(default)]
+    pub inventory: InventorySettings,
+    /// API server access used by features that talk to Kubernetes
+    #[serde(default)]
+    pub kubernetes: KubernetesSettings,
+    /// GPU collection through nvidia-smi
+    #[serde(default)]
+    pub gpu: GpuSettings,
+    /// Directory for state kept across restarts, e.g. a hostPath volume at
+    /// `/var/lib/system-info-exporter`; nothing is persisted when unset
+    #[serde(default)]
+    pub state_dir: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerSettings {
     pub host: String,
     pub port: u16,
+    /// Serve over HTTPS when set; plain HTTP otherwise
+    #[serde(default)]
+    pub tls: Option<TlsSettings>,
+}
+
+/// TLS configuration for the HTTP server
+#[derive(Debug, Deserialize, Clone)]
+pub struct TlsSettings {
+    /// PEM encoded certificate chain
+    pub cert_path: String,
+    /// PEM encoded private key
+    pub key_path: String,
+    /// PEM encoded CA bundle used to verify client certificates (enables mTLS)
+    #[serde(default)]
+    pub client_ca_path: Option<String>,
+    /// Interval for checking the certificate files for changes
+    #[serde(default = "default_tls_reload_interval_secs")]
+    pub reload_interval_secs: u64,
+}
+
+fn default_tls_reload_interval_secs() -> u64 {
+    30
+}
+
+/// Authentication for HTTP endpoints
+/// Disabled unless at least one of tokens, users or CIDRs is configured
+#[derive(Debug, Deserialize, Clone)]
+pub struct AuthSettings {
+    /// Accepted `Authorization: Bearer <token>` values
+    #[serde(default)]
+    pub bearer_tokens: Vec<String>,
+    /// HTTP basic auth users mapped to bcrypt password hashes
+    #[serde(default)]
+    pub basic_auth_users: HashMap<String, String>,
+    /// Client networks allowed to connect; empty allows any address
+    #[serde(default)]
+    pub allowed_cidrs: Vec<IpNet>,
+    /// Routes served without authentication (e.g. kubelet probes)
+    #[serde(default = "default_public_routes")]
+    pub public_routes: Vec<String>,
+}
+
+fn default_public_routes() -> Vec<String> {
+    vec![
+        "/health".to_string(),
+        "/healthz".to_string(),
+        "/ready".to_string(),
+    ]
+}
+
+impl Default for AuthSettings {
+    fn default() -> Self {
+        Self {
+            bearer_tokens: Vec::new(),
+            basic_auth_users: HashMap::new(),
+            allowed_cidrs: Vec::new(),
+            public_routes: default_public_routes(),
+        }
+    }
+}
+
+impl AuthSettings {
+    pub fn requires_credentials(&self) -> bool {
+        !self.bearer_tokens.is_empty() || !self.basic_auth_users.is_empty()
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.requires_credentials() || !self.allowed_cidrs.is_empty()
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct MetricsSettings {
     pub collect_interval_secs: u64,
+    /// Metric or collector name patterns to export; empty exports everything
+    /// Globs (`hw_gpu_*`) or anchored regexes wrapped in slashes (`/hw_(cpu|memory)_.*/`)
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Patterns removed from the included set
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// Legacy per-metric switches, a key set to false excludes its metric
     #[serde(default)]
     pub enabled: MetricsEnabled,
 }
 
-/// Configuration for which metrics are enabled
-#[derive(Debug, Deserialize, Clone)]
-pub struct MetricsEnabled {
-    // Node metrics
-    #[serde(default = "default_true")]
-    pub node_info: bool,
-    #[serde(default = "default_true")]
-    pub node_uptime: bool,
-
-    // CPU metrics
-    #[serde(default = "default_true")]
-    pub cpu_cores: bool,
-    #[serde(default = "default_true")]
-    pub cpu_threads: bool,
-    #[serde(default = "default_true")]
-    pub cpu_usage: bool,
-    #[serde(default = "default_true")]
-    pub cpu_used_cores: bool,
-
-    // Memory metrics
-    #[serde(default = "default_true")]
-    pub memory_total: bool,
-    #[serde(default = "default_true")]
-    pub memory_used: bool,
-    #[serde(default = "default_true")]
-    pub memory_available: bool,
-    #[serde(default = "default_true")]
-    pub memory_usage: bool,
-
-    // GPU metrics
-    #[serde(default = "default_true")]
-    pub gpu_count: bool,
-    #[serde(default = "default_true")]
-    pub gpu_used_count: bool,
-    #[serde(default = "default_true")]
-    pub gpu_type_count: bool,
-    #[serde(default = "default_true")]
-    pub gpu_memory_total: bool,
-    #[serde(default = "default_true")]
-    pub gpu_memory_used: bool,
-    #[serde(default = "default_true")]
-    pub gpu_memory_free: bool,
-    #[serde(default = "default_true")]
-    pub gpu_utilization: bool,
-    #[serde(default = "default_true")]
-    pub gpu_temperature: bool,
-    #[serde(default = "default_true")]
-    pub gpu_power_draw: bool,
-    #[serde(default = "default_true")]
-    pub gpu_power_limit: bool,
-}
-
-fn default_true() -> bool {
-    true
-}
-
-impl Default for MetricsEnabled {
+/// Legacy `[metrics.enabled]` table mapping keys such as `gpu_power_draw`
+/// to booleans, see `metrics::filter::LEGACY_METRIC_KEYS`
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(transparent)]
+pub struct MetricsEnabled(pub HashMap<String, bool>);
+
+/// Credentials and extra headers attached to outgoing HTTP requests
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct HttpAuthSettings {
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    #[serde(default)]
+    pub basic_auth: Option<BasicAuthSettings>,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct BasicAuthSettings {
+    pub username: String,
+    pub password: String,
+}
+
+/// Prometheus remote-write push configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct RemoteWriteSettings {
+    pub url: String,
+    #[serde(default = "default_push_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Maximum number of unsent collections kept during outages, oldest dropped first
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    #[serde(default = "default_min_backoff_ms")]
+    pub min_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default)]
+    pub auth: HttpAuthSettings,
+}
+
+/// OTLP/HTTP metrics export configuration
+#[derive(Debug, Deserialize, Clone)]
+pub struct OtlpSettings {
+    /// Full metrics URL, e.g. `http://otel-collector:4318/v1/metrics`
+    pub endpoint: String,
+    #[serde(default)]
+    pub protocol: OtlpProtocol,
+    #[serde(default = "default_push_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+    #[serde(default = "default_min_backoff_ms")]
+    pub min_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default)]
+    pub auth: HttpAuthSettings,
+}
+
+/// OTLP payload encoding
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OtlpProtocol {
+    #[default]
+    #[serde(rename = "http/protobuf")]
+    HttpProtobuf,
+    #[serde(rename = "http/json")]
+    HttpJson,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct InfluxSettings {
+    /// Send each background collection to a line protocol listener (e.g. Telegraf)
+    #[serde(default)]
+    pub push: Option<SocketPushSettings>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GraphiteSettings {
+    /// Path prefix prepended to every metric name, e.g. `k8s.hw`
+    #[serde(default)]
+    pub prefix: String,
+    /// Send each background collection to a carbon plaintext listener
+    #[serde(default)]
+    pub push: Option<SocketPushSettings>,
+}
+
+/// Plain socket push target
+#[derive(Debug, Deserialize, Clone)]
+pub struct SocketPushSettings {
+    /// `host:port` of the listener
+    pub address: String,
+    #[serde(default)]
+    pub transport: Transport,
+    #[serde(default = "default_push_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    #[default]
+    Tcp,
+    Udp,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct StatsdSettings {
+    /// `host:port` for UDP or `unix:///path/to/dsd.socket` for a unix datagram socket
+    pub address: String,
+    /// Prefix prepended to every metric name, e.g. `hw`
+    #[serde(default)]
+    pub prefix: String,
+    #[serde(default)]
+    pub flavor: StatsdFlavor,
+    /// Largest datagram to send, defaults to 1432 for UDP and 8192 for unix sockets
+    #[serde(default)]
+    pub max_packet_size: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsdFlavor {
+    /// Labels become `|#name:value` tags
+    #[default]
+    Dogstatsd,
+    /// No tag support, sample label values are appended to the metric path
+    Statsd,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HistorySettings {
+    /// How long background collections are kept, 0 disables the history
+    #[serde(default = "default_history_retention_secs")]
+    pub retention_secs: u64,
+}
+
+impl Default for HistorySettings {
+    fn default() -> Self {
+        Self {
+            retention_secs: default_history_retention_secs(),
+        }
+    }
+}
+
+fn default_history_retention_secs() -> u64 {
+    0
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AlertSettings {
+    #[serde(default)]
+    pub rules: Vec<AlertRuleSettings>,
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSettings>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AlertRuleSettings {
+    pub name: String,
+    /// `<metric>{<label>="<value>",...} <op> <threshold> [for <duration>]`,
+    /// e.g. `hw_gpu_temperature_celsius > 85 for 2m`
+    pub expr: String,
+    /// How long the condition must hold before firing, e.g. `2m`
+    #[serde(default, rename = "for")]
+    pub for_duration: Option<String>,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// May use `{{ $value }}` and `{{ $labels.<name> }}`
+    #[serde(default)]
+    pub annotations: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookSettings {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    /// Firing alerts are re-sent to Alertmanager so they do not auto-resolve
+    #[serde(default = "default_webhook_resend_interval_secs")]
+    pub resend_interval_secs: u64,
+    #[serde(default = "default_push_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub auth: HttpAuthSettings,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// Alertmanager `POST /api/v2/alerts` body
+    #[default]
+    Alertmanager,
+    /// `{"status": ..., "alerts": [...]}` sent on state changes only
+    Generic,
+}
+
+fn default_webhook_resend_interval_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HealthSettings {
+    /// GPUs the node should have, the GPUMissing condition is skipped when unset
+    #[serde(default)]
+    pub expected_gpu_count: Option<usize>,
+    /// Consecutive nvidia-smi failures before NvidiaSmiFailing is reported
+    #[serde(default = "default_nvidia_smi_failure_threshold")]
+    pub nvidia_smi_failure_threshold: u32,
+    /// Kernel log scanned for `NVRM: Xid` messages, empty disables the check
+    #[serde(default = "default_xid_log_path")]
+    pub xid_log_path: String,
+    /// Xid codes that indicate a GPU needing attention
+    #[serde(default = "default_critical_xids")]
+    pub critical_xids: Vec<u32>,
+    /// Mount points checked by the DiskFull condition
+    #[serde(default = "default_disk_paths")]
+    pub disk_paths: Vec<String>,
+    #[serde(default = "default_disk_full_percent")]
+    pub disk_full_percent: f64,
+    /// Patch the conditions onto the Node object through the API server
+    #[serde(default)]
+    pub node_conditions: Option<NodeConditionSettings>,
+}
+
+impl Default for HealthSettings {
+    fn default() -> Self {
+        Self {
+            expected_gpu_count: None,
+            nvidia_smi_failure_threshold: default_nvidia_smi_failure_threshold(),
+            xid_log_path: default_xid_log_path(),
+            critical_xids: default_critical_xids(),
+            disk_paths: default_disk_paths(),
+            disk_full_percent: default_disk_full_percent(),
+            node_conditions: None,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct NodeConditionSettings {
+    /// Conditions are re-patched at this interval even when unchanged
+    #[serde(default = "default_node_condition_heartbeat_secs")]
+    pub heartbeat_interval_secs: u64,
+    /// Deprecated aliases of the `[kubernetes]` keys, moved there on load
+    #[serde(default)]
+    pub api_server: Option<String>,
+    #[serde(default)]
+    pub token_path: Option<String>,
+    #[serde(default)]
+    pub ca_path: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Kubernetes API access for NodeConditions and node label lookups
+#[derive(Debug, Deserialize, Clone)]
+pub struct KubernetesSettings {
+    /// API server URL, defaults to the in-cluster service address
+    #[serde(default)]
+    pub api_server: Option<String>,
+    #[serde(default = "default_service_account_token_path")]
+    pub token_path: String,
+    /// CA bundle used to verify an https API server
+    #[serde(default = "default_service_account_ca_path")]
+    pub ca_path: String,
+    #[serde(default = "default_push_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for KubernetesSettings {
+    fn default() -> Self {
+        Self {
+            api_server: None,
+            token_path: default_service_account_token_path(),
+            ca_path: default_service_account_ca_path(),
+            timeout_secs: default_push_timeout_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GpuSettings {
+    /// Age after which cached GPU data stops being reported as current
+    #[serde(default = "default_gpu_cache_max_age_secs")]
+    pub cache_max_age_secs: u64,
+    /// What happens to per-device series once the cache is older than
+    /// `cache_max_age_secs`
+    #[serde(default)]
+    pub stale_policy: StalePolicy,
+    /// nvidia-smi binary, searched in the container and host paths when unset
+    #[serde(default)]
+    pub nvidia_smi_path: Option<String>,
+    /// Per invocation, the whole process group is killed once exceeded
+    #[serde(default = "default_nvidia_smi_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Prepended to `LD_LIBRARY_PATH`, e.g. host driver libraries for a
+    /// host-mounted nvidia-smi
+    #[serde(default)]
+    pub library_path: Option<String>,
+    /// Keep a long-running `nvidia-smi dmon` and export min/avg/max/p95 of
+    /// utilization and power over `metrics.collect_interval_secs`
+    #[serde(default)]
+    pub sampling: bool,
+    /// Seconds between `nvidia-smi dmon` samples
+    #[serde(default = "default_gpu_sample_interval_secs")]
+    pub sample_interval_secs: u64,
+}
+
+impl Default for GpuSettings {
     fn default() -> Self {
         Self {
-            node_info: true,
-            node_uptime: true,
-            cpu_cores: true,
-            cpu_threads: true,
-            cpu_usage: true,
-            cpu_used_cores: true,
-            memory_total: true,
-            memory_used: true,
-            memory_available: true,
-            memory_usage: true,
-            gpu_count: true,
-            gpu_used_count: true,
-            gpu_type_count: true,
-            gpu_memory_total: true,
-            gpu_memory_used: true,
-            gpu_memory_free: true,
-            gpu_utilization: true,
-            gpu_temperature: true,
-            gpu_power_draw: true,
-            gpu_power_limit: true,
+            cache_max_age_secs: default_gpu_cache_max_age_secs(),
+            stale_policy: StalePolicy::default(),
+            nvidia_smi_path: None,
+            timeout_secs: default_nvidia_smi_timeout_secs(),
+            library_path: None,
+            sampling: false,
+            sample_interval_secs: default_gpu_sample_interval_secs(),
         }
     }
 }
 
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StalePolicy {
+    /// Keep the series with a `stale="true"` label
+    #[default]
+    Mark,
+    /// Stop exporting the series
+    Drop,
+}
+
+fn default_gpu_cache_max_age_secs() -> u64 {
+    300
+}
+
+fn default_nvidia_smi_timeout_secs() -> u64 {
+    5
+}
+
+fn default_gpu_sample_interval_secs() -> u64 {
+    1
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct InventorySettings {
+    /// Expected hardware, the first entry matching the node applies
+    #[serde(default)]
+    pub expected: Vec<ExpectedHardwareSettings>,
+}
+
+/// Hardware a node should have, every unset field is left unchecked
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ExpectedHardwareSettings {
+    /// Shown in `/api/v1/inventory/check`, defaults to the entry position
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Node name globs or `/regex/` patterns
+    #[serde(default)]
+    pub nodes: Vec<String>,
+    /// Node labels that must all match, looked up through the API server
+    #[serde(default)]
+    pub node_selector: BTreeMap<String, String>,
+    #[serde(default)]
+    pub gpu_count: Option<usize>,
+    /// Substring every GPU name must contain, e.g. `A100-SXM4-80GB`
+    #[serde(default)]
+    pub gpu_model: Option<String>,
+    /// Lower bound for `hw_memory_total_bytes`, which excludes memory reserved
+    /// by firmware and the kernel
+    #[serde(default)]
+    pub min_memory_gib: Option<f64>,
+    /// Substring the CPU brand must contain, e.g. `EPYC 7763`
+    #[serde(default)]
+    pub cpu_model: Option<String>,
+    /// Physical core count
+    #[serde(default)]
+    pub cpu_cores: Option<usize>,
+}
+
+fn default_nvidia_smi_failure_threshold() -> u32 {
+    3
+}
+
+fn default_xid_log_path() -> String {
+    "/dev/kmsg".to_string()
+}
+
+fn default_critical_xids() -> Vec<u32> {
+    vec![48, 61, 62, 63, 64, 68, 69, 73, 74, 79, 92, 94, 95, 119, 120]
+}
+
+fn default_disk_paths() -> Vec<String> {
+    vec!["/".to_string()]
+}
+
+fn default_disk_full_percent() -> f64 {
+    95.0
+}
+
+fn default_service_account_token_path() -> String {
+    "/var/run/secrets/kubernetes.io/serviceaccount/token".to_string()
+}
+
+fn default_service_account_ca_path() -> String {
+    "/var/run/secrets/kubernetes.io/serviceaccount/ca.crt".to_string()
+}
+
+fn default_node_condition_heartbeat_secs() -> u64 {
+    60
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PushgatewaySettings {
+    /// Base URL, e.g. `http://pushgateway:9091`
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default = "default_pushgateway_job")]
+    pub job: String,
+    /// Extra grouping key labels after `job` and `node`, values may reference
+    /// environment variables like constant labels
+    #[serde(default)]
+    pub grouping_labels: BTreeMap<String, String>,
+    #[serde(default)]
+    pub method: PushMethod,
+    /// Delete the group from the Pushgateway once the run finishes
+    #[serde(default)]
+    pub delete_on_exit: bool,
+    /// Attempts per push when the Pushgateway is unavailable
+    #[serde(default = "default_pushgateway_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_push_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub auth: HttpAuthSettings,
+}
+
+impl Default for PushgatewaySettings {
+    fn default() -> Self {
+        Self {
+            url: None,
+            job: default_pushgateway_job(),
+            grouping_labels: BTreeMap::new(),
+            method: PushMethod::default(),
+            delete_on_exit: false,
+            max_attempts: default_pushgateway_max_attempts(),
+            timeout_secs: default_push_timeout_secs(),
+            auth: HttpAuthSettings::default(),
+        }
+    }
+}
+
+/// `put` replaces every metric of the group, `post` only those with the same name
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PushMethod {
+    #[default]
+    Put,
+    Post,
+}
+
+fn default_pushgateway_job() -> String {
+    "system-info-exporter".to_string()
+}
+
+fn default_pushgateway_max_attempts() -> u32 {
+    3
+}
+
+fn default_push_timeout_secs() -> u64 {
+    10
+}
+
+fn default_queue_capacity() -> usize {
+    1000
+}
+
+fn default_min_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_backoff_secs() -> u64 {
+    60
+}
+
 impl Default for Settings {
     fn default() -> Self {
         Self {
             server: ServerSettings {
                 host: "0.0.0.0".to_string(),
                 port: 8080,
+                tls: None,
             },
             metrics: MetricsSettings {
                 collect_interval_secs: 15,
+                include: Vec::new(),
+                exclude: Vec::new(),
                 enabled: MetricsEnabled::default(),
             },
+            auth: AuthSettings::default(),
+            labels: BTreeMap::new(),
+            remote_write: None,
+            otlp: None,
+            influx: InfluxSettings::default(),
+            graphite: GraphiteSettings::default(),
+            statsd: None,
+            pushgateway: PushgatewaySettings::default(),
+            history: HistorySettings::default(),
+            alerts: AlertSettings::default(),
+            health: None,
+            inventory: InventorySettings::default(),
+            kubernetes: KubernetesSettings::default(),
+            gpu: GpuSettings::default(),
+            state_dir: None,
         }
     }
 }
@@ -126,7 +676,177 @@ impl Settings {
             .add_source(Environment::with_prefix("APP").separator("__"))
             .build()?;
 
-        let settings: Settings = config.try_deserialize().unwrap_or_default();
+        let mut settings: Settings = config.try_deserialize()?;
+        settings.move_deprecated_kubernetes_keys();
+        settings.labels = resolve_labels(&settings.labels)?;
+        settings.pushgateway.grouping_labels =
+            resolve_labels(&settings.pushgateway.grouping_labels)?;
         Ok(settings)
     }
+
+    /// API server access used to live in `[health.node_conditions]`, those
+    /// keys still apply and take precedence over `[kubernetes]`
+    fn move_deprecated_kubernetes_keys(&mut self) {
+        let Some(node_conditions) = self
+            .health
+            .as_mut()
+            .and_then(|health| health.node_conditions.as_mut())
+        else {
+            return;
+        };
+
+        let kubernetes = &mut self.kubernetes;
+        let mut moved = Vec::new();
+        if let Some(api_server) = node_conditions.api_server.take() {
+            kubernetes.api_server = Some(api_server);
+            moved.push("api_server");
+        }
+        if let Some(token_path) = node_conditions.token_path.take() {
+            kubernetes.token_path = token_path;
+            moved.push("token_path");
+        }
+        if let Some(ca_path) = node_conditions.ca_path.take() {
+            kubernetes.ca_path = ca_path;
+            moved.push("ca_path");
+        }
+        if let Some(timeout_secs) = node_conditions.timeout_secs.take() {
+            kubernetes.timeout_secs = timeout_secs;
+            moved.push("timeout_secs");
+        }
+
+        if !moved.is_empty() {
+            tracing::warn!(
+                "[health.node_conditions] {} are deprecated, set them in [kubernetes]",
+                moved.join(", ")
+            );
+        }
+    }
+}
+
+/// Expand environment references in constant label values and validate names
+/// Labels that expand to an empty value are dropped, matching Prometheus
+/// semantics where an empty label is the same as an absent one
+fn resolve_labels(labels: &BTreeMap<String, String>) -> anyhow::Result<BTreeMap<String, String>> {
+    let mut resolved = BTreeMap::new();
+
+    for (name, value) in labels {
+        let valid_name = name
+            .chars()
+            .enumerate()
+            .all(|(i, c)| c == '_' || c.is_ascii_alphabetic() || (i > 0 && c.is_ascii_digit()));
+        if name.is_empty() || !valid_name || name.starts_with("__") {
+            anyhow::bail!("invalid label name: {}", name);
+        }
+
+        let value = expand_env(value);
+        if value.is_empty() {
+            tracing::warn!("Constant label {} is empty, skipping", name);
+            continue;
+        }
+        resolved.insert(name.clone(), value);
+    }
+
+    Ok(resolved)
+}
+
+/// Replace `${VAR}` and `${VAR:-default}` with values from the environment
+fn expand_env(value: &str) -> String {
+    let mut output = String::new();
+    let mut rest = value;
+
+    while let Some(start) = rest.find("${") {
+        // Leave an unterminated reference as literal text
+        let Some(end) = rest[start..].find('}') else {
+            break;
+        };
+        output.push_str(&rest[..start]);
+        let expr = &rest[start + 2..start + end];
+        let (var, default) = match expr.split_once(":-") {
+            Some((var, default)) => (var, default),
+            None => (expr, ""),
+        };
+        match std::env::var(var) {
+            Ok(v) if !v.is_empty() => output.push_str(&v),
+            _ => output.push_str(default),
+        }
+        rest = &rest[start + end + 1..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+/// Parse a duration such as `90`, `30s`, `10m`, `1h` or `1h30m`
+/// A bare number is taken as seconds
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim();
+    if s.is_empty() {
+        return None;
+    }
+    if let Ok(secs) = s.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let mut total = 0u64;
+    let mut digits = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+        let value: u64 = digits.parse().ok()?;
+        digits.clear();
+        let unit = match c {
+            's' => 1,
+            'm' => 60,
+            'h' => 3600,
+            'd' => 86400,
+            _ => return None,
+        };
+        total = total.checked_add(value.checked_mul(unit)?)?;
+    }
+
+    // Trailing digits without a unit, e.g. `1h30`
+    if !digits.is_empty() {
+        return None;
+    }
+    Some(Duration::from_secs(total))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::FileFormat;
+
+    #[test]
+    fn deprecated_node_condition_keys_move_to_kubernetes() {
+        let mut settings: Settings = Config::builder()
+            .add_source(File::from_str(
+                r#"
+                [health.node_conditions]
+                heartbeat_interval_secs = 30
+                api_server = "https://10.0.0.1:6443"
+                token_path = "/etc/exporter/token"
+                timeout_secs = 3
+                "#,
+                FileFormat::Toml,
+            ))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        settings.move_deprecated_kubernetes_keys();
+
+        let kubernetes = &settings.kubernetes;
+        assert_eq!(
+            kubernetes.api_server.as_deref(),
+            Some("https://10.0.0.1:6443")
+        );
+        assert_eq!(kubernetes.token_path, "/etc/exporter/token");
+        assert_eq!(kubernetes.ca_path, default_service_account_ca_path());
+        assert_eq!(kubernetes.timeout_secs, 3);
+        let node_conditions = settings.health.unwrap().node_conditions.unwrap();
+        assert_eq!(node_conditions.heartbeat_interval_secs, 30);
+        assert!(node_conditions.api_server.is_none());
+    }
 }
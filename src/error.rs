@@ -2,22 +2,47 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AppError {
-    #[error("Configuration error: {0}")]
-    Config(String),
-
     #[error("Metrics collection error: {0}")]
     Metrics(String),
 
     #[error("Internal server error: {0}")]
     Internal(String),
+
+    #[error("Bad request: {0}")]
+    BadRequest(String),
+
+    #[error("Not found: {0}")]
+    NotFound(String),
+
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
 }
 
 impl axum::response::IntoResponse for AppError {
     fn into_response(self) -> axum::response::Response {
         let (status, message) = match self {
-            AppError::Config(msg) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg),
             AppError::Metrics(msg) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg),
             AppError::Internal(msg) => (axum::http::StatusCode::INTERNAL_SERVER_ERROR, msg),
+            AppError::BadRequest(msg) => (axum::http::StatusCode::BAD_REQUEST, msg),
+            AppError::NotFound(msg) => (axum::http::StatusCode::NOT_FOUND, msg),
+            AppError::Unauthorized(msg) => {
+                let body = serde_json::json!({
+                    "error": msg
+                });
+                return (
+                    axum::http::StatusCode::UNAUTHORIZED,
+                    [(
+                        axum::http::header::WWW_AUTHENTICATE,
+                        "Basic realm=\"system-info-exporter\"",
+                    )],
+                    axum::Json(body),
+                )
+                    .into_response();
+            }
+            AppError::Forbidden(msg) => (axum::http::StatusCode::FORBIDDEN, msg),
         };
 
         let body = serde_json::json!({
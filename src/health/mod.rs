@@ -0,0 +1,772 @@
+use regex::Regex;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex, RwLock};
+use sysinfo::Disks;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::collector::Snapshot;
+use crate::config::{HealthSettings, KubernetesSettings};
+use crate::metrics::{self, NodeMetrics};
+
+pub mod node_conditions;
+
+/// Condition types, named like the NodeConditions they are patched as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, clap::ValueEnum)]
+pub enum ConditionType {
+    #[value(name = "GPUMissing")]
+    GpuMissing,
+    #[value(name = "GPUXidError")]
+    GpuXidError,
+    #[value(name = "GPUDoubleBitECCError")]
+    GpuDoubleBitEccError,
+    #[value(name = "NvidiaSmiFailing")]
+    NvidiaSmiFailing,
+    #[value(name = "DiskFull")]
+    DiskFull,
+}
+
+impl ConditionType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConditionType::GpuMissing => "GPUMissing",
+            ConditionType::GpuXidError => "GPUXidError",
+            ConditionType::GpuDoubleBitEccError => "GPUDoubleBitECCError",
+            ConditionType::NvidiaSmiFailing => "NvidiaSmiFailing",
+            ConditionType::DiskFull => "DiskFull",
+        }
+    }
+}
+
+impl Serialize for ConditionType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// `True` means the problem is present, as for kubelet's pressure conditions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ConditionStatus {
+    True,
+    False,
+    Unknown,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Condition {
+    #[serde(rename = "type")]
+    pub condition_type: ConditionType,
+    pub status: ConditionStatus,
+    pub reason: String,
+    pub message: String,
+}
+
+impl Condition {
+    fn new(
+        condition_type: ConditionType,
+        status: ConditionStatus,
+        reason: &str,
+        message: String,
+    ) -> Self {
+        Self {
+            condition_type,
+            status,
+            reason: reason.to_string(),
+            message,
+        }
+    }
+}
+
+/// Kernel log read so far, later evaluations only look at new records
+#[derive(Default)]
+struct XidScan {
+    /// Last `/dev/kmsg` sequence number seen
+    last_sequence: Option<u64>,
+    /// Length of a regular log file when it was last read
+    offset: usize,
+    /// Most recent critical Xid per PCI device
+    devices: BTreeMap<String, u32>,
+}
+
+/// Evaluates health conditions from collected metrics and node state
+pub struct HealthChecker {
+    settings: HealthSettings,
+    xid_pattern: Regex,
+    xid_scan: Mutex<XidScan>,
+}
+
+impl HealthChecker {
+    pub fn new(settings: HealthSettings) -> Self {
+        Self {
+            settings,
+            xid_pattern: Regex::new(r"NVRM: Xid \(PCI:([0-9A-Fa-f:.]+)\): (\d+)")
+                .expect("Xid pattern is valid"),
+            xid_scan: Mutex::new(XidScan::default()),
+        }
+    }
+
+    /// Every condition that applies to this node, checks without the data to
+    /// decide (no expected GPU count, no NVIDIA driver) are left out
+    pub fn evaluate(&self, metrics: &NodeMetrics) -> Vec<Condition> {
+        [
+            self.gpu_missing(metrics),
+            self.xid_errors(),
+            ecc_errors(metrics),
+            self.nvidia_smi_failing(metrics),
+            self.disk_full(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    fn gpu_missing(&self, metrics: &NodeMetrics) -> Option<Condition> {
+        let expected = self.settings.expected_gpu_count?;
+        if metrics.nvidia_smi_failures > 0 {
+            return Some(nvidia_smi_unknown(ConditionType::GpuMissing));
+        }
+        let found = metrics.gpu_count;
+        let message = format!("found {} of {} expected GPU(s)", found, expected);
+
+        Some(if found < expected {
+            Condition::new(
+                ConditionType::GpuMissing,
+                ConditionStatus::True,
+                "GPUCountBelowExpected",
+                message,
+            )
+        } else {
+            Condition::new(
+                ConditionType::GpuMissing,
+                ConditionStatus::False,
+                "GPUCountAsExpected",
+                message,
+            )
+        })
+    }
+
+    fn xid_errors(&self) -> Option<Condition> {
+        let path = &self.settings.xid_log_path;
+        if path.is_empty() {
+            return None;
+        }
+
+        let log = match read_kernel_log(path) {
+            Ok(log) => log,
+            Err(e) => {
+                return Some(Condition::new(
+                    ConditionType::GpuXidError,
+                    ConditionStatus::Unknown,
+                    "KernelLogUnavailable",
+                    format!("cannot read {}: {}", path, e),
+                ))
+            }
+        };
+
+        let mut scan = self.xid_scan.lock().unwrap();
+        let scan = &mut *scan;
+        for line in new_records(&log, scan) {
+            for caps in self.xid_pattern.captures_iter(line) {
+                let Ok(xid) = caps[2].parse::<u32>() else {
+                    continue;
+                };
+                if self.settings.critical_xids.contains(&xid) {
+                    scan.devices.insert(caps[1].to_string(), xid);
+                }
+            }
+        }
+        let devices = &scan.devices;
+
+        Some(if devices.is_empty() {
+            Condition::new(
+                ConditionType::GpuXidError,
+                ConditionStatus::False,
+                "NoCriticalXid",
+                "no critical Xid errors in the kernel log".to_string(),
+            )
+        } else {
+            let details: Vec<String> = devices
+                .iter()
+                .map(|(pci, xid)| format!("Xid {} on {}", xid, pci))
+                .collect();
+            Condition::new(
+                ConditionType::GpuXidError,
+                ConditionStatus::True,
+                "CriticalXid",
+                details.join(", "),
+            )
+        })
+    }
+
+    fn nvidia_smi_failing(&self, metrics: &NodeMetrics) -> Option<Condition> {
+        // Failures are only counted with a driver loaded
+        let failures = metrics.nvidia_smi_failures;
+        if failures == 0 && !metrics::has_nvidia_gpu() {
+            return None;
+        }
+
+        let threshold = self.settings.nvidia_smi_failure_threshold.max(1);
+        Some(if failures >= threshold {
+            Condition::new(
+                ConditionType::NvidiaSmiFailing,
+                ConditionStatus::True,
+                "NvidiaSmiFailing",
+                format!("nvidia-smi failed {} time(s) in a row", failures),
+            )
+        } else if failures > 0 {
+            Condition::new(
+                ConditionType::NvidiaSmiFailing,
+                ConditionStatus::False,
+                "NvidiaSmiFailedBelowThreshold",
+                format!(
+                    "nvidia-smi failed {} time(s) in a row, below the threshold of {}",
+                    failures, threshold
+                ),
+            )
+        } else {
+            Condition::new(
+                ConditionType::NvidiaSmiFailing,
+                ConditionStatus::False,
+                "NvidiaSmiResponding",
+                "nvidia-smi is responding".to_string(),
+            )
+        })
+    }
+
+    fn disk_full(&self) -> Option<Condition> {
+        if self.settings.disk_paths.is_empty() {
+            return None;
+        }
+
+        let disks = Disks::new_with_refreshed_list();
+        let mut full = Vec::new();
+        let mut missing = Vec::new();
+
+        for path in &self.settings.disk_paths {
+            // The filesystem holding a path is the one with the longest
+            // mount point that prefixes it
+            let disk = disks
+                .iter()
+                .filter(|disk| Path::new(path).starts_with(disk.mount_point()))
+                .max_by_key(|disk| disk.mount_point().as_os_str().len());
+            let Some(disk) = disk else {
+                missing.push(path.as_str());
+                continue;
+            };
+            if disk.total_space() == 0 {
+                continue;
+            }
+
+            let used = disk.total_space() - disk.available_space();
+            let percent = used as f64 / disk.total_space() as f64 * 100.0;
+            if percent >= self.settings.disk_full_percent {
+                full.push(format!("{} is {:.1}% full", path, percent));
+            }
+        }
+
+        Some(if !full.is_empty() {
+            Condition::new(
+                ConditionType::DiskFull,
+                ConditionStatus::True,
+                "DiskFull",
+                full.join(", "),
+            )
+        } else if !missing.is_empty() {
+            Condition::new(
+                ConditionType::DiskFull,
+                ConditionStatus::Unknown,
+                "FilesystemNotFound",
+                format!("no filesystem found for {}", missing.join(", ")),
+            )
+        } else {
+            Condition::new(
+                ConditionType::DiskFull,
+                ConditionStatus::False,
+                "DiskHasSpace",
+                format!(
+                    "all checked filesystems are below {}%",
+                    self.settings.disk_full_percent
+                ),
+            )
+        })
+    }
+}
+
+/// GPU conditions cannot be decided from cached data while nvidia-smi fails
+fn nvidia_smi_unknown(condition_type: ConditionType) -> Condition {
+    Condition::new(
+        condition_type,
+        ConditionStatus::Unknown,
+        "NvidiaSmiFailing",
+        "nvidia-smi is failing, GPU data is unavailable".to_string(),
+    )
+}
+
+fn ecc_errors(metrics: &NodeMetrics) -> Option<Condition> {
+    if metrics.nvidia_smi_failures > 0 {
+        return Some(nvidia_smi_unknown(ConditionType::GpuDoubleBitEccError));
+    }
+    if metrics.gpu_devices.is_empty() {
+        return None;
+    }
+
+    let affected: Vec<String> = metrics
+        .gpu_devices
+        .iter()
+        .filter_map(|gpu| {
+            let errors = gpu.ecc_uncorrected_errors.filter(|&errors| errors > 0)?;
+            Some(format!("GPU {} has {} error(s)", gpu.index, errors))
+        })
+        .collect();
+
+    Some(if affected.is_empty() {
+        Condition::new(
+            ConditionType::GpuDoubleBitEccError,
+            ConditionStatus::False,
+            "NoDoubleBitECCErrors",
+            "no uncorrected ECC errors".to_string(),
+        )
+    } else {
+        Condition::new(
+            ConditionType::GpuDoubleBitEccError,
+            ConditionStatus::True,
+            "DoubleBitECCErrors",
+            affected.join(", "),
+        )
+    })
+}
+
+/// Lines of `log` not seen by earlier scans. `/dev/kmsg` is read from the
+/// start of its ring buffer every time, its records are told apart by their
+/// sequence number. A regular file is followed by offset and read again from
+/// the start once it shrinks
+fn new_records<'a>(log: &'a str, scan: &mut XidScan) -> Vec<&'a str> {
+    if log.lines().next().and_then(kmsg_sequence).is_some() {
+        let last = scan.last_sequence;
+        return log
+            .lines()
+            .filter(|line| {
+                let Some(sequence) = kmsg_sequence(line) else {
+                    return false;
+                };
+                scan.last_sequence = scan.last_sequence.max(Some(sequence));
+                last.is_none_or(|last| sequence > last)
+            })
+            .collect();
+    }
+
+    if log.len() < scan.offset {
+        scan.offset = 0;
+    }
+    let new = log.get(scan.offset..).unwrap_or(log);
+    scan.offset = log.len();
+    new.lines().collect()
+}
+
+/// Sequence number of a `/dev/kmsg` record, `<prio>,<seq>,<usec>,<flags>;<msg>`
+fn kmsg_sequence(line: &str) -> Option<u64> {
+    let (prefix, _) = line.split_once(';')?;
+    let mut fields = prefix.split(',');
+    fields.next()?.parse::<u32>().ok()?;
+    fields.next()?.parse().ok()
+}
+
+/// Read a kernel log without blocking. `/dev/kmsg` returns one record per
+/// read and `EAGAIN` once the buffer is drained, regular files end at EOF
+fn read_kernel_log(path: &str) -> std::io::Result<String> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path)?;
+
+    let mut log = String::new();
+    let mut buf = vec![0u8; 8192];
+    loop {
+        match file.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => log.push_str(&String::from_utf8_lossy(&buf[..n])),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            // Records were overwritten while reading, continue with the next one
+            Err(e) if e.raw_os_error() == Some(libc::EPIPE) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(log)
+}
+
+/// Latest conditions of the background collection, optionally mirrored to
+/// the Node object
+pub struct HealthMonitor {
+    checker: HealthChecker,
+    enabled: bool,
+    kubernetes: KubernetesSettings,
+    conditions: RwLock<Vec<Condition>>,
+}
+
+impl HealthMonitor {
+    pub fn new(settings: Option<HealthSettings>, kubernetes: KubernetesSettings) -> Self {
+        Self {
+            enabled: settings.is_some(),
+            checker: HealthChecker::new(settings.unwrap_or_default()),
+            kubernetes,
+            conditions: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Whether `[health]` is configured
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn conditions(&self) -> Vec<Condition> {
+        self.conditions.read().unwrap().clone()
+    }
+
+    pub fn spawn(
+        self: &Arc<Self>,
+        mut snapshots: broadcast::Receiver<Arc<Snapshot>>,
+    ) -> anyhow::Result<()> {
+        let mut patcher = match &self.checker.settings.node_conditions {
+            Some(settings) => Some(node_conditions::NodeConditionPatcher::new(
+                settings,
+                &self.kubernetes,
+            )?),
+            None => None,
+        };
+
+        let monitor = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let snapshot = match snapshots.recv().await {
+                    Ok(snapshot) => snapshot,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Health evaluation skipped {} snapshot(s)", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+
+                let conditions = {
+                    let metrics = snapshot.metrics.clone();
+                    let checker = monitor.clone();
+                    // Reads the kernel log and disk list, keep it off the runtime
+                    match tokio::task::spawn_blocking(move || checker.checker.evaluate(&metrics))
+                        .await
+                    {
+                        Ok(conditions) => conditions,
+                        Err(e) => {
+                            warn!("Health evaluation failed: {}", e);
+                            continue;
+                        }
+                    }
+                };
+
+                let previous = monitor.conditions();
+                for condition in &conditions {
+                    let changed = previous
+                        .iter()
+                        .find(|c| c.condition_type == condition.condition_type)
+                        .is_none_or(|c| c.status != condition.status);
+                    if changed {
+                        info!(
+                            "Health condition {} is {:?}: {}",
+                            condition.condition_type.as_str(),
+                            condition.status,
+                            condition.message
+                        );
+                    }
+                }
+
+                if let Some(patcher) = patcher.as_mut() {
+                    patcher.update(&snapshot.metrics.node, &conditions).await;
+                }
+                *monitor.conditions.write().unwrap() = conditions;
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// node-problem-detector custom plugin mode: collect once, print a message
+/// and exit 0 when OK, 1 when a problem is present and 2 when unknown
+pub async fn run_check(settings: &HealthSettings, condition: Option<ConditionType>) -> ExitCode {
+    let (code, message) = check(settings, condition, NodeMetrics::collect).await;
+    println!("{}", message);
+    code
+}
+
+/// Exit code and message of `run_check` for the metrics returned by `collect`
+async fn check<F>(
+    settings: &HealthSettings,
+    condition: Option<ConditionType>,
+    collect: F,
+) -> (ExitCode, String)
+where
+    F: FnOnce() -> NodeMetrics + Send + 'static,
+{
+    // A single run sees at most one failure, it must be enough to report
+    let checker = HealthChecker::new(HealthSettings {
+        nvidia_smi_failure_threshold: 1,
+        ..settings.clone()
+    });
+
+    let conditions = match tokio::task::spawn_blocking(move || checker.evaluate(&collect())).await {
+        Ok(conditions) => conditions,
+        Err(e) => {
+            return (
+                ExitCode::from(2),
+                format!("health evaluation failed: {}", e),
+            )
+        }
+    };
+
+    let conditions: Vec<&Condition> = conditions
+        .iter()
+        .filter(|c| condition.is_none_or(|wanted| c.condition_type == wanted))
+        .collect();
+    if conditions.is_empty() {
+        return (
+            ExitCode::SUCCESS,
+            format!(
+                "{} does not apply to this node",
+                condition.map_or("no condition", |c| c.as_str())
+            ),
+        );
+    }
+
+    let with_status = |status: ConditionStatus| -> Vec<String> {
+        conditions
+            .iter()
+            .filter(|c| c.status == status)
+            .map(|c| format!("{}: {}", c.condition_type.as_str(), c.message))
+            .collect()
+    };
+
+    let problems = with_status(ConditionStatus::True);
+    if !problems.is_empty() {
+        return (ExitCode::from(1), problems.join("; "));
+    }
+    let unknown = with_status(ConditionStatus::Unknown);
+    if !unknown.is_empty() {
+        return (ExitCode::from(2), unknown.join("; "));
+    }
+    (
+        ExitCode::SUCCESS,
+        with_status(ConditionStatus::False).join("; "),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{nvidia_smi, GpuInfo};
+    use std::io::Write;
+
+    fn checker(log: &Path) -> HealthChecker {
+        HealthChecker::new(HealthSettings {
+            xid_log_path: log.to_str().unwrap().to_string(),
+            ..Default::default()
+        })
+    }
+
+    fn xid_message(checker: &HealthChecker) -> (ConditionStatus, String) {
+        let condition = checker.xid_errors().unwrap();
+        (condition.status, condition.message)
+    }
+
+    #[test]
+    fn kmsg_records_are_evaluated_once() {
+        let log = tempfile::NamedTempFile::new().unwrap();
+        let checker = checker(log.path());
+        std::fs::write(
+            log.path(),
+            "6,10,100,-;NVRM: loading NVIDIA UNIX x86_64 Kernel Module\n\
+             4,11,200,-;NVRM: Xid (PCI:0000:3b:00): 13, pid=1, Graphics Exception\n",
+        )
+        .unwrap();
+        assert_eq!(xid_message(&checker).0, ConditionStatus::False);
+
+        std::fs::write(
+            log.path(),
+            "4,11,200,-;NVRM: Xid (PCI:0000:3b:00): 13, pid=1, Graphics Exception\n\
+             4,12,300,-;NVRM: Xid (PCI:0000:3b:00): 79, pid=1, GPU has fallen off the bus\n\
+             \x20SUBSYSTEM=pci\n",
+        )
+        .unwrap();
+        assert_eq!(
+            xid_message(&checker),
+            (ConditionStatus::True, "Xid 79 on 0000:3b:00".to_string())
+        );
+
+        // Records up to the last sequence number were already evaluated
+        std::fs::write(
+            log.path(),
+            "4,12,300,-;NVRM: Xid (PCI:0000:3b:00): 79, pid=1, GPU has fallen off the bus\n\
+             4,9,50,-;NVRM: Xid (PCI:0000:86:00): 48, pid=1, DBE\n\
+             4,13,400,-;NVRM: Xid (PCI:0000:3b:00): 94, pid=1, Contained ECC error\n",
+        )
+        .unwrap();
+        assert_eq!(
+            xid_message(&checker),
+            (ConditionStatus::True, "Xid 94 on 0000:3b:00".to_string())
+        );
+    }
+
+    #[test]
+    fn log_files_are_followed_by_offset() {
+        let mut log = tempfile::NamedTempFile::new().unwrap();
+        let checker = checker(log.path());
+        writeln!(log, "kernel: NVRM: Xid (PCI:0000:3b:00): 79, pid=1").unwrap();
+        assert_eq!(
+            xid_message(&checker),
+            (ConditionStatus::True, "Xid 79 on 0000:3b:00".to_string())
+        );
+
+        writeln!(log, "kernel: NVRM: Xid (PCI:0000:86:00): 48, pid=1").unwrap();
+        assert_eq!(
+            xid_message(&checker).1,
+            "Xid 79 on 0000:3b:00, Xid 48 on 0000:86:00"
+        );
+
+        // Rotated, the new file is read from its start
+        std::fs::write(
+            log.path(),
+            "kernel: NVRM: Xid (PCI:0000:3b:00): 94, pid=1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            xid_message(&checker).1,
+            "Xid 94 on 0000:3b:00, Xid 48 on 0000:86:00"
+        );
+    }
+
+    /// Settings with only the GPU checks enabled
+    fn gpu_settings(threshold: u32) -> HealthSettings {
+        HealthSettings {
+            expected_gpu_count: Some(8),
+            nvidia_smi_failure_threshold: threshold,
+            xid_log_path: String::new(),
+            disk_paths: Vec::new(),
+            ..Default::default()
+        }
+    }
+
+    fn gpus(errors: &[u64]) -> Vec<GpuInfo> {
+        errors
+            .iter()
+            .enumerate()
+            .map(|(index, errors)| GpuInfo {
+                index: index as u32,
+                ecc_uncorrected_errors: Some(*errors),
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn status(conditions: &[Condition], condition_type: ConditionType) -> (ConditionStatus, &str) {
+        let condition = conditions
+            .iter()
+            .find(|c| c.condition_type == condition_type)
+            .unwrap();
+        (condition.status, condition.reason.as_str())
+    }
+
+    #[test]
+    fn gpu_conditions_use_the_collected_devices() {
+        let mut metrics = NodeMetrics::collect();
+        metrics.nvidia_smi_failures = 0;
+        metrics.gpu_devices = gpus(&[0, 2, 0, 0, 0, 0, 0]);
+        metrics.gpu_count = metrics.gpu_devices.len();
+
+        let conditions = HealthChecker::new(gpu_settings(3)).evaluate(&metrics);
+        assert_eq!(
+            status(&conditions, ConditionType::GpuMissing),
+            (ConditionStatus::True, "GPUCountBelowExpected")
+        );
+        let ecc = conditions
+            .iter()
+            .find(|c| c.condition_type == ConditionType::GpuDoubleBitEccError)
+            .unwrap();
+        assert_eq!(ecc.status, ConditionStatus::True);
+        assert_eq!(ecc.message, "GPU 1 has 2 error(s)");
+    }
+
+    #[test]
+    fn failing_nvidia_smi_makes_gpu_conditions_unknown() {
+        let mut metrics = NodeMetrics::collect();
+        // Devices served from the cache
+        metrics.gpu_devices = gpus(&[0, 2, 0, 0, 0, 0, 0, 0]);
+        metrics.gpu_count = metrics.gpu_devices.len();
+        metrics.gpu_data_stale = true;
+        metrics.nvidia_smi_failures = 1;
+        let checker = HealthChecker::new(gpu_settings(3));
+
+        let conditions = checker.evaluate(&metrics);
+        for condition_type in [
+            ConditionType::GpuMissing,
+            ConditionType::GpuDoubleBitEccError,
+        ] {
+            assert_eq!(
+                status(&conditions, condition_type),
+                (ConditionStatus::Unknown, "NvidiaSmiFailing")
+            );
+        }
+
+        // Below the threshold the failure is reported, but not as a problem
+        let failing = conditions
+            .iter()
+            .find(|c| c.condition_type == ConditionType::NvidiaSmiFailing)
+            .unwrap();
+        assert_eq!(failing.status, ConditionStatus::False);
+        assert_eq!(failing.reason, "NvidiaSmiFailedBelowThreshold");
+        assert_eq!(
+            failing.message,
+            "nvidia-smi failed 1 time(s) in a row, below the threshold of 3"
+        );
+
+        metrics.nvidia_smi_failures = 3;
+        assert_eq!(
+            status(&checker.evaluate(&metrics), ConditionType::NvidiaSmiFailing),
+            (ConditionStatus::True, "NvidiaSmiFailing")
+        );
+    }
+
+    #[tokio::test]
+    async fn check_reports_a_failing_nvidia_smi_on_the_first_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let nvidia_smi = nvidia_smi::fake(&dir, "echo 'NVIDIA-SMI has failed' >&2; exit 9");
+        let collect = || {
+            let nvidia_smi = nvidia_smi.clone();
+            move || NodeMetrics::collect_with_nvidia_smi(&nvidia_smi)
+        };
+
+        // The configured threshold cannot be reached by a single run
+        let settings = gpu_settings(3);
+        let (code, message) = check(&settings, None, collect()).await;
+        assert_eq!(code, ExitCode::from(1));
+        assert!(
+            message.starts_with("NvidiaSmiFailing: nvidia-smi failed"),
+            "{message}"
+        );
+
+        let (code, message) = check(&settings, Some(ConditionType::GpuMissing), collect()).await;
+        assert_eq!(code, ExitCode::from(2));
+        assert_eq!(
+            message,
+            "GPUMissing: nvidia-smi is failing, GPU data is unavailable"
+        );
+
+        let (code, message) = check(&settings, Some(ConditionType::DiskFull), collect()).await;
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(message, "DiskFull does not apply to this node");
+    }
+}
@@ -0,0 +1,186 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::collector::Snapshot;
+use crate::config::Settings;
+use crate::metrics::exposition::series_labels;
+
+/// One labelled series of a metric over time
+#[derive(Debug, Serialize)]
+pub struct Series {
+    pub labels: BTreeMap<String, String>,
+    /// `[timestamp_ms, value]` pairs, oldest first
+    pub points: Vec<(i64, f64)>,
+}
+
+/// Ring buffer of the most recent background collections, sized so that
+/// `retention_secs` worth of snapshots fit at `collect_interval_secs`
+pub struct History {
+    capacity: usize,
+    retention: Duration,
+    snapshots: RwLock<VecDeque<Arc<Snapshot>>>,
+}
+
+impl History {
+    pub fn new(settings: &Settings) -> Self {
+        let interval = settings.metrics.collect_interval_secs.max(1);
+        let retention_secs = settings.history.retention_secs;
+        let capacity = retention_secs.div_ceil(interval) as usize;
+
+        Self {
+            capacity,
+            retention: Duration::from_secs(retention_secs),
+            snapshots: RwLock::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn retention(&self) -> Duration {
+        self.retention
+    }
+
+    /// Record every snapshot broadcast by the collector
+    pub fn spawn(self: &Arc<Self>, mut snapshots: broadcast::Receiver<Arc<Snapshot>>) {
+        info!(
+            "Keeping {}s of history ({} snapshots)",
+            self.retention.as_secs(),
+            self.capacity
+        );
+
+        let history = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match snapshots.recv().await {
+                    Ok(snapshot) => history.record(snapshot),
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("History skipped {} snapshot(s)", skipped)
+                    }
+                    Err(RecvError::Closed) => return,
+                }
+            }
+        });
+    }
+
+    fn record(&self, snapshot: Arc<Snapshot>) {
+        let mut snapshots = self.snapshots.write().unwrap();
+        if snapshots.len() == self.capacity {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot);
+    }
+
+    /// Series of `metric` recorded at or after `since_ms`, with constant labels
+    /// included, ordered by label set
+    pub fn query(&self, metric: &str, since_ms: i64) -> Vec<Series> {
+        let snapshots = self.snapshots.read().unwrap();
+        let mut series: BTreeMap<BTreeMap<String, String>, Vec<(i64, f64)>> = BTreeMap::new();
+
+        for snapshot in snapshots.iter() {
+            let timestamp = snapshot.timestamp_ms();
+            if timestamp < since_ms {
+                continue;
+            }
+            let Some(family) = snapshot.families.iter().find(|f| f.name == metric) else {
+                continue;
+            };
+            for sample in &family.samples {
+                let labels = series_labels(sample, &snapshot.metrics.labels)
+                    .into_iter()
+                    .map(|(name, value)| (name.to_string(), value.to_string()))
+                    .collect();
+                series
+                    .entry(labels)
+                    .or_default()
+                    .push((timestamp, sample.value));
+            }
+        }
+
+        series
+            .into_iter()
+            .map(|(labels, points)| Series { labels, points })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::exposition::MetricFamily;
+    use std::time::UNIX_EPOCH;
+
+    fn history(retention_secs: u64, collect_interval_secs: u64) -> History {
+        let mut settings = Settings::default();
+        settings.history.retention_secs = retention_secs;
+        settings.metrics.collect_interval_secs = collect_interval_secs;
+        History::new(&settings)
+    }
+
+    /// Snapshot taken at `timestamp_ms` with one sample per GPU
+    fn snapshot(timestamp_ms: u64, values: &[f64]) -> Arc<Snapshot> {
+        let mut family = MetricFamily::gauge("hw_gpu_utilization_percent", "Utilization", "gpu");
+        for (gpu, value) in values.iter().enumerate() {
+            family.add_sample(vec![("gpu".to_string(), gpu.to_string())], *value);
+        }
+        let mut snapshot = Snapshot::with_families(vec![family], &[("cluster", "c1")]);
+        snapshot.timestamp = UNIX_EPOCH + Duration::from_millis(timestamp_ms);
+        Arc::new(snapshot)
+    }
+
+    fn labels(gpu: &str) -> BTreeMap<String, String> {
+        BTreeMap::from([
+            ("cluster".to_string(), "c1".to_string()),
+            ("gpu".to_string(), gpu.to_string()),
+        ])
+    }
+
+    #[test]
+    fn disabled_by_default() {
+        assert!(!History::new(&Settings::default()).is_enabled());
+        assert!(!history(0, 15).is_enabled());
+    }
+
+    #[test]
+    fn capacity_covers_the_retention() {
+        assert_eq!(history(3600, 15).capacity, 240);
+        assert_eq!(history(100, 30).capacity, 4);
+        assert_eq!(history(10, 0).capacity, 10);
+        assert_eq!(history(3600, 15).retention(), Duration::from_secs(3600));
+    }
+
+    #[test]
+    fn drops_snapshots_older_than_the_retention() {
+        let history = history(30, 15);
+        for (i, value) in [10.0, 20.0, 30.0].into_iter().enumerate() {
+            history.record(snapshot(1_000 + i as u64 * 15_000, &[value]));
+        }
+
+        let series = history.query("hw_gpu_utilization_percent", 0);
+        assert_eq!(series.len(), 1);
+        assert_eq!(series[0].points, [(16_000, 20.0), (31_000, 30.0)]);
+    }
+
+    #[test]
+    fn query_groups_series_by_labels_since_a_time() {
+        let history = history(60, 15);
+        history.record(snapshot(1_000, &[1.0, 2.0]));
+        history.record(snapshot(16_000, &[3.0, 4.0]));
+        history.record(snapshot(31_000, &[5.0]));
+
+        let series = history.query("hw_gpu_utilization_percent", 16_000);
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].labels, labels("0"));
+        assert_eq!(series[0].points, [(16_000, 3.0), (31_000, 5.0)]);
+        assert_eq!(series[1].labels, labels("1"));
+        assert_eq!(series[1].points, [(16_000, 4.0)]);
+
+        assert!(history.query("hw_gpu_temperature_celsius", 0).is_empty());
+    }
+}
@@ -0,0 +1,497 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::collector::Snapshot;
+use crate::metrics::NodeMetrics;
+
+/// Events kept for `/api/v1/events`, oldest dropped first
+const MAX_EVENTS: usize = 256;
+const STATE_FILE: &str = "inventory.json";
+
+/// Components a change is counted under in `hw_inventory_changes_total`
+pub const COMPONENTS: &[&str] = &["gpu", "memory", "kernel", "cpu"];
+
+/// Hardware identity compared between collections and across restarts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Fingerprint {
+    /// GPU UUID to name
+    gpus: BTreeMap<String, String>,
+    memory_total_bytes: u64,
+    kernel_version: String,
+    cpu_cores: usize,
+    cpu_threads: usize,
+}
+
+impl Fingerprint {
+    fn from_metrics(metrics: &NodeMetrics) -> Self {
+        Self {
+            gpus: metrics
+                .gpu_devices
+                .iter()
+                .map(|gpu| (gpu.uuid.clone(), gpu.name.clone()))
+                .collect(),
+            memory_total_bytes: metrics.memory_total_bytes,
+            kernel_version: metrics.kernel_version.clone(),
+            cpu_cores: metrics.cpu_cores,
+            cpu_threads: metrics.cpu_threads,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    GpuAdded,
+    GpuRemoved,
+    MemoryChanged,
+    KernelChanged,
+    CpuChanged,
+}
+
+impl ChangeKind {
+    pub fn component(&self) -> &'static str {
+        match self {
+            ChangeKind::GpuAdded | ChangeKind::GpuRemoved => "gpu",
+            ChangeKind::MemoryChanged => "memory",
+            ChangeKind::KernelChanged => "kernel",
+            ChangeKind::CpuChanged => "cpu",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+    /// RFC 3339 time of the collection that noticed the change
+    pub time: String,
+    pub node: String,
+    pub kind: ChangeKind,
+    pub component: &'static str,
+    pub message: String,
+    pub previous: Option<String>,
+    pub current: Option<String>,
+}
+
+/// Compares each background collection with the last known hardware and
+/// records an event for every difference, persisting the last known state
+/// under `state_dir` so changes across reboots are noticed too
+pub struct ChangeDetector {
+    state_path: Option<PathBuf>,
+    last: Mutex<Option<Fingerprint>>,
+    events: RwLock<VecDeque<ChangeEvent>>,
+    counts: RwLock<BTreeMap<&'static str, u64>>,
+}
+
+impl ChangeDetector {
+    pub fn new(state_dir: Option<&str>) -> Self {
+        let state_path = state_dir.map(|dir| Path::new(dir).join(STATE_FILE));
+        let last = state_path.as_deref().and_then(load_state);
+
+        Self {
+            state_path,
+            last: Mutex::new(last),
+            events: RwLock::new(VecDeque::new()),
+            counts: RwLock::new(COMPONENTS.iter().map(|c| (*c, 0)).collect()),
+        }
+    }
+
+    /// Recorded events, oldest first
+    pub fn events(&self) -> Vec<ChangeEvent> {
+        self.events.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Number of changes per component since the exporter started
+    pub fn counts(&self) -> Vec<(&'static str, u64)> {
+        self.counts
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(component, count)| (*component, *count))
+            .collect()
+    }
+
+    pub fn spawn(self: &Arc<Self>, mut snapshots: broadcast::Receiver<Arc<Snapshot>>) {
+        let detector = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let snapshot = match snapshots.recv().await {
+                    Ok(snapshot) => snapshot,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("Change detection skipped {} snapshot(s)", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+
+                let detector = detector.clone();
+                // Writes the state file, keep it off the runtime
+                if let Err(e) = tokio::task::spawn_blocking(move || {
+                    detector.observe(&snapshot.metrics, snapshot.timestamp)
+                })
+                .await
+                {
+                    warn!("Change detection failed: {}", e);
+                }
+            }
+        });
+    }
+
+    fn observe(&self, metrics: &NodeMetrics, time: SystemTime) {
+        let mut current = Fingerprint::from_metrics(metrics);
+        let mut last = self.last.lock().unwrap();
+
+        let Some(previous) = last.as_ref() else {
+            info!("Recorded hardware baseline for change detection");
+            self.save(&current);
+            *last = Some(current);
+            return;
+        };
+
+        // A failing nvidia-smi without cached data reports no GPUs, which is
+        // not the same as the GPUs being gone
+        if current.gpus.is_empty() && metrics.nvidia_smi_failures > 0 {
+            current.gpus = previous.gpus.clone();
+        }
+        if &current == previous {
+            return;
+        }
+
+        let events = diff(previous, &current);
+        let time = humantime::format_rfc3339_seconds(time).to_string();
+        {
+            let mut stored = self.events.write().unwrap();
+            let mut counts = self.counts.write().unwrap();
+            for (kind, message, before, after) in events {
+                let event = ChangeEvent {
+                    time: time.clone(),
+                    node: metrics.node.clone(),
+                    kind,
+                    component: kind.component(),
+                    message,
+                    previous: before,
+                    current: after,
+                };
+                warn!(
+                    component = event.component,
+                    kind = ?event.kind,
+                    previous = event.previous.as_deref().unwrap_or(""),
+                    current = event.current.as_deref().unwrap_or(""),
+                    "Hardware change: {}",
+                    event.message
+                );
+
+                *counts.entry(event.component).or_insert(0) += 1;
+                if stored.len() == MAX_EVENTS {
+                    stored.pop_front();
+                }
+                stored.push_back(event);
+            }
+        }
+
+        self.save(&current);
+        *last = Some(current);
+    }
+
+    fn save(&self, fingerprint: &Fingerprint) {
+        let Some(path) = &self.state_path else {
+            return;
+        };
+        if let Err(e) = write_state(path, fingerprint) {
+            warn!("Failed to write {}: {}", path.display(), e);
+        }
+    }
+}
+
+type Difference = (ChangeKind, String, Option<String>, Option<String>);
+
+fn diff(previous: &Fingerprint, current: &Fingerprint) -> Vec<Difference> {
+    let mut changes = Vec::new();
+
+    for (uuid, name) in &previous.gpus {
+        if !current.gpus.contains_key(uuid) {
+            changes.push((
+                ChangeKind::GpuRemoved,
+                format!("GPU {} ({}) disappeared", uuid, name),
+                Some(uuid.clone()),
+                None,
+            ));
+        }
+    }
+    for (uuid, name) in &current.gpus {
+        if !previous.gpus.contains_key(uuid) {
+            changes.push((
+                ChangeKind::GpuAdded,
+                format!("GPU {} ({}) appeared", uuid, name),
+                None,
+                Some(uuid.clone()),
+            ));
+        }
+    }
+
+    if previous.memory_total_bytes != current.memory_total_bytes {
+        changes.push((
+            ChangeKind::MemoryChanged,
+            format!(
+                "memory total changed from {} to {} bytes",
+                previous.memory_total_bytes, current.memory_total_bytes
+            ),
+            Some(previous.memory_total_bytes.to_string()),
+            Some(current.memory_total_bytes.to_string()),
+        ));
+    }
+
+    if previous.kernel_version != current.kernel_version {
+        changes.push((
+            ChangeKind::KernelChanged,
+            format!(
+                "kernel changed from {} to {}",
+                previous.kernel_version, current.kernel_version
+            ),
+            Some(previous.kernel_version.clone()),
+            Some(current.kernel_version.clone()),
+        ));
+    }
+
+    if previous.cpu_cores != current.cpu_cores || previous.cpu_threads != current.cpu_threads {
+        let describe = |f: &Fingerprint| format!("{} cores/{} threads", f.cpu_cores, f.cpu_threads);
+        changes.push((
+            ChangeKind::CpuChanged,
+            format!(
+                "CPU count changed from {} to {}",
+                describe(previous),
+                describe(current)
+            ),
+            Some(describe(previous)),
+            Some(describe(current)),
+        ));
+    }
+
+    changes
+}
+
+fn load_state(path: &Path) -> Option<Fingerprint> {
+    let content = match std::fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    match serde_json::from_slice(&content) {
+        Ok(fingerprint) => {
+            info!("Loaded last known hardware from {}", path.display());
+            Some(fingerprint)
+        }
+        Err(e) => {
+            warn!("Ignoring invalid state file {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// Write through a temporary file so a crash never leaves a truncated state
+fn write_state(path: &Path, fingerprint: &Fingerprint) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec_pretty(fingerprint)?)?;
+    std::fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::GpuInfo;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn metrics(gpus: &[(&str, &str)]) -> NodeMetrics {
+        let mut metrics = NodeMetrics::collect();
+        metrics.node = "gpu-a-1".to_string();
+        metrics.gpu_devices = gpus
+            .iter()
+            .map(|(uuid, name)| GpuInfo {
+                uuid: uuid.to_string(),
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        metrics.gpu_count = gpus.len();
+        metrics.nvidia_smi_failures = 0;
+        metrics.memory_total_bytes = 512 << 30;
+        metrics.kernel_version = "5.15.0-105-generic".to_string();
+        metrics.cpu_cores = 64;
+        metrics.cpu_threads = 128;
+        metrics
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    fn kinds(detector: &ChangeDetector) -> Vec<ChangeKind> {
+        detector.events().iter().map(|e| e.kind).collect()
+    }
+
+    const H100: (&str, &str) = ("GPU-1111", "NVIDIA H100 80GB HBM3");
+    const A100: (&str, &str) = ("GPU-2222", "NVIDIA A100-SXM4-80GB");
+
+    #[test]
+    fn first_collection_is_the_baseline() {
+        let dir = tempfile::tempdir().unwrap();
+        let detector = ChangeDetector::new(dir.path().to_str());
+
+        detector.observe(&metrics(&[H100]), at(1_700_000_000));
+
+        assert!(detector.events().is_empty());
+        assert!(detector.counts().iter().all(|(_, count)| *count == 0));
+        let saved = load_state(&dir.path().join(STATE_FILE)).unwrap();
+        assert_eq!(saved, Fingerprint::from_metrics(&metrics(&[H100])));
+        assert!(!dir.path().join("inventory.json.tmp").exists());
+    }
+
+    #[test]
+    fn records_added_and_removed_gpus() {
+        let detector = ChangeDetector::new(None);
+        detector.observe(&metrics(&[H100]), at(1_700_000_000));
+
+        detector.observe(&metrics(&[A100]), at(1_700_000_060));
+
+        let events = detector.events();
+        assert_eq!(
+            kinds(&detector),
+            [ChangeKind::GpuRemoved, ChangeKind::GpuAdded]
+        );
+        assert_eq!(events[0].time, "2023-11-14T22:14:20Z");
+        assert_eq!(events[0].node, "gpu-a-1");
+        assert_eq!(events[0].component, "gpu");
+        assert_eq!(
+            events[0].message,
+            "GPU GPU-1111 (NVIDIA H100 80GB HBM3) disappeared"
+        );
+        assert_eq!(events[0].previous.as_deref(), Some("GPU-1111"));
+        assert_eq!(events[0].current, None);
+        assert_eq!(events[1].previous, None);
+        assert_eq!(events[1].current.as_deref(), Some("GPU-2222"));
+        assert!(detector.counts().contains(&("gpu", 2)));
+
+        // Unchanged hardware records nothing
+        detector.observe(&metrics(&[A100]), at(1_700_000_120));
+        assert_eq!(detector.events().len(), 2);
+    }
+
+    #[test]
+    fn reports_memory_kernel_and_cpu_changes() {
+        let detector = ChangeDetector::new(None);
+        detector.observe(&metrics(&[H100]), at(1_700_000_000));
+
+        let mut changed = metrics(&[H100]);
+        changed.memory_total_bytes = 256 << 30;
+        changed.kernel_version = "6.8.0-40-generic".to_string();
+        changed.cpu_threads = 64;
+        detector.observe(&changed, at(1_700_000_060));
+
+        let events = detector.events();
+        assert_eq!(
+            kinds(&detector),
+            [
+                ChangeKind::MemoryChanged,
+                ChangeKind::KernelChanged,
+                ChangeKind::CpuChanged
+            ]
+        );
+        assert_eq!(events[1].previous.as_deref(), Some("5.15.0-105-generic"));
+        assert_eq!(events[1].current.as_deref(), Some("6.8.0-40-generic"));
+        assert_eq!(events[2].current.as_deref(), Some("64 cores/64 threads"));
+        assert_eq!(
+            detector.counts(),
+            [("cpu", 1), ("gpu", 0), ("kernel", 1), ("memory", 1)]
+        );
+    }
+
+    #[test]
+    fn keeps_the_previous_gpus_while_nvidia_smi_fails() {
+        let detector = ChangeDetector::new(None);
+        detector.observe(&metrics(&[H100, A100]), at(1_700_000_000));
+
+        let mut failing = metrics(&[]);
+        failing.nvidia_smi_failures = 3;
+        detector.observe(&failing, at(1_700_000_060));
+        assert!(detector.events().is_empty());
+
+        // Other components are still compared
+        failing.kernel_version = "6.8.0-40-generic".to_string();
+        detector.observe(&failing, at(1_700_000_120));
+        assert_eq!(kinds(&detector), [ChangeKind::KernelChanged]);
+
+        // GPUs missing while nvidia-smi works are removed
+        detector.observe(&metrics(&[H100]), at(1_700_000_180));
+        assert_eq!(
+            kinds(&detector),
+            [
+                ChangeKind::KernelChanged,
+                ChangeKind::GpuRemoved,
+                ChangeKind::KernelChanged
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_at_most_max_events() {
+        let detector = ChangeDetector::new(None);
+        let mut metrics = metrics(&[]);
+        detector.observe(&metrics, at(1_700_000_000));
+
+        for i in 1..=MAX_EVENTS as u64 + 10 {
+            metrics.memory_total_bytes = i << 30;
+            detector.observe(&metrics, at(1_700_000_000 + i));
+        }
+
+        let events = detector.events();
+        assert_eq!(events.len(), MAX_EVENTS);
+        assert_eq!(
+            events[0].current.as_deref(),
+            Some((11u64 << 30).to_string().as_str())
+        );
+        assert_eq!(
+            events.last().unwrap().current.as_deref(),
+            Some(((MAX_EVENTS as u64 + 10) << 30).to_string().as_str())
+        );
+        // The counter keeps counting evicted events
+        assert!(detector
+            .counts()
+            .contains(&("memory", MAX_EVENTS as u64 + 10)));
+    }
+
+    #[test]
+    fn compares_with_the_state_from_before_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let state_dir = dir.path().join("state");
+        ChangeDetector::new(state_dir.to_str()).observe(&metrics(&[H100, A100]), at(1_700_000_000));
+
+        let restarted = ChangeDetector::new(state_dir.to_str());
+        restarted.observe(&metrics(&[H100]), at(1_700_000_600));
+
+        assert_eq!(kinds(&restarted), [ChangeKind::GpuRemoved]);
+        let saved = load_state(&state_dir.join(STATE_FILE)).unwrap();
+        assert_eq!(saved.gpus.len(), 1);
+    }
+
+    #[test]
+    fn ignores_an_invalid_state_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join(STATE_FILE), "{\"gpus\":").unwrap();
+
+        let detector = ChangeDetector::new(dir.path().to_str());
+        detector.observe(&metrics(&[H100]), at(1_700_000_000));
+
+        assert!(detector.events().is_empty());
+        assert!(load_state(&dir.path().join(STATE_FILE)).is_some());
+    }
+}
@@ -0,0 +1,443 @@
+use serde::Serialize;
+use std::fs;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::path::Path;
+
+use super::{read_trimmed, sysfs_root};
+
+/// `IFNAMSIZ` including the terminating NUL
+const IFNAMSIZ: usize = 16;
+const ETHTOOL_GDRVINFO: u32 = 0x03;
+const ETHTOOL_STRING_LEN: usize = 32;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PciDevice {
+    /// Domain, bus, device and function, e.g. `0000:3b:00.0`
+    pub address: String,
+    pub vendor_id: Option<String>,
+    pub device_id: Option<String>,
+    pub subsystem_vendor_id: Option<String>,
+    pub subsystem_device_id: Option<String>,
+    pub class: Option<String>,
+    pub class_name: &'static str,
+    pub driver: Option<String>,
+    /// Absent on single node systems, where the kernel reports -1
+    pub numa_node: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Nic {
+    pub name: String,
+    pub mac: Option<String>,
+    pub driver: Option<String>,
+    /// Address of the backing device, the PCI address for PCI NICs
+    pub bus_info: Option<String>,
+    pub firmware_version: Option<String>,
+    pub speed_mbps: Option<u32>,
+    pub mtu: Option<u32>,
+    pub operstate: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Disk {
+    pub name: String,
+    pub vendor: Option<String>,
+    pub model: Option<String>,
+    pub serial: Option<String>,
+    pub firmware_version: Option<String>,
+    pub size_bytes: u64,
+    pub rotational: Option<bool>,
+}
+
+/// Name of the directory a sysfs symlink such as `driver` points to
+fn link_name(path: &Path) -> Option<String> {
+    let target = fs::read_link(path).ok()?;
+    Some(target.file_name()?.to_string_lossy().into_owned())
+}
+
+fn sorted_entries(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+pub fn read_pci_devices() -> Vec<PciDevice> {
+    read_pci_devices_in(Path::new(sysfs_root()))
+}
+
+fn read_pci_devices_in(sysfs: &Path) -> Vec<PciDevice> {
+    let root = sysfs.join("bus/pci/devices");
+
+    sorted_entries(&root)
+        .into_iter()
+        .map(|address| {
+            let dir = root.join(&address);
+            let class = read_trimmed(&dir.join("class"));
+            let class_name = class
+                .as_deref()
+                .and_then(|c| u32::from_str_radix(c.trim_start_matches("0x"), 16).ok())
+                .map_or("unknown", |c| pci_class_name((c >> 16) as u8));
+
+            PciDevice {
+                vendor_id: read_trimmed(&dir.join("vendor")),
+                device_id: read_trimmed(&dir.join("device")),
+                subsystem_vendor_id: read_trimmed(&dir.join("subsystem_vendor")),
+                subsystem_device_id: read_trimmed(&dir.join("subsystem_device")),
+                class,
+                class_name,
+                driver: link_name(&dir.join("driver")),
+                numa_node: read_trimmed(&dir.join("numa_node")).and_then(|n| n.parse().ok()),
+                address,
+            }
+        })
+        .collect()
+}
+
+fn pci_class_name(class: u8) -> &'static str {
+    match class {
+        0x00 => "unclassified",
+        0x01 => "storage",
+        0x02 => "network",
+        0x03 => "display",
+        0x04 => "multimedia",
+        0x05 => "memory",
+        0x06 => "bridge",
+        0x07 => "communication",
+        0x08 => "system",
+        0x09 => "input",
+        0x0C => "serial_bus",
+        0x0D => "wireless",
+        0x12 => "accelerator",
+        _ => "other",
+    }
+}
+
+/// Physical network interfaces, virtual ones (bridges, veth, bonds) have no
+/// backing device and are skipped
+pub fn read_nics() -> Vec<Nic> {
+    read_nics_in(Path::new(sysfs_root()))
+}
+
+fn read_nics_in(sysfs: &Path) -> Vec<Nic> {
+    let root = sysfs.join("class/net");
+
+    sorted_entries(&root)
+        .into_iter()
+        .filter(|name| root.join(name).join("device").exists())
+        .map(|name| {
+            let dir = root.join(&name);
+            let bus_info = link_name(&dir.join("device"));
+
+            Nic {
+                mac: read_trimmed(&dir.join("address")),
+                driver: link_name(&dir.join("device/driver")),
+                firmware_version: firmware_version(&name, bus_info.as_deref()),
+                // Reading speed fails while the link is down
+                speed_mbps: read_trimmed(&dir.join("speed")).and_then(|s| s.parse().ok()),
+                mtu: read_trimmed(&dir.join("mtu")).and_then(|s| s.parse().ok()),
+                operstate: read_trimmed(&dir.join("operstate")),
+                bus_info,
+                name,
+            }
+        })
+        .collect()
+}
+
+/// `struct ethtool_drvinfo` from `linux/ethtool.h`
+#[repr(C)]
+struct EthtoolDrvinfo {
+    cmd: u32,
+    driver: [u8; ETHTOOL_STRING_LEN],
+    version: [u8; ETHTOOL_STRING_LEN],
+    fw_version: [u8; ETHTOOL_STRING_LEN],
+    bus_info: [u8; ETHTOOL_STRING_LEN],
+    erom_version: [u8; ETHTOOL_STRING_LEN],
+    reserved2: [u8; 12],
+    n_priv_flags: u32,
+    n_stats: u32,
+    testinfo_len: u32,
+    eedump_len: u32,
+    regdump_len: u32,
+}
+
+/// `struct ifreq` with the `ifr_data` member of its union
+#[repr(C)]
+struct IfReq {
+    name: [u8; IFNAMSIZ],
+    data: *mut libc::c_void,
+    _pad: [u8; 16],
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).trim().to_string()
+}
+
+/// Firmware version through the `ETHTOOL_GDRVINFO` ioctl
+/// The ioctl resolves interface names in the exporter's network namespace,
+/// so the result is only used when its bus info matches the sysfs device;
+/// without `hostNetwork` a pod interface may share the host interface name
+fn firmware_version(name: &str, bus_info: Option<&str>) -> Option<String> {
+    let bus_info = bus_info?;
+    if name.len() >= IFNAMSIZ {
+        return None;
+    }
+
+    // SAFETY: plain socket creation, the descriptor is owned right away
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return None;
+    }
+    // SAFETY: `fd` is a valid descriptor nothing else owns
+    let socket = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut info = EthtoolDrvinfo {
+        cmd: ETHTOOL_GDRVINFO,
+        driver: [0; ETHTOOL_STRING_LEN],
+        version: [0; ETHTOOL_STRING_LEN],
+        fw_version: [0; ETHTOOL_STRING_LEN],
+        bus_info: [0; ETHTOOL_STRING_LEN],
+        erom_version: [0; ETHTOOL_STRING_LEN],
+        reserved2: [0; 12],
+        n_priv_flags: 0,
+        n_stats: 0,
+        testinfo_len: 0,
+        eedump_len: 0,
+        regdump_len: 0,
+    };
+    let mut request = IfReq {
+        name: [0; IFNAMSIZ],
+        data: &mut info as *mut EthtoolDrvinfo as *mut libc::c_void,
+        _pad: [0; 16],
+    };
+    request.name[..name.len()].copy_from_slice(name.as_bytes());
+
+    // SAFETY: `request` points at a live, correctly sized ethtool_drvinfo
+    let result = unsafe {
+        libc::ioctl(
+            socket.as_raw_fd(),
+            libc::SIOCETHTOOL as libc::Ioctl,
+            &mut request,
+        )
+    };
+    if result < 0 || c_string(&info.bus_info) != bus_info {
+        return None;
+    }
+
+    let version = c_string(&info.fw_version);
+    (!version.is_empty() && version != "N/A").then_some(version)
+}
+
+/// Block devices backed by hardware, skipping loop, ram, zram, device
+/// mapper and software RAID devices
+pub fn read_disks() -> Vec<Disk> {
+    read_disks_in(Path::new(sysfs_root()))
+}
+
+fn read_disks_in(sysfs: &Path) -> Vec<Disk> {
+    let root = sysfs.join("block");
+
+    sorted_entries(&root)
+        .into_iter()
+        .filter(|name| root.join(name).join("device").exists())
+        .map(|name| {
+            let dir = root.join(&name);
+            let device = dir.join("device");
+
+            Disk {
+                vendor: read_trimmed(&device.join("vendor")),
+                model: read_trimmed(&device.join("model")),
+                serial: read_trimmed(&device.join("serial"))
+                    .or_else(|| read_trimmed(&dir.join("serial")))
+                    .or_else(|| scsi_serial(&device)),
+                firmware_version: read_trimmed(&device.join("firmware_rev"))
+                    .or_else(|| read_trimmed(&device.join("rev"))),
+                // Always counted in 512 byte sectors
+                size_bytes: read_trimmed(&dir.join("size"))
+                    .and_then(|s| s.parse::<u64>().ok())
+                    .unwrap_or(0)
+                    * 512,
+                rotational: read_trimmed(&dir.join("queue/rotational")).map(|r| r == "1"),
+                name,
+            }
+        })
+        .collect()
+}
+
+/// Unit serial number from the SCSI VPD page 0x80, which follows a 4 byte
+/// header
+fn scsi_serial(device: &Path) -> Option<String> {
+    let page = fs::read(device.join("vpd_pg80")).ok()?;
+    let serial = c_string(page.get(4..)?);
+    (!serial.is_empty()).then_some(serial)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+    use std::path::PathBuf;
+
+    fn write(root: &Path, relative: &str, contents: &[u8]) -> PathBuf {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn link(root: &Path, relative: &str, target: &str) {
+        let path = root.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        symlink(target, path).unwrap();
+    }
+
+    #[test]
+    fn reads_pci_devices_in_address_order() {
+        let sysfs = tempfile::tempdir().unwrap();
+        let root = sysfs.path();
+        write(root, "bus/pci/devices/0000:3b:00.0/class", b"0x030200\n");
+        write(root, "bus/pci/devices/0000:3b:00.0/vendor", b"0x10de\n");
+        write(root, "bus/pci/devices/0000:3b:00.0/device", b"0x20b2\n");
+        write(
+            root,
+            "bus/pci/devices/0000:3b:00.0/subsystem_vendor",
+            b"0x10de\n",
+        );
+        write(
+            root,
+            "bus/pci/devices/0000:3b:00.0/subsystem_device",
+            b"0x1463\n",
+        );
+        write(root, "bus/pci/devices/0000:3b:00.0/numa_node", b"1\n");
+        link(
+            root,
+            "bus/pci/devices/0000:3b:00.0/driver",
+            "../../../bus/pci/drivers/nvidia",
+        );
+        write(root, "bus/pci/devices/0000:00:00.0/class", b"0x060000\n");
+        write(root, "bus/pci/devices/0000:00:00.0/numa_node", b"-1\n");
+
+        let devices = read_pci_devices_in(root);
+
+        assert_eq!(devices.len(), 2);
+        let bridge = &devices[0];
+        assert_eq!(bridge.address, "0000:00:00.0");
+        assert_eq!(bridge.class_name, "bridge");
+        assert_eq!(bridge.driver, None);
+        assert_eq!(bridge.numa_node, None);
+        assert_eq!(bridge.vendor_id, None);
+
+        let gpu = &devices[1];
+        assert_eq!(gpu.address, "0000:3b:00.0");
+        assert_eq!(gpu.class.as_deref(), Some("0x030200"));
+        assert_eq!(gpu.class_name, "display");
+        assert_eq!(gpu.vendor_id.as_deref(), Some("0x10de"));
+        assert_eq!(gpu.device_id.as_deref(), Some("0x20b2"));
+        assert_eq!(gpu.subsystem_vendor_id.as_deref(), Some("0x10de"));
+        assert_eq!(gpu.subsystem_device_id.as_deref(), Some("0x1463"));
+        assert_eq!(gpu.driver.as_deref(), Some("nvidia"));
+        assert_eq!(gpu.numa_node, Some(1));
+    }
+
+    #[test]
+    fn reads_only_nics_with_a_backing_device() {
+        let sysfs = tempfile::tempdir().unwrap();
+        let root = sysfs.path();
+        write(root, "devices/pci0000:00/0000:5e:00.0/vendor", b"0x15b3\n");
+        link(
+            root,
+            "devices/pci0000:00/0000:5e:00.0/driver",
+            "../../../bus/pci/drivers/mlx5_core",
+        );
+        link(
+            root,
+            "class/net/enp94s0f0/device",
+            "../../../devices/pci0000:00/0000:5e:00.0",
+        );
+        write(root, "class/net/enp94s0f0/address", b"b8:59:9f:00:00:01\n");
+        write(root, "class/net/enp94s0f0/speed", b"100000\n");
+        write(root, "class/net/enp94s0f0/mtu", b"9000\n");
+        write(root, "class/net/enp94s0f0/operstate", b"up\n");
+        write(root, "class/net/lo/address", b"00:00:00:00:00:00\n");
+        write(root, "class/net/cni0/address", b"0a:58:0a:f4:00:01\n");
+
+        let nics = read_nics_in(root);
+
+        assert_eq!(nics.len(), 1);
+        let nic = &nics[0];
+        assert_eq!(nic.name, "enp94s0f0");
+        assert_eq!(nic.mac.as_deref(), Some("b8:59:9f:00:00:01"));
+        assert_eq!(nic.driver.as_deref(), Some("mlx5_core"));
+        assert_eq!(nic.bus_info.as_deref(), Some("0000:5e:00.0"));
+        assert_eq!(nic.speed_mbps, Some(100000));
+        assert_eq!(nic.mtu, Some(9000));
+        assert_eq!(nic.operstate.as_deref(), Some("up"));
+        // No such interface in this namespace, so the ioctl finds nothing
+        assert_eq!(nic.firmware_version, None);
+    }
+
+    #[test]
+    fn reads_disks_with_serial_fallbacks() {
+        let sysfs = tempfile::tempdir().unwrap();
+        let root = sysfs.path();
+        write(
+            root,
+            "block/nvme0n1/device/model",
+            b"SAMSUNG MZQL23T8HCLS-00A07 \n",
+        );
+        write(root, "block/nvme0n1/device/serial", b"  S64HNE0R000001  \n");
+        write(root, "block/nvme0n1/device/firmware_rev", b"GDC5602Q\n");
+        write(root, "block/nvme0n1/size", b"7501476528\n");
+        write(root, "block/nvme0n1/queue/rotational", b"0\n");
+
+        write(root, "block/sda/device/vendor", b"ATA     \n");
+        write(root, "block/sda/device/model", b"ST8000NM017B\n");
+        write(root, "block/sda/device/rev", b"SN02\n");
+        write(
+            root,
+            "block/sda/device/vpd_pg80",
+            b"\x00\x80\x00\x0cZA1B2C3D    ",
+        );
+        write(root, "block/sda/size", b"2048\n");
+        write(root, "block/sda/queue/rotational", b"1\n");
+
+        write(root, "block/sdb/device/model", b"Virtual disk\n");
+        write(root, "block/sdb/serial", b"6000c29f\n");
+
+        write(root, "block/loop0/size", b"2048\n");
+        write(root, "block/dm-0/size", b"2048\n");
+
+        let disks = read_disks_in(root);
+
+        let names: Vec<&str> = disks.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["nvme0n1", "sda", "sdb"]);
+
+        let nvme = &disks[0];
+        assert_eq!(nvme.vendor, None);
+        assert_eq!(nvme.model.as_deref(), Some("SAMSUNG MZQL23T8HCLS-00A07"));
+        assert_eq!(nvme.serial.as_deref(), Some("S64HNE0R000001"));
+        assert_eq!(nvme.firmware_version.as_deref(), Some("GDC5602Q"));
+        assert_eq!(nvme.size_bytes, 7_501_476_528 * 512);
+        assert_eq!(nvme.rotational, Some(false));
+
+        let hdd = &disks[1];
+        assert_eq!(hdd.vendor.as_deref(), Some("ATA"));
+        assert_eq!(hdd.serial.as_deref(), Some("ZA1B2C3D"));
+        assert_eq!(hdd.firmware_version.as_deref(), Some("SN02"));
+        assert_eq!(hdd.size_bytes, 1024 * 1024);
+        assert_eq!(hdd.rotational, Some(true));
+
+        let virtual_disk = &disks[2];
+        assert_eq!(virtual_disk.serial.as_deref(), Some("6000c29f"));
+        assert_eq!(virtual_disk.firmware_version, None);
+        assert_eq!(virtual_disk.size_bytes, 0);
+        assert_eq!(virtual_disk.rotational, None);
+    }
+}
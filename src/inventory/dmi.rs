@@ -0,0 +1,351 @@
+use serde::Serialize;
+use std::sync::OnceLock;
+
+use super::{read_trimmed, sysfs_path};
+
+/// SMBIOS structure types
+const SMBIOS_MEMORY_DEVICE: u8 = 17;
+const SMBIOS_END_OF_TABLE: u8 = 127;
+
+/// System, board and BIOS identification from `/sys/class/dmi/id`
+/// Serial numbers are only readable as root
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DmiInfo {
+    pub system_vendor: Option<String>,
+    pub product_name: Option<String>,
+    pub product_serial: Option<String>,
+    pub product_uuid: Option<String>,
+    pub board_vendor: Option<String>,
+    pub board_name: Option<String>,
+    pub board_serial: Option<String>,
+    pub bios_vendor: Option<String>,
+    pub bios_version: Option<String>,
+    pub bios_date: Option<String>,
+}
+
+impl DmiInfo {
+    fn read() -> Self {
+        let dir = sysfs_path("class/dmi/id");
+        let field = |name: &str| read_trimmed(&dir.join(name));
+
+        Self {
+            system_vendor: field("sys_vendor"),
+            product_name: field("product_name"),
+            product_serial: field("product_serial"),
+            product_uuid: field("product_uuid"),
+            board_vendor: field("board_vendor"),
+            board_name: field("board_name"),
+            board_serial: field("board_serial"),
+            bios_vendor: field("bios_vendor"),
+            bios_version: field("bios_version"),
+            bios_date: field("bios_date"),
+        }
+    }
+
+    /// Fields as `hw_dmi_info` labels, unavailable ones left out
+    pub fn labels(&self) -> Vec<(String, String)> {
+        [
+            ("system_vendor", &self.system_vendor),
+            ("product_name", &self.product_name),
+            ("product_serial", &self.product_serial),
+            ("board_vendor", &self.board_vendor),
+            ("board_name", &self.board_name),
+            ("bios_vendor", &self.bios_vendor),
+            ("bios_version", &self.bios_version),
+            ("bios_date", &self.bios_date),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+        .collect()
+    }
+}
+
+/// DMI data only changes with a reboot or firmware update, read it once
+pub fn dmi_info() -> &'static DmiInfo {
+    static DMI: OnceLock<DmiInfo> = OnceLock::new();
+    DMI.get_or_init(DmiInfo::read)
+}
+
+/// Installed memory module from an SMBIOS type 17 structure
+#[derive(Debug, Clone, Serialize)]
+pub struct Dimm {
+    pub locator: Option<String>,
+    pub bank: Option<String>,
+    pub size_bytes: Option<u64>,
+    pub memory_type: &'static str,
+    pub speed_mts: Option<u16>,
+    pub configured_speed_mts: Option<u16>,
+    pub manufacturer: Option<String>,
+    pub serial: Option<String>,
+    pub part_number: Option<String>,
+}
+
+/// Memory modules from the raw SMBIOS table, empty when the table is not
+/// readable (it requires root) or the firmware does not describe them
+pub fn read_dimms() -> Vec<Dimm> {
+    match std::fs::read(sysfs_path("firmware/dmi/tables/DMI")) {
+        Ok(table) => parse_dimms(&table),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// One SMBIOS structure: the formatted area and the strings that follow it
+struct Structure<'a> {
+    data: &'a [u8],
+    strings: Vec<&'a [u8]>,
+}
+
+impl Structure<'_> {
+    fn byte(&self, offset: usize) -> Option<u8> {
+        self.data.get(offset).copied()
+    }
+
+    fn word(&self, offset: usize) -> Option<u16> {
+        let bytes = self.data.get(offset..offset + 2)?;
+        Some(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn dword(&self, offset: usize) -> Option<u32> {
+        let bytes = self.data.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// String referenced by the 1-based index stored at `offset`
+    fn string(&self, offset: usize) -> Option<String> {
+        let index = self.byte(offset)? as usize;
+        let raw = self.strings.get(index.checked_sub(1)?)?;
+        let value = String::from_utf8_lossy(raw).trim().to_string();
+        (!value.is_empty()).then_some(value)
+    }
+}
+
+fn structures(table: &[u8]) -> Vec<Structure<'_>> {
+    let mut structures = Vec::new();
+    let mut offset = 0;
+
+    while offset + 4 <= table.len() {
+        let kind = table[offset];
+        let length = table[offset + 1] as usize;
+        if length < 4 || offset + length > table.len() {
+            break;
+        }
+        let data = &table[offset..offset + length];
+
+        // The string set ends with two NUL bytes
+        let mut end = offset + length;
+        let mut strings = Vec::new();
+        let mut start = end;
+        while end < table.len() {
+            if table[end] == 0 {
+                if end == start {
+                    break;
+                }
+                strings.push(&table[start..end]);
+                start = end + 1;
+            }
+            end += 1;
+        }
+        // Structures without strings still carry the double NUL
+        let next = if strings.is_empty() { end + 2 } else { end + 1 };
+
+        if kind == SMBIOS_END_OF_TABLE {
+            break;
+        }
+        structures.push(Structure { data, strings });
+        offset = next;
+    }
+
+    structures
+}
+
+fn parse_dimms(table: &[u8]) -> Vec<Dimm> {
+    structures(table)
+        .into_iter()
+        .filter(|s| s.byte(0) == Some(SMBIOS_MEMORY_DEVICE))
+        .filter_map(|s| {
+            let size = s.word(0x0C)?;
+            // Empty slot
+            if size == 0 {
+                return None;
+            }
+            let size_bytes = match size {
+                0xFFFF => None,
+                0x7FFF => s
+                    .dword(0x1C)
+                    .map(|mb| (mb & 0x7FFF_FFFF) as u64 * 1024 * 1024),
+                size if size & 0x8000 != 0 => Some((size & 0x7FFF) as u64 * 1024),
+                size => Some(size as u64 * 1024 * 1024),
+            };
+            let speed = |offset| s.word(offset).filter(|&v| v != 0 && v != 0xFFFF);
+
+            Some(Dimm {
+                locator: s.string(0x10),
+                bank: s.string(0x11),
+                size_bytes,
+                memory_type: memory_type_name(s.byte(0x12).unwrap_or(0)),
+                speed_mts: speed(0x15),
+                configured_speed_mts: speed(0x20),
+                manufacturer: s.string(0x17),
+                serial: s.string(0x18),
+                part_number: s.string(0x1A),
+            })
+        })
+        .collect()
+}
+
+fn memory_type_name(code: u8) -> &'static str {
+    match code {
+        0x0F => "SDRAM",
+        0x12 => "DDR",
+        0x13 => "DDR2",
+        0x18 => "DDR3",
+        0x1A => "DDR4",
+        0x1B => "LPDDR",
+        0x1C => "LPDDR2",
+        0x1D => "LPDDR3",
+        0x1E => "LPDDR4",
+        0x1F => "Logical non-volatile device",
+        0x20 => "HBM",
+        0x21 => "HBM2",
+        0x22 => "DDR5",
+        0x23 => "LPDDR5",
+        0x24 => "HBM3",
+        0x01 => "Other",
+        _ => "Unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MEMORY_DEVICE_LENGTH: usize = 0x22;
+
+    /// SMBIOS type 17 structure with strings 1 to 5 as locator, bank,
+    /// manufacturer, serial and part number
+    fn memory_device(size: u16, extended_size: u32, strings: &[&str]) -> Vec<u8> {
+        let mut data = vec![0u8; MEMORY_DEVICE_LENGTH];
+        data[0] = SMBIOS_MEMORY_DEVICE;
+        data[1] = MEMORY_DEVICE_LENGTH as u8;
+        data[0x0C..0x0E].copy_from_slice(&size.to_le_bytes());
+        data[0x10] = 1;
+        data[0x11] = 2;
+        data[0x12] = 0x1A;
+        data[0x15..0x17].copy_from_slice(&3200u16.to_le_bytes());
+        data[0x17] = 3;
+        data[0x18] = 4;
+        data[0x1A] = 5;
+        data[0x1C..0x20].copy_from_slice(&extended_size.to_le_bytes());
+        data[0x20..0x22].copy_from_slice(&2933u16.to_le_bytes());
+        append_strings(&mut data, strings);
+        data
+    }
+
+    fn append_strings(data: &mut Vec<u8>, strings: &[&str]) {
+        for string in strings {
+            data.extend_from_slice(string.as_bytes());
+            data.push(0);
+        }
+        if strings.is_empty() {
+            data.push(0);
+        }
+        data.push(0);
+    }
+
+    /// Structure of another type whose strings must be skipped
+    fn system_information(strings: &[&str]) -> Vec<u8> {
+        let mut data = vec![1, 8, 0, 0, 1, 2, 0, 0];
+        append_strings(&mut data, strings);
+        data
+    }
+
+    fn end_of_table() -> Vec<u8> {
+        vec![SMBIOS_END_OF_TABLE, 4, 0, 0, 0, 0]
+    }
+
+    const STRINGS: [&str; 5] = [
+        "DIMM_A1",
+        "P0 CHANNEL A",
+        "Samsung",
+        "03A1B2C3",
+        "M393A2K43DB3-CWE    ",
+    ];
+
+    #[test]
+    fn parses_a_memory_device_after_other_structures() {
+        let table = [
+            system_information(&["Dell Inc.", "PowerEdge R750"]),
+            system_information(&[]),
+            memory_device(16 * 1024, 0, &STRINGS),
+            end_of_table(),
+        ]
+        .concat();
+
+        let dimms = parse_dimms(&table);
+
+        assert_eq!(dimms.len(), 1);
+        let dimm = &dimms[0];
+        assert_eq!(dimm.locator.as_deref(), Some("DIMM_A1"));
+        assert_eq!(dimm.bank.as_deref(), Some("P0 CHANNEL A"));
+        assert_eq!(dimm.size_bytes, Some(16 << 30));
+        assert_eq!(dimm.memory_type, "DDR4");
+        assert_eq!(dimm.speed_mts, Some(3200));
+        assert_eq!(dimm.configured_speed_mts, Some(2933));
+        assert_eq!(dimm.manufacturer.as_deref(), Some("Samsung"));
+        assert_eq!(dimm.serial.as_deref(), Some("03A1B2C3"));
+        assert_eq!(dimm.part_number.as_deref(), Some("M393A2K43DB3-CWE"));
+    }
+
+    #[test]
+    fn reads_the_extended_size_in_megabytes() {
+        // Bit 31 of the extended size is reserved
+        let table = [
+            memory_device(0x7FFF, 0x8000_0000 | (256 * 1024), &STRINGS),
+            end_of_table(),
+        ]
+        .concat();
+
+        assert_eq!(parse_dimms(&table)[0].size_bytes, Some(256 << 30));
+    }
+
+    #[test]
+    fn reads_kilobyte_granularity_sizes() {
+        let table = [memory_device(0x8000 | 512, 0, &STRINGS), end_of_table()].concat();
+
+        assert_eq!(parse_dimms(&table)[0].size_bytes, Some(512 * 1024));
+    }
+
+    #[test]
+    fn skips_empty_slots_and_keeps_unknown_sizes() {
+        let mut unknown = memory_device(0xFFFF, 0, &["DIMM_B1"]);
+        // Unset string references and speeds
+        unknown[0x11] = 0;
+        unknown[0x15..0x17].copy_from_slice(&0xFFFFu16.to_le_bytes());
+        let table = [memory_device(0, 0, &["DIMM_A2"]), unknown, end_of_table()].concat();
+
+        let dimms = parse_dimms(&table);
+
+        assert_eq!(dimms.len(), 1);
+        let dimm = &dimms[0];
+        assert_eq!(dimm.locator.as_deref(), Some("DIMM_B1"));
+        assert_eq!(dimm.size_bytes, None);
+        assert_eq!(dimm.bank, None);
+        assert_eq!(dimm.manufacturer, None);
+        assert_eq!(dimm.speed_mts, None);
+    }
+
+    #[test]
+    fn stops_at_the_end_of_table_and_truncated_structures() {
+        let after_end = [
+            memory_device(8 * 1024, 0, &STRINGS),
+            end_of_table(),
+            memory_device(8 * 1024, 0, &STRINGS),
+        ]
+        .concat();
+        assert_eq!(parse_dimms(&after_end).len(), 1);
+
+        let mut truncated = memory_device(8 * 1024, 0, &STRINGS);
+        truncated.extend_from_slice(&memory_device(8 * 1024, 0, &STRINGS)[..0x10]);
+        assert_eq!(parse_dimms(&truncated).len(), 1);
+    }
+}
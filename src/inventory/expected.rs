@@ -0,0 +1,446 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::warn;
+
+use crate::config::{ExpectedHardwareSettings, InventorySettings, KubernetesSettings};
+use crate::kube::ApiClient;
+use crate::metrics::filter::Pattern;
+use crate::metrics::{self, NodeMetrics};
+
+/// Node labels rarely change, refresh them at this interval
+const LABEL_REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+const LABEL_RETRY_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Comparison of one expected hardware property with the collected value
+#[derive(Debug, Clone, Serialize)]
+pub struct InventoryCheck {
+    pub component: &'static str,
+    pub expected: String,
+    pub actual: String,
+    #[serde(rename = "match")]
+    pub matches: bool,
+}
+
+/// Result of checking a node against the expected hardware
+#[derive(Debug, Serialize)]
+pub struct InventoryReport {
+    pub node: String,
+    /// Name of the applied entry, none when no entry matches the node
+    pub rule: Option<String>,
+    /// Every checked property matches
+    pub ok: bool,
+    pub mismatches: Vec<InventoryCheck>,
+    pub checks: Vec<InventoryCheck>,
+}
+
+struct ExpectedHardware {
+    name: String,
+    nodes: Vec<Pattern>,
+    settings: ExpectedHardwareSettings,
+}
+
+/// Checks collected metrics against the `[[inventory.expected]]` entries
+pub struct InventoryChecker {
+    expected: Vec<ExpectedHardware>,
+    kubernetes: KubernetesSettings,
+    /// Labels of this node, unknown until loaded from the API server
+    node_labels: RwLock<Option<BTreeMap<String, String>>>,
+}
+
+impl InventoryChecker {
+    pub fn from_settings(
+        settings: &InventorySettings,
+        kubernetes: &KubernetesSettings,
+    ) -> anyhow::Result<Self> {
+        let expected = settings
+            .expected
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let nodes = entry
+                    .nodes
+                    .iter()
+                    .map(|p| Pattern::parse(p))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(anyhow::Error::msg)?;
+                Ok(ExpectedHardware {
+                    name: entry
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("expected[{}]", index)),
+                    nodes,
+                    settings: entry.clone(),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            expected,
+            kubernetes: kubernetes.clone(),
+            node_labels: RwLock::new(None),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.expected.is_empty()
+    }
+
+    fn needs_node_labels(&self) -> bool {
+        self.expected
+            .iter()
+            .any(|entry| !entry.settings.node_selector.is_empty())
+    }
+
+    /// Load the node labels once, for one-shot runs
+    pub async fn load_node_labels(&self) -> anyhow::Result<()> {
+        if !self.needs_node_labels() {
+            return Ok(());
+        }
+        let client = ApiClient::new(&self.kubernetes)?;
+        let labels = client.node_labels(&metrics::node_name()).await?;
+        *self.node_labels.write().unwrap() = Some(labels);
+        Ok(())
+    }
+
+    /// Keep the node labels up to date when an entry uses `node_selector`
+    pub fn spawn(self: &Arc<Self>) -> anyhow::Result<()> {
+        if !self.needs_node_labels() {
+            return Ok(());
+        }
+
+        let client = ApiClient::new(&self.kubernetes)?;
+        let node = metrics::node_name();
+        let checker = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let delay = match client.node_labels(&node).await {
+                    Ok(labels) => {
+                        *checker.node_labels.write().unwrap() = Some(labels);
+                        LABEL_REFRESH_INTERVAL
+                    }
+                    Err(e) => {
+                        warn!("Failed to load node labels for inventory checks: {}", e);
+                        LABEL_RETRY_INTERVAL
+                    }
+                };
+                tokio::time::sleep(delay).await;
+            }
+        });
+
+        Ok(())
+    }
+
+    /// First entry matching the node, `None` also while an earlier entry
+    /// selects by labels that are not loaded yet
+    fn matching(&self, node: &str) -> Option<&ExpectedHardware> {
+        let labels = self.node_labels.read().unwrap();
+
+        for entry in &self.expected {
+            if !entry.nodes.is_empty() && !entry.nodes.iter().any(|p| p.matches(node)) {
+                continue;
+            }
+            if entry.settings.node_selector.is_empty() {
+                return Some(entry);
+            }
+            let labels = labels.as_ref()?;
+            let selected = entry
+                .settings
+                .node_selector
+                .iter()
+                .all(|(name, value)| labels.get(name) == Some(value));
+            if selected {
+                return Some(entry);
+            }
+        }
+
+        None
+    }
+
+    /// Compare the collected hardware with the entry matching the node
+    pub fn check(&self, metrics: &NodeMetrics) -> InventoryReport {
+        let entry = self.matching(&metrics.node);
+        let checks = entry
+            .map(|entry| compare(&entry.settings, metrics))
+            .unwrap_or_default();
+        let mismatches: Vec<InventoryCheck> =
+            checks.iter().filter(|c| !c.matches).cloned().collect();
+
+        InventoryReport {
+            node: metrics.node.clone(),
+            rule: entry.map(|entry| entry.name.clone()),
+            ok: mismatches.is_empty(),
+            mismatches,
+            checks,
+        }
+    }
+}
+
+fn compare(expected: &ExpectedHardwareSettings, metrics: &NodeMetrics) -> Vec<InventoryCheck> {
+    let mut checks = Vec::new();
+
+    if let Some(count) = expected.gpu_count {
+        checks.push(InventoryCheck {
+            component: "gpu_count",
+            expected: count.to_string(),
+            actual: metrics.gpu_count.to_string(),
+            matches: metrics.gpu_count == count,
+        });
+    }
+
+    if let Some(model) = &expected.gpu_model {
+        let names: BTreeSet<&str> = metrics
+            .gpu_devices
+            .iter()
+            .map(|gpu| gpu.name.as_str())
+            .collect();
+        checks.push(InventoryCheck {
+            component: "gpu_model",
+            expected: model.clone(),
+            actual: if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.iter().copied().collect::<Vec<_>>().join(",")
+            },
+            matches: !names.is_empty() && names.iter().all(|name| name.contains(model.as_str())),
+        });
+    }
+
+    if let Some(min_gib) = expected.min_memory_gib {
+        let gib = metrics.memory_total_bytes as f64 / (1u64 << 30) as f64;
+        checks.push(InventoryCheck {
+            component: "memory",
+            expected: format!(">={}GiB", min_gib),
+            actual: format!("{:.1}GiB", gib),
+            matches: gib >= min_gib,
+        });
+    }
+
+    if let Some(model) = &expected.cpu_model {
+        checks.push(InventoryCheck {
+            component: "cpu_model",
+            expected: model.clone(),
+            actual: metrics.cpu_model.clone(),
+            matches: metrics.cpu_model.contains(model.as_str()),
+        });
+    }
+
+    if let Some(cores) = expected.cpu_cores {
+        checks.push(InventoryCheck {
+            component: "cpu_cores",
+            expected: cores.to_string(),
+            actual: metrics.cpu_cores.to_string(),
+            matches: metrics.cpu_cores == cores,
+        });
+    }
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::GpuInfo;
+
+    fn entry(name: &str, nodes: &[&str], selector: &[(&str, &str)]) -> ExpectedHardwareSettings {
+        ExpectedHardwareSettings {
+            name: Some(name.to_string()),
+            nodes: nodes.iter().map(|n| n.to_string()).collect(),
+            node_selector: labels(selector),
+            ..Default::default()
+        }
+    }
+
+    fn labels(labels: &[(&str, &str)]) -> BTreeMap<String, String> {
+        labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn checker(expected: Vec<ExpectedHardwareSettings>) -> InventoryChecker {
+        InventoryChecker::from_settings(
+            &InventorySettings { expected },
+            &KubernetesSettings::default(),
+        )
+        .unwrap()
+    }
+
+    fn matching<'a>(checker: &'a InventoryChecker, node: &str) -> Option<&'a str> {
+        checker.matching(node).map(|entry| entry.name.as_str())
+    }
+
+    fn metrics(gpus: &[&str]) -> NodeMetrics {
+        let mut metrics = NodeMetrics::collect();
+        metrics.node = "gpu-a-1".to_string();
+        metrics.gpu_devices = gpus
+            .iter()
+            .map(|name| GpuInfo {
+                name: name.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        metrics.gpu_count = gpus.len();
+        metrics.memory_total_bytes = 503 << 30;
+        metrics.cpu_model = "AMD EPYC 7763 64-Core Processor".to_string();
+        metrics.cpu_cores = 128;
+        metrics
+    }
+
+    fn results(checks: &[InventoryCheck]) -> Vec<(&str, &str, &str, bool)> {
+        checks
+            .iter()
+            .map(|c| {
+                (
+                    c.component,
+                    c.expected.as_str(),
+                    c.actual.as_str(),
+                    c.matches,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_matching_entry_applies() {
+        let checker = checker(vec![
+            entry("a100", &["gpu-a-*"], &[]),
+            entry("regex", &["/gpu-[ab]-\\d+/"], &[]),
+            ExpectedHardwareSettings::default(),
+        ]);
+        assert_eq!(matching(&checker, "gpu-a-1"), Some("a100"));
+        assert_eq!(matching(&checker, "gpu-b-2"), Some("regex"));
+        assert_eq!(matching(&checker, "gpu-b-x"), Some("expected[2]"));
+        assert_eq!(matching(&checker, "cpu-1"), Some("expected[2]"));
+        assert!(!checker.needs_node_labels());
+    }
+
+    #[test]
+    fn node_selector_waits_for_labels() {
+        let checker = checker(vec![
+            entry("h100", &[], &[("gpu.product", "H100")]),
+            entry("gpu", &["gpu-*"], &[]),
+        ]);
+        assert!(checker.needs_node_labels());
+        // An earlier entry may still apply once labels are known
+        assert_eq!(matching(&checker, "gpu-a-1"), None);
+
+        *checker.node_labels.write().unwrap() = Some(labels(&[("gpu.product", "A100")]));
+        assert_eq!(matching(&checker, "gpu-a-1"), Some("gpu"));
+        assert_eq!(matching(&checker, "cpu-1"), None);
+
+        *checker.node_labels.write().unwrap() =
+            Some(labels(&[("gpu.product", "H100"), ("zone", "a")]));
+        assert_eq!(matching(&checker, "cpu-1"), Some("h100"));
+    }
+
+    #[test]
+    fn nodes_and_selector_must_both_match() {
+        let checker = checker(vec![entry("both", &["gpu-*"], &[("pool", "train")])]);
+        *checker.node_labels.write().unwrap() = Some(labels(&[("pool", "train")]));
+        assert_eq!(matching(&checker, "gpu-1"), Some("both"));
+        assert_eq!(matching(&checker, "cpu-1"), None);
+
+        *checker.node_labels.write().unwrap() = Some(labels(&[("pool", "serve")]));
+        assert_eq!(matching(&checker, "gpu-1"), None);
+    }
+
+    #[test]
+    fn compares_every_configured_property() {
+        let expected = ExpectedHardwareSettings {
+            gpu_count: Some(8),
+            gpu_model: Some("A100-SXM4-80GB".to_string()),
+            min_memory_gib: Some(500.0),
+            cpu_model: Some("EPYC 7763".to_string()),
+            cpu_cores: Some(128),
+            ..Default::default()
+        };
+        let metrics = metrics(&["NVIDIA A100-SXM4-80GB"; 8]);
+        assert_eq!(
+            results(&compare(&expected, &metrics)),
+            [
+                ("gpu_count", "8", "8", true),
+                ("gpu_model", "A100-SXM4-80GB", "NVIDIA A100-SXM4-80GB", true),
+                ("memory", ">=500GiB", "503.0GiB", true),
+                (
+                    "cpu_model",
+                    "EPYC 7763",
+                    "AMD EPYC 7763 64-Core Processor",
+                    true
+                ),
+                ("cpu_cores", "128", "128", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_mismatches() {
+        let expected = ExpectedHardwareSettings {
+            gpu_count: Some(8),
+            gpu_model: Some("A100-SXM4-80GB".to_string()),
+            min_memory_gib: Some(1024.0),
+            cpu_model: Some("Xeon".to_string()),
+            cpu_cores: Some(96),
+            ..Default::default()
+        };
+        let metrics = metrics(&["NVIDIA A100-SXM4-80GB", "NVIDIA A100-SXM4-40GB"]);
+        assert_eq!(
+            results(&compare(&expected, &metrics)),
+            [
+                ("gpu_count", "8", "2", false),
+                (
+                    "gpu_model",
+                    "A100-SXM4-80GB",
+                    "NVIDIA A100-SXM4-40GB,NVIDIA A100-SXM4-80GB",
+                    false
+                ),
+                ("memory", ">=1024GiB", "503.0GiB", false),
+                (
+                    "cpu_model",
+                    "Xeon",
+                    "AMD EPYC 7763 64-Core Processor",
+                    false
+                ),
+                ("cpu_cores", "96", "128", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn gpu_model_without_gpus_does_not_match() {
+        let expected = ExpectedHardwareSettings {
+            gpu_model: Some("A100".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            results(&compare(&expected, &metrics(&[]))),
+            [("gpu_model", "A100", "none", false)]
+        );
+        assert!(compare(&ExpectedHardwareSettings::default(), &metrics(&[])).is_empty());
+    }
+
+    #[test]
+    fn check_reports_the_applied_entry() {
+        let checker = checker(vec![ExpectedHardwareSettings {
+            name: Some("a100".to_string()),
+            gpu_count: Some(8),
+            cpu_cores: Some(128),
+            ..Default::default()
+        }]);
+        let report = checker.check(&metrics(&["NVIDIA A100-SXM4-80GB"; 7]));
+        assert_eq!(report.node, "gpu-a-1");
+        assert_eq!(report.rule.as_deref(), Some("a100"));
+        assert!(!report.ok);
+        assert_eq!(report.checks.len(), 2);
+        assert_eq!(
+            results(&report.mismatches),
+            [("gpu_count", "8", "7", false)]
+        );
+
+        let report = self::checker(vec![]).check(&metrics(&[]));
+        assert_eq!(report.rule, None);
+        assert!(report.ok);
+        assert!(report.checks.is_empty());
+    }
+}
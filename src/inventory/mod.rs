@@ -0,0 +1,143 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::metrics;
+
+pub mod changes;
+pub mod devices;
+pub mod dmi;
+pub mod expected;
+
+pub use changes::{ChangeDetector, ChangeEvent};
+pub use devices::{Disk, Nic, PciDevice};
+pub use dmi::{dmi_info, Dimm, DmiInfo};
+pub use expected::{InventoryCheck, InventoryChecker, InventoryReport};
+
+/// Host sysfs mounted into the container, falling back to the container's own
+/// /sys where network interfaces are those of the pod's namespace
+const HOST_SYSFS: &str = "/host/sys";
+
+fn sysfs_root() -> &'static str {
+    if Path::new(HOST_SYSFS).is_dir() {
+        HOST_SYSFS
+    } else {
+        "/sys"
+    }
+}
+
+fn sysfs_path(relative: &str) -> PathBuf {
+    Path::new(sysfs_root()).join(relative)
+}
+
+/// Contents of a sysfs attribute, `None` when missing, unreadable or empty
+fn read_trimmed(path: &Path) -> Option<String> {
+    let value = fs::read_to_string(path).ok()?;
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[derive(Debug, Serialize)]
+pub struct CpuInventory {
+    pub model: Option<String>,
+    pub sockets: usize,
+    pub cores: usize,
+    pub threads: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MemoryInventory {
+    /// `MemTotal` as seen by the kernel
+    pub total_bytes: Option<u64>,
+    /// Sum of the DIMM sizes, when the SMBIOS table lists them
+    pub installed_bytes: Option<u64>,
+    pub dimms: Vec<Dimm>,
+}
+
+/// Hardware inventory served by `/api/v1/inventory`
+#[derive(Debug, Serialize)]
+pub struct Inventory {
+    pub node: String,
+    pub dmi: DmiInfo,
+    pub cpu: CpuInventory,
+    pub memory: MemoryInventory,
+    pub pci_devices: Vec<PciDevice>,
+    pub nics: Vec<Nic>,
+    pub disks: Vec<Disk>,
+}
+
+impl Inventory {
+    /// Read the inventory from sysfs and procfs, blocking on file I/O
+    pub fn collect() -> Self {
+        let dimms = dmi::read_dimms();
+        let installed_bytes = if dimms.is_empty() {
+            None
+        } else {
+            Some(dimms.iter().filter_map(|dimm| dimm.size_bytes).sum())
+        };
+
+        Self {
+            node: metrics::node_name(),
+            dmi: dmi_info().clone(),
+            cpu: read_cpu(),
+            memory: MemoryInventory {
+                total_bytes: read_mem_total(),
+                installed_bytes,
+                dimms,
+            },
+            pci_devices: devices::read_pci_devices(),
+            nics: devices::read_nics(),
+            disks: devices::read_disks(),
+        }
+    }
+}
+
+/// CPU topology from sysfs, model from /proc/cpuinfo
+fn read_cpu() -> CpuInventory {
+    let model = fs::read_to_string("/proc/cpuinfo").ok().and_then(|info| {
+        info.lines()
+            .find(|line| line.starts_with("model name"))
+            .and_then(|line| line.split_once(':'))
+            .map(|(_, model)| model.trim().to_string())
+    });
+
+    let root = sysfs_path("devices/system/cpu");
+    let mut sockets = BTreeSet::new();
+    let mut cores = BTreeSet::new();
+    let mut threads = 0;
+
+    for entry in fs::read_dir(&root).into_iter().flatten().flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_cpu = name
+            .strip_prefix("cpu")
+            .is_some_and(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()));
+        if !is_cpu {
+            continue;
+        }
+
+        let topology = entry.path().join("topology");
+        // Offline CPUs have no topology directory
+        let Some(package) = read_trimmed(&topology.join("physical_package_id")) else {
+            continue;
+        };
+        let core = read_trimmed(&topology.join("core_id")).unwrap_or_default();
+        threads += 1;
+        sockets.insert(package.clone());
+        cores.insert((package, core));
+    }
+
+    CpuInventory {
+        model,
+        sockets: sockets.len(),
+        cores: cores.len(),
+        threads,
+    }
+}
+
+fn read_mem_total() -> Option<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
@@ -0,0 +1,96 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::info;
+
+use crate::config::KubernetesSettings;
+
+/// Minimal Kubernetes API client authenticating with the service account token
+pub struct ApiClient {
+    client: reqwest::Client,
+    api_server: String,
+    token_path: String,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    metadata: ObjectMeta,
+}
+
+#[derive(Deserialize)]
+struct ObjectMeta {
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+}
+
+impl ApiClient {
+    pub fn new(settings: &KubernetesSettings) -> anyhow::Result<Self> {
+        let api_server = match &settings.api_server {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None => {
+                let host = std::env::var("KUBERNETES_SERVICE_HOST").map_err(|_| {
+                    anyhow::anyhow!("kubernetes.api_server is required outside a cluster")
+                })?;
+                let port =
+                    std::env::var("KUBERNETES_SERVICE_PORT").unwrap_or_else(|_| "443".into());
+                // IPv6 service addresses must be bracketed
+                if host.contains(':') {
+                    format!("https://[{}]:{}", host, port)
+                } else {
+                    format!("https://{}:{}", host, port)
+                }
+            }
+        };
+
+        let mut builder = reqwest::Client::builder()
+            .timeout(Duration::from_secs(settings.timeout_secs))
+            .user_agent(concat!("system-info-exporter/", env!("CARGO_PKG_VERSION")));
+        if api_server.starts_with("https://") {
+            let pem = std::fs::read(&settings.ca_path).map_err(|e| {
+                anyhow::anyhow!("failed to read API server CA {}: {}", settings.ca_path, e)
+            })?;
+            for cert in reqwest::Certificate::from_pem_bundle(&pem)? {
+                builder = builder.add_root_certificate(cert);
+            }
+        }
+
+        info!("Using Kubernetes API server {}", api_server);
+
+        Ok(Self {
+            client: builder.build()?,
+            api_server,
+            token_path: settings.token_path.clone(),
+        })
+    }
+
+    /// Request to an API path such as `/api/v1/nodes/<node>`
+    pub fn request(&self, method: reqwest::Method, path: &str) -> reqwest::RequestBuilder {
+        let request = self
+            .client
+            .request(method, format!("{}{}", self.api_server, path));
+        // Projected service account tokens rotate, read it for every request
+        match std::fs::read_to_string(&self.token_path) {
+            Ok(token) => request.bearer_auth(token.trim()),
+            Err(_) => request,
+        }
+    }
+
+    /// Labels of a Node object
+    pub async fn node_labels(&self, node: &str) -> anyhow::Result<BTreeMap<String, String>> {
+        let response = self
+            .request(reqwest::Method::GET, &format!("/api/v1/nodes/{}", node))
+            .send()
+            .await?;
+        let status = response.status();
+        if !status.is_success() {
+            let text = response.text().await.unwrap_or_default();
+            anyhow::bail!(
+                "getting node {} failed with {}: {}",
+                node,
+                status,
+                text.trim()
+            );
+        }
+        Ok(response.json::<Node>().await?.metadata.labels)
+    }
+}
@@ -1,29 +1,170 @@
 use anyhow::Result;
-use tracing::info;
+use clap::Parser;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
+mod alerts;
 mod api;
+mod cli;
+mod collector;
 mod config;
 mod error;
+mod health;
+mod history;
+mod inventory;
+mod kube;
 mod metrics;
+mod push;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
+    let cli = cli::Cli::parse();
+
     // Initialize tracing
+    // Plugin output goes to stdout in check mode, keep logs out of it
+    let writer = if matches!(cli.command, Some(cli::Command::Check(_))) {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
     tracing_subscriber::fmt()
         .with_env_filter(
             tracing_subscriber::EnvFilter::from_default_env()
                 .add_directive("system_info_exporter=info".parse().unwrap()),
         )
+        .with_writer(writer)
         .json()
         .init();
 
-    info!("Starting system-info-exporter v{}", env!("CARGO_PKG_VERSION"));
+    info!(
+        "Starting system-info-exporter v{}",
+        env!("CARGO_PKG_VERSION")
+    );
 
     // Load configuration
     let settings = config::Settings::load()?;
+    metrics::configure_gpu(&settings.gpu);
+    metrics::load_gpu_cache(settings.state_dir.as_deref());
+    let selector = Arc::new(
+        metrics::filter::MetricSelector::from_settings(&settings.metrics)
+            .map_err(anyhow::Error::msg)?,
+    );
+
+    if let Some(cli::Command::Check(args)) = &cli.command {
+        let health = settings.health.clone().unwrap_or_default();
+        return Ok(health::run_check(&health, args.condition).await);
+    }
+
+    // Summaries cover one collect interval
+    metrics::gpu_sampler::start(Duration::from_secs(
+        settings.metrics.collect_interval_secs.max(1),
+    ));
+
+    let inventory = Arc::new(inventory::InventoryChecker::from_settings(
+        &settings.inventory,
+        &settings.kubernetes,
+    )?);
+
+    let changes = Arc::new(inventory::ChangeDetector::new(
+        settings.state_dir.as_deref(),
+    ));
+
+    // Background collection feeding the push sinks
+    let collector = collector::Collector::new(
+        &settings,
+        selector.clone(),
+        inventory.clone(),
+        changes.clone(),
+    );
+
+    if let Some(cli::Command::Push(args)) = &cli.command {
+        let mut pushgateway = settings.pushgateway.clone();
+        args.apply(&mut pushgateway);
+        let interval = Duration::from_secs(
+            args.interval
+                .unwrap_or(settings.metrics.collect_interval_secs)
+                .max(1),
+        );
+
+        if let Err(e) = inventory.load_node_labels().await {
+            warn!("Failed to load node labels for inventory checks: {}", e);
+        }
+
+        let success =
+            push::pushgateway::run(&pushgateway, &collector, args.count, interval).await?;
+        return Ok(if success {
+            ExitCode::SUCCESS
+        } else {
+            ExitCode::FAILURE
+        });
+    }
+
+    let alerts = Arc::new(alerts::Evaluator::from_settings(&settings.alerts)?);
+    if alerts.is_enabled() {
+        alerts.spawn(settings.alerts.webhooks.clone(), collector.subscribe())?;
+    }
+    inventory.spawn()?;
+    changes.spawn(collector.subscribe());
+    let health = Arc::new(health::HealthMonitor::new(
+        settings.health.clone(),
+        settings.kubernetes.clone(),
+    ));
+    if health.is_enabled() {
+        health.spawn(collector.subscribe())?;
+    }
+    let history = Arc::new(history::History::new(&settings));
+    if history.is_enabled() {
+        history.spawn(collector.subscribe());
+    }
+    if let Some(remote_write) = &settings.remote_write {
+        push::remote_write::spawn(remote_write.clone(), collector.subscribe())?;
+    }
+    if let Some(otlp) = &settings.otlp {
+        push::otlp::spawn(otlp.clone(), collector.subscribe())?;
+    }
+    if let Some(influx_push) = &settings.influx.push {
+        push::socket::spawn_line_push(
+            "InfluxDB",
+            influx_push.clone(),
+            collector.subscribe(),
+            |snapshot| {
+                metrics::influx::encode(
+                    &snapshot.families,
+                    &snapshot.metrics.labels,
+                    snapshot.timestamp_ms(),
+                )
+            },
+        );
+    }
+    if let Some(graphite_push) = &settings.graphite.push {
+        let prefix = settings.graphite.prefix.clone();
+        push::socket::spawn_line_push(
+            "Graphite",
+            graphite_push.clone(),
+            collector.subscribe(),
+            move |snapshot| {
+                metrics::graphite::encode(
+                    &snapshot.families,
+                    &snapshot.metrics.labels,
+                    &prefix,
+                    snapshot.timestamp_ms(),
+                )
+            },
+        );
+    }
+    if let Some(statsd) = &settings.statsd {
+        push::statsd::spawn(statsd.clone(), collector.subscribe())?;
+    }
+    collector.spawn();
 
     // Start the API server
-    api::serve(settings).await?;
+    api::serve(
+        settings, selector, history, alerts, health, inventory, changes,
+    )
+    .await?;
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Prometheus metric type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Gauge,
+    Counter,
+}
+
+impl MetricType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MetricType::Gauge => "gauge",
+            MetricType::Counter => "counter",
+        }
+    }
+}
+
+/// A single series of a metric family
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub labels: Vec<(String, String)>,
+    pub value: f64,
+}
+
+/// All series sharing a metric name, along with the collector that produced them
+#[derive(Debug, Clone)]
+pub struct MetricFamily {
+    pub name: String,
+    pub help: String,
+    pub metric_type: MetricType,
+    /// Collector the metric belongs to: node, cpu, memory or gpu
+    pub collector: &'static str,
+    pub samples: Vec<Sample>,
+}
+
+impl MetricFamily {
+    pub fn new(name: &str, help: &str, metric_type: MetricType, collector: &'static str) -> Self {
+        Self {
+            name: name.to_string(),
+            help: help.to_string(),
+            metric_type,
+            collector,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn gauge(name: &str, help: &str, collector: &'static str) -> Self {
+        Self::new(name, help, MetricType::Gauge, collector)
+    }
+
+    pub fn counter(name: &str, help: &str, collector: &'static str) -> Self {
+        Self::new(name, help, MetricType::Counter, collector)
+    }
+
+    pub fn with_sample(mut self, labels: Vec<(String, String)>, value: f64) -> Self {
+        self.add_sample(labels, value);
+        self
+    }
+
+    pub fn add_sample(&mut self, labels: Vec<(String, String)>, value: f64) {
+        self.samples.push(Sample { labels, value });
+    }
+}
+
+/// Render metric families in the Prometheus text exposition format
+/// Constant labels are appended to every series unless the series already
+/// carries a label of the same name. Families without samples are omitted
+pub fn encode_text(families: &[MetricFamily], const_labels: &BTreeMap<String, String>) -> String {
+    let mut output = String::new();
+
+    for family in families.iter().filter(|f| !f.samples.is_empty()) {
+        let _ = writeln!(output, "# HELP {} {}", family.name, family.help);
+        let _ = writeln!(
+            output,
+            "# TYPE {} {}",
+            family.name,
+            family.metric_type.as_str()
+        );
+
+        for sample in &family.samples {
+            let labels = series_labels(sample, const_labels);
+
+            output.push_str(&family.name);
+            if !labels.is_empty() {
+                output.push('{');
+                for (i, (name, value)) in labels.iter().enumerate() {
+                    if i > 0 {
+                        output.push(',');
+                    }
+                    let _ = write!(output, "{}=\"{}\"", name, escape_label_value(value));
+                }
+                output.push('}');
+            }
+            let _ = writeln!(output, " {}", format_value(sample.value));
+        }
+    }
+
+    output
+}
+
+/// Labels of a sample followed by the constant labels it does not override
+pub fn series_labels<'a>(
+    sample: &'a Sample,
+    const_labels: &'a BTreeMap<String, String>,
+) -> Vec<(&'a str, &'a str)> {
+    let mut labels: Vec<(&str, &str)> = sample
+        .labels
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+
+    for (name, value) in const_labels {
+        if !sample.labels.iter().any(|(existing, _)| existing == name) {
+            labels.push((name.as_str(), value.as_str()));
+        }
+    }
+
+    labels
+}
+
+pub fn escape_label_value(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Format a sample value, printing integral values without a fractional part
+pub fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        format!("{}", value)
+    }
+}
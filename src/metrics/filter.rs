@@ -0,0 +1,302 @@
+use regex::Regex;
+use tracing::warn;
+
+use super::exposition::MetricFamily;
+use crate::config::MetricsSettings;
+
+/// Collectors that can be selected with `collect[]` or matched by patterns
+pub const COLLECTORS: &[&str] = &["node", "cpu", "memory", "gpu", "inventory"];
+
+/// Legacy `[metrics.enabled]` keys and the metric each one controls
+pub const LEGACY_METRIC_KEYS: &[(&str, &str)] = &[
+    ("node_info", "hw_node_info"),
+    ("node_uptime", "hw_node_uptime_seconds"),
+    ("cpu_cores", "hw_cpu_cores"),
+    ("cpu_threads", "hw_cpu_threads"),
+    ("cpu_usage", "hw_cpu_usage_percent"),
+    ("cpu_used_cores", "hw_cpu_used_cores"),
+    ("memory_total", "hw_memory_total_bytes"),
+    ("memory_used", "hw_memory_used_bytes"),
+    ("memory_available", "hw_memory_available_bytes"),
+    ("memory_usage", "hw_memory_usage_percent"),
+    ("gpu_count", "hw_gpu_count"),
+    ("gpu_used_count", "hw_gpu_used_count"),
+    ("gpu_type_count", "hw_gpu_type_count"),
+    ("gpu_memory_total", "hw_gpu_memory_total_bytes"),
+    ("gpu_memory_used", "hw_gpu_memory_used_bytes"),
+    ("gpu_memory_free", "hw_gpu_memory_free_bytes"),
+    ("gpu_utilization", "hw_gpu_utilization_percent"),
+    (
+        "gpu_memory_utilization",
+        "hw_gpu_memory_utilization_percent",
+    ),
+    (
+        "gpu_encoder_utilization",
+        "hw_gpu_encoder_utilization_percent",
+    ),
+    (
+        "gpu_decoder_utilization",
+        "hw_gpu_decoder_utilization_percent",
+    ),
+    ("gpu_temperature", "hw_gpu_temperature_celsius"),
+    ("gpu_power_draw", "hw_gpu_power_draw_watts"),
+    ("gpu_power_limit", "hw_gpu_power_limit_watts"),
+    ("gpu_fan_speed", "hw_gpu_fan_speed_percent"),
+];
+
+/// Metrics with many series per GPU, only exported when an include pattern
+/// matches their name
+pub const OPT_IN_METRICS: &[&str] = &["hw_gpu_field_supported"];
+
+/// A metric or collector name pattern
+/// `/.../` is a regular expression anchored at both ends, anything else is a
+/// glob supporting `*` and `?`
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Glob(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn parse(pattern: &str) -> Result<Self, String> {
+        match pattern.strip_prefix('/').and_then(|p| p.strip_suffix('/')) {
+            Some(expr) => Regex::new(&format!("^(?:{})$", expr))
+                .map(Pattern::Regex)
+                .map_err(|e| format!("invalid regex pattern {}: {}", pattern, e)),
+            None => Ok(Pattern::Glob(pattern.to_string())),
+        }
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match self {
+            Pattern::Glob(glob) => glob_match(glob, value),
+            Pattern::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+/// Configured metric selection from `metrics.include` / `metrics.exclude`
+/// Patterns match either the metric name or its collector name
+#[derive(Debug, Clone, Default)]
+pub struct MetricSelector {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl MetricSelector {
+    /// Compile the selector from settings, mapping legacy `enabled` keys set
+    /// to false onto exclude patterns; unknown keys are logged and ignored
+    pub fn from_settings(settings: &MetricsSettings) -> Result<Self, String> {
+        let include = settings
+            .include
+            .iter()
+            .map(|p| Pattern::parse(p))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut exclude = settings
+            .exclude
+            .iter()
+            .map(|p| Pattern::parse(p))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for (key, enabled) in &settings.enabled.0 {
+            let Some((_, metric)) = LEGACY_METRIC_KEYS
+                .iter()
+                .find(|(legacy_key, _)| legacy_key == key)
+            else {
+                warn!("Ignoring unknown metrics.enabled key: {}", key);
+                continue;
+            };
+            if !enabled {
+                exclude.push(Pattern::Glob(metric.to_string()));
+            }
+        }
+
+        Ok(Self { include, exclude })
+    }
+
+    pub fn allows(&self, name: &str, collector: &str) -> bool {
+        if OPT_IN_METRICS.contains(&name) && !self.include.iter().any(|p| p.matches(name)) {
+            return false;
+        }
+        let matches = |p: &Pattern| p.matches(name) || p.matches(collector);
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+/// Per-scrape selection of metrics from `name[]` and `collect[]` query parameters
+/// Empty lists select everything
+#[derive(Debug, Clone, Default)]
+pub struct ScrapeFilter {
+    pub names: Vec<String>,
+    pub collectors: Vec<String>,
+}
+
+impl ScrapeFilter {
+    /// Build a filter from decoded query parameters, ignoring unrelated keys
+    pub fn from_query(params: &[(String, String)]) -> Result<Self, String> {
+        let mut filter = ScrapeFilter::default();
+
+        for (key, value) in params {
+            match key.as_str() {
+                "name[]" | "name" => filter.names.push(value.clone()),
+                "collect[]" | "collect" => {
+                    if !COLLECTORS.contains(&value.as_str()) {
+                        return Err(format!(
+                            "unknown collector \"{}\", expected one of: {}",
+                            value,
+                            COLLECTORS.join(", ")
+                        ));
+                    }
+                    filter.collectors.push(value.clone());
+                }
+                _ => {}
+            }
+        }
+
+        Ok(filter)
+    }
+
+    pub fn matches(&self, family: &MetricFamily) -> bool {
+        let collector_ok =
+            self.collectors.is_empty() || self.collectors.iter().any(|c| c == family.collector);
+        let name_ok = self.names.is_empty()
+            || self
+                .names
+                .iter()
+                .any(|pattern| glob_match(pattern, &family.name));
+        collector_ok && name_ok
+    }
+}
+
+/// Match `name` against a glob pattern supporting `*` and `?`
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+
+    let (mut p, mut n) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = backtrack {
+            p = star_p + 1;
+            n = star_n + 1;
+            backtrack = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::MetricsEnabled;
+
+    fn selector(include: &[&str], exclude: &[&str], enabled: &[(&str, bool)]) -> MetricSelector {
+        MetricSelector::from_settings(&MetricsSettings {
+            include: include.iter().map(|p| p.to_string()).collect(),
+            exclude: exclude.iter().map(|p| p.to_string()).collect(),
+            enabled: MetricsEnabled(
+                enabled
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), *value))
+                    .collect(),
+            ),
+            collect_interval_secs: 15,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn glob_wildcards() {
+        assert!(glob_match("hw_gpu_*", "hw_gpu_count"));
+        assert!(glob_match("hw_*_bytes", "hw_memory_total_bytes"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("hw_cpu_core?", "hw_cpu_cores"));
+        assert!(!glob_match("hw_cpu_core?", "hw_cpu_core"));
+        assert!(!glob_match("hw_gpu_*", "hw_cpu_cores"));
+        assert!(!glob_match("hw_gpu", "hw_gpu_count"));
+    }
+
+    #[test]
+    fn regex_patterns_are_anchored() {
+        let pattern = Pattern::parse("/hw_(cpu|memory)_.*/").unwrap();
+        assert!(pattern.matches("hw_cpu_cores"));
+        assert!(pattern.matches("hw_memory_used_bytes"));
+        assert!(!pattern.matches("hw_gpu_cpu_cores"));
+
+        let pattern = Pattern::parse("/cpu/").unwrap();
+        assert!(pattern.matches("cpu"));
+        assert!(!pattern.matches("hw_cpu_cores"));
+
+        assert!(Pattern::parse("/hw_(/").is_err());
+        assert!(matches!(Pattern::parse("/"), Ok(Pattern::Glob(_))));
+    }
+
+    #[test]
+    fn empty_selector_allows_everything() {
+        let selector = selector(&[], &[], &[]);
+        assert!(selector.allows("hw_gpu_count", "gpu"));
+        assert!(selector.allows("hw_node_info", "node"));
+    }
+
+    #[test]
+    fn include_matches_names_or_collectors() {
+        let selector = selector(&["cpu", "hw_memory_total_bytes"], &[], &[]);
+        assert!(selector.allows("hw_cpu_cores", "cpu"));
+        assert!(selector.allows("hw_memory_total_bytes", "memory"));
+        assert!(!selector.allows("hw_memory_used_bytes", "memory"));
+        assert!(!selector.allows("hw_gpu_count", "gpu"));
+    }
+
+    #[test]
+    fn exclude_wins_over_include() {
+        let selector = selector(&["gpu"], &["hw_gpu_field_supported", "/.*_info/"], &[]);
+        assert!(selector.allows("hw_gpu_count", "gpu"));
+        assert!(!selector.allows("hw_gpu_field_supported", "gpu"));
+        assert!(!selector.allows("hw_gpu_info", "gpu"));
+        assert!(!selector.allows("hw_cpu_cores", "cpu"));
+    }
+
+    #[test]
+    fn opt_in_metrics_need_an_include_matching_their_name() {
+        assert!(!selector(&[], &[], &[]).allows("hw_gpu_field_supported", "gpu"));
+        assert!(!selector(&["gpu"], &[], &[]).allows("hw_gpu_field_supported", "gpu"));
+
+        let selector = selector(&["gpu", "hw_gpu_field_supported"], &[], &[]);
+        assert!(selector.allows("hw_gpu_field_supported", "gpu"));
+        assert!(selector.allows("hw_gpu_count", "gpu"));
+    }
+
+    #[test]
+    fn legacy_enabled_keys_map_to_excludes() {
+        let selector = selector(&[], &[], &[("gpu_power_draw", false), ("cpu_usage", true)]);
+        assert!(!selector.allows("hw_gpu_power_draw_watts", "gpu"));
+        assert!(selector.allows("hw_gpu_power_limit_watts", "gpu"));
+        assert!(selector.allows("hw_cpu_usage_percent", "cpu"));
+    }
+
+    #[test]
+    fn unknown_legacy_keys_are_ignored() {
+        let selector = selector(&[], &[], &[("gpu_clock", false), ("cpu_usage", false)]);
+        assert!(!selector.allows("hw_cpu_usage_percent", "cpu"));
+        assert!(selector.allows("hw_gpu_count", "gpu"));
+    }
+
+    #[test]
+    fn every_legacy_key_names_a_distinct_metric() {
+        let mut metrics: Vec<_> = LEGACY_METRIC_KEYS.iter().map(|(_, m)| *m).collect();
+        metrics.sort();
+        metrics.dedup();
+        assert_eq!(metrics.len(), LEGACY_METRIC_KEYS.len());
+    }
+}
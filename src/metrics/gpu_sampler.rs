@@ -0,0 +1,378 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{BufRead, BufReader};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use super::{gpu_settings, has_nvidia_gpu, nvidia_smi};
+
+/// Wait before restarting nvidia-smi dmon after it exited or stalled
+const RESTART_DELAY: Duration = Duration::from_secs(10);
+
+/// Sampler of the background thread, set once it started
+static SAMPLER: OnceLock<Mutex<Sampler>> = OnceLock::new();
+
+/// Samples parsed from dmon output, kept for one window
+struct Sampler {
+    /// How far back the summaries look, the collect interval
+    window: Duration,
+    /// Column names of the last dmon header
+    columns: Vec<String>,
+    /// Recent samples per GPU index
+    gpus: BTreeMap<u32, GpuSamples>,
+}
+
+#[derive(Default)]
+struct GpuSamples {
+    utilization: VecDeque<(Instant, f64)>,
+    power: VecDeque<(Instant, f64)>,
+}
+
+/// Summary of the samples taken during the window
+#[derive(Debug, Serialize, Clone)]
+pub struct SampleSummary {
+    pub min: f64,
+    pub avg: f64,
+    pub max: f64,
+    pub p95: f64,
+    pub samples: usize,
+}
+
+impl SampleSummary {
+    fn from_samples(samples: &VecDeque<(Instant, f64)>) -> Option<Self> {
+        let mut values: Vec<f64> = samples.iter().map(|(_, value)| *value).collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+
+        // Nearest rank
+        let rank = ((values.len() as f64 * 0.95).ceil() as usize).max(1);
+        Some(Self {
+            min: values[0],
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+            max: values[values.len() - 1],
+            p95: values[rank - 1],
+            samples: values.len(),
+        })
+    }
+
+    /// Values by `stat` label
+    pub fn stats(&self) -> [(&'static str, f64); 4] {
+        [
+            ("min", self.min),
+            ("avg", self.avg),
+            ("max", self.max),
+            ("p95", self.p95),
+        ]
+    }
+}
+
+/// Sampled utilization and power of one GPU
+#[derive(Debug, Serialize, Clone)]
+pub struct GpuSampleStats {
+    pub index: u32,
+    pub utilization_percent: Option<SampleSummary>,
+    pub power_draw_watts: Option<SampleSummary>,
+}
+
+/// Start the background sampler when `gpu.sampling` is enabled, summaries
+/// cover the last `window`
+pub fn start(window: Duration) {
+    if !gpu_settings().sampling {
+        return;
+    }
+    let sampler = SAMPLER.get_or_init(|| Mutex::new(Sampler::new(window)));
+
+    if let Err(e) = thread::Builder::new()
+        .name("gpu-sampler".to_string())
+        .spawn(move || run(sampler))
+    {
+        warn!("Failed to start the GPU sampler: {}", e);
+    }
+}
+
+/// Summaries of the samples within the window, GPUs without samples are left out
+pub fn summaries() -> Vec<GpuSampleStats> {
+    match SAMPLER.get().map(Mutex::lock) {
+        Some(Ok(mut sampler)) => sampler.summaries(),
+        _ => Vec::new(),
+    }
+}
+
+/// Keep nvidia-smi dmon running, restarting it when it exits or stalls
+fn run(sampler: &Mutex<Sampler>) {
+    let settings = gpu_settings();
+    let interval = settings.sample_interval_secs.max(1);
+    // A dmon stuck in the driver is killed and restarted
+    let stall = Duration::from_secs(interval + settings.timeout_secs);
+
+    loop {
+        if has_nvidia_gpu() {
+            if let Some(nvidia_smi) = nvidia_smi::find() {
+                stream(&nvidia_smi, interval, stall, sampler);
+            }
+        }
+        thread::sleep(RESTART_DELAY);
+    }
+}
+
+/// Run one nvidia-smi dmon sampling every `interval` seconds until it exits
+/// or prints nothing for `stall`
+fn stream(nvidia_smi: &str, interval: u64, stall: Duration, sampler: &Mutex<Sampler>) {
+    let interval_arg = interval.to_string();
+
+    let mut child =
+        match nvidia_smi::command(nvidia_smi, &["dmon", "-s", "pu", "-d", &interval_arg]).spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                warn!("Failed to run {} dmon: {}", nvidia_smi, e);
+                return;
+            }
+        };
+    let pid = child.id() as libc::pid_t;
+    info!("Sampling GPUs with {} dmon every {}s", nvidia_smi, interval);
+
+    if let Some(stderr) = child.stderr.take() {
+        thread::spawn(move || {
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                warn!("nvidia-smi dmon: {}", line);
+            }
+        });
+    }
+
+    let (sender, lines) = mpsc::channel();
+    if let Some(stdout) = child.stdout.take() {
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    loop {
+        match lines.recv_timeout(stall) {
+            Ok(line) => {
+                if let Ok(mut sampler) = sampler.lock() {
+                    sampler.record(&line, Instant::now());
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                warn!(
+                    "nvidia-smi dmon printed nothing for {}s, restarting",
+                    stall.as_secs()
+                );
+                break;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+    }
+
+    nvidia_smi::kill_group(pid);
+    match child.wait() {
+        Ok(status) => warn!("nvidia-smi dmon exited with {}", status),
+        Err(e) => warn!("Failed to wait for nvidia-smi dmon: {}", e),
+    }
+}
+
+impl Sampler {
+    fn new(window: Duration) -> Self {
+        Self {
+            window,
+            columns: Vec::new(),
+            gpus: BTreeMap::new(),
+        }
+    }
+
+    /// Record a line of dmon output taken at `now`
+    /// The first header names the columns, e.g.
+    /// `# gpu    pwr  gtemp  mtemp     sm    mem    enc    dec`, it is followed
+    /// by a units header and one row per GPU, `-` marks unsupported values
+    fn record(&mut self, line: &str, now: Instant) {
+        if let Some(header) = line.strip_prefix('#') {
+            let names: Vec<String> = header.split_whitespace().map(str::to_string).collect();
+            if names.first().is_some_and(|name| name == "gpu") {
+                self.columns = names;
+            }
+            return;
+        }
+
+        let values: Vec<&str> = line.split_whitespace().collect();
+        let Some(index) = values.first().and_then(|value| value.parse::<u32>().ok()) else {
+            return;
+        };
+        let value = |name: &str| {
+            let position = self.columns.iter().position(|column| column == name)?;
+            values.get(position)?.parse::<f64>().ok()
+        };
+        let utilization = value("sm");
+        let power = value("pwr");
+
+        let gpu = self.gpus.entry(index).or_default();
+        if let Some(utilization) = utilization {
+            gpu.utilization.push_back((now, utilization));
+            prune(&mut gpu.utilization, self.window);
+        }
+        if let Some(power) = power {
+            gpu.power.push_back((now, power));
+            prune(&mut gpu.power, self.window);
+        }
+    }
+
+    fn summaries(&mut self) -> Vec<GpuSampleStats> {
+        let window = self.window;
+        self.gpus
+            .iter_mut()
+            .filter_map(|(index, gpu)| {
+                prune(&mut gpu.utilization, window);
+                prune(&mut gpu.power, window);
+                let stats = GpuSampleStats {
+                    index: *index,
+                    utilization_percent: SampleSummary::from_samples(&gpu.utilization),
+                    power_draw_watts: SampleSummary::from_samples(&gpu.power),
+                };
+                (stats.utilization_percent.is_some() || stats.power_draw_watts.is_some())
+                    .then_some(stats)
+            })
+            .collect()
+    }
+}
+
+fn prune(samples: &mut VecDeque<(Instant, f64)>, window: Duration) {
+    while samples
+        .front()
+        .is_some_and(|(taken, _)| taken.elapsed() > window)
+    {
+        samples.pop_front();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    const HEADER: &str = "# gpu    pwr  gtemp  mtemp     sm    mem    enc    dec";
+    const UNITS: &str = "# Idx      W      C      C      %      %      %      %";
+
+    fn values(summary: &Option<SampleSummary>) -> Option<(f64, f64, f64, f64, usize)> {
+        summary
+            .as_ref()
+            .map(|s| (s.min, s.avg, s.max, s.p95, s.samples))
+    }
+
+    #[test]
+    fn summarizes_dmon_rows() {
+        let mut sampler = Sampler::new(Duration::from_secs(60));
+        let now = Instant::now();
+        for line in [
+            HEADER,
+            UNITS,
+            "    0     60     33      -     10      1      0      0",
+            "    1      -     35      -      -      2      0      0",
+        ]
+        .into_iter()
+        .chain((1..=19).map(|_| "    0     80     33      -     30      1      0      0"))
+        {
+            sampler.record(line, now);
+        }
+        sampler.record(
+            "    0    300     33      -    100      1      0      0",
+            now,
+        );
+
+        let stats = sampler.summaries();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].index, 0);
+        // 21 samples, the nearest rank p95 is the 20th
+        assert_eq!(
+            values(&stats[0].utilization_percent),
+            Some((10.0, 680.0 / 21.0, 100.0, 30.0, 21))
+        );
+        assert_eq!(
+            values(&stats[0].power_draw_watts),
+            Some((60.0, 1880.0 / 21.0, 300.0, 80.0, 21))
+        );
+    }
+
+    #[test]
+    fn drops_samples_outside_the_window() {
+        let window = Duration::from_secs(5);
+        let mut sampler = Sampler::new(window);
+        let Some(old) = Instant::now().checked_sub(window * 2) else {
+            return;
+        };
+        sampler.record(HEADER, old);
+        sampler.record(
+            "    0     60     33      -     10      1      0      0",
+            old,
+        );
+        sampler.record(
+            "    0     70     33      -     20      1      0      0",
+            Instant::now(),
+        );
+
+        let stats = sampler.summaries();
+        assert_eq!(
+            values(&stats[0].utilization_percent),
+            Some((20.0, 20.0, 20.0, 20.0, 1))
+        );
+    }
+
+    #[test]
+    fn stalled_dmon_is_killed_and_restarted() {
+        let dir = tempfile::tempdir().unwrap();
+        let pids = dir.path().join("pids");
+        let nvidia_smi = dir.path().join("nvidia-smi");
+        std::fs::write(
+            &nvidia_smi,
+            format!(
+                "#!/bin/sh\necho $$ >> {}\necho '{}'\necho '{}'\n\
+                 echo '    0     60     33      -     10      1      0      0'\n\
+                 echo '    1      -     35      -      -      2      0      0'\n\
+                 echo '    0     80     34      -     50      1      0      0'\n\
+                 sleep 60\n",
+                pids.display(),
+                HEADER,
+                UNITS
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&nvidia_smi, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let nvidia_smi = nvidia_smi.to_str().unwrap();
+
+        let sampler = Mutex::new(Sampler::new(Duration::from_secs(60)));
+        let stall = Duration::from_secs(1);
+        for _ in 0..2 {
+            let started = Instant::now();
+            stream(nvidia_smi, 1, stall, &sampler);
+            let elapsed = started.elapsed();
+            assert!(elapsed >= stall && elapsed < stall + Duration::from_secs(2));
+        }
+
+        // Each run was a new process, killed and reaped before returning
+        let pids = std::fs::read_to_string(&pids).unwrap();
+        let pids: Vec<&str> = pids.lines().collect();
+        assert_eq!(pids.len(), 2);
+        assert_ne!(pids[0], pids[1]);
+        for pid in pids {
+            assert!(!std::path::Path::new(&format!("/proc/{}", pid)).exists());
+        }
+
+        let stats = sampler.lock().unwrap().summaries();
+        assert_eq!(stats.len(), 1);
+        assert_eq!(
+            values(&stats[0].utilization_percent),
+            Some((10.0, 30.0, 50.0, 50.0, 4))
+        );
+        assert_eq!(
+            values(&stats[0].power_draw_watts),
+            Some((60.0, 70.0, 80.0, 80.0, 4))
+        );
+    }
+}
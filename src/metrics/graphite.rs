@@ -0,0 +1,156 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::exposition::{format_value, series_labels, MetricFamily};
+
+/// Render metric families in the Graphite plaintext protocol using tags
+/// Each series becomes `[prefix.]<metric>;<label>=<value>... <v> <timestamp s>`
+pub fn encode(
+    families: &[MetricFamily],
+    const_labels: &BTreeMap<String, String>,
+    prefix: &str,
+    timestamp_ms: i64,
+) -> String {
+    let mut output = String::new();
+    let timestamp_secs = timestamp_ms / 1000;
+
+    for family in families {
+        for sample in &family.samples {
+            if !sample.value.is_finite() {
+                continue;
+            }
+
+            if !prefix.is_empty() {
+                output.push_str(&sanitize_path(prefix));
+                output.push('.');
+            }
+            output.push_str(&sanitize_path(&family.name));
+
+            for (name, value) in series_labels(sample, const_labels) {
+                let (name, value) = (sanitize_tag_name(name), sanitize_tag_value(value));
+                // Graphite rejects empty tag names and values
+                if name.is_empty() || value.is_empty() {
+                    continue;
+                }
+                let _ = write!(output, ";{}={}", name, value);
+            }
+
+            let _ = writeln!(output, " {} {}", format_value(sample.value), timestamp_secs);
+        }
+    }
+
+    output
+}
+
+/// Metric paths cannot contain whitespace or the tag separator
+fn sanitize_path(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            if c.is_whitespace() || c == ';' {
+                '_'
+            } else {
+                c
+            }
+        })
+        .collect()
+}
+
+/// Tag names may not contain `;!^=` or whitespace
+fn sanitize_tag_name(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ';' | '!' | '^' | '=' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Tag values may not contain `;` or whitespace and must not start with `~`
+fn sanitize_tag_value(s: &str) -> String {
+    let value: String = s
+        .chars()
+        .map(|c| match c {
+            ';' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect();
+    match value.strip_prefix('~') {
+        Some(rest) => format!("_{}", rest),
+        None => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[(&str, &str)]) -> Vec<(String, String)> {
+        labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn renders_tagged_series_with_prefix() {
+        let families = [MetricFamily::gauge("hw_cpu_usage_percent", "CPU", "cpu")
+            .with_sample(labels(&[("node", "n1")]), 12.5)];
+        let const_labels = BTreeMap::from([("cluster".to_string(), "c1".to_string())]);
+        assert_eq!(
+            encode(&families, &const_labels, "k8s.hw", 1_700_000_000_999),
+            "k8s.hw.hw_cpu_usage_percent;node=n1;cluster=c1 12.5 1700000000\n"
+        );
+    }
+
+    #[test]
+    fn sanitizes_paths() {
+        assert_eq!(sanitize_path("k8s hw;x\ny"), "k8s_hw_x_y");
+        assert_eq!(sanitize_path("k8s.hw,a=b"), "k8s.hw,a=b");
+
+        let families =
+            [MetricFamily::gauge("hw_node_info", "Node", "node").with_sample(vec![], 1.0)];
+        assert_eq!(
+            encode(&families, &BTreeMap::new(), "my prefix", 1_000),
+            "my_prefix.hw_node_info 1 1\n"
+        );
+    }
+
+    #[test]
+    fn sanitizes_tags() {
+        let families = [
+            MetricFamily::gauge("hw_gpu_info", "GPU", "gpu").with_sample(
+                labels(&[
+                    ("name", "Tesla T4, rev=a1"),
+                    ("a=b;c", "x"),
+                    ("path", r"C:\gpu"),
+                    ("serial", "line1\nline2;x"),
+                    ("mode", "~default"),
+                    ("empty", ""),
+                ]),
+                1.0,
+            ),
+        ];
+        assert_eq!(
+            encode(&families, &BTreeMap::new(), "", 1_000),
+            concat!(
+                r"hw_gpu_info;name=Tesla_T4,_rev=a1;a_b_c=x;path=C:\gpu;serial=line1_line2_x;mode=_default",
+                " 1 1\n"
+            )
+        );
+    }
+
+    #[test]
+    fn skips_non_finite_values() {
+        let families = [
+            MetricFamily::gauge("hw_gpu_temperature_celsius", "Temp", "gpu")
+                .with_sample(labels(&[("gpu", "0")]), f64::NAN)
+                .with_sample(labels(&[("gpu", "1")]), 40.0),
+        ];
+        assert_eq!(
+            encode(&families, &BTreeMap::new(), "", 1_000),
+            "hw_gpu_temperature_celsius;gpu=1 40 1\n"
+        );
+    }
+}
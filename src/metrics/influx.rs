@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use super::exposition::{format_value, series_labels, MetricFamily};
+
+/// Render metric families as InfluxDB line protocol
+/// Each series becomes `<metric>,<labels as tags> value=<v> <timestamp ns>`
+pub fn encode(
+    families: &[MetricFamily],
+    const_labels: &BTreeMap<String, String>,
+    timestamp_ms: i64,
+) -> String {
+    let mut output = String::new();
+    let timestamp_ns = timestamp_ms as i128 * 1_000_000;
+
+    for family in families {
+        for sample in &family.samples {
+            // Line protocol has no representation for NaN or infinity
+            if !sample.value.is_finite() {
+                continue;
+            }
+
+            output.push_str(&escape_measurement(&family.name));
+            let mut tags = series_labels(sample, const_labels);
+            // Tags should be sorted by key for best write performance
+            tags.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, value) in tags {
+                if value.is_empty() {
+                    continue;
+                }
+                let _ = write!(output, ",{}={}", escape_tag(name), escape_tag(value));
+            }
+            let _ = writeln!(
+                output,
+                " value={} {}",
+                format_value(sample.value),
+                timestamp_ns
+            );
+        }
+    }
+
+    output
+}
+
+/// Measurements escape commas, spaces and backslashes
+/// Newlines cannot be escaped and are replaced with escaped spaces
+fn escape_measurement(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ',' | ' ' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push_str("\\ "),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Tag keys and values escape commas, equals signs, spaces and backslashes
+/// Newlines cannot be escaped and are replaced with escaped spaces
+fn escape_tag(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            ',' | '=' | ' ' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' | '\r' => escaped.push_str("\\ "),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(labels: &[(&str, &str)]) -> Vec<(String, String)> {
+        labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn escapes_tag_keys_and_values() {
+        let families = [
+            MetricFamily::gauge("hw_gpu_info", "GPU", "gpu").with_sample(
+                labels(&[
+                    ("name", "Tesla T4, rev=a1"),
+                    ("path", r"C:\gpu"),
+                    ("serial", "line1\nline2"),
+                    ("odd key=", "x"),
+                ]),
+                1.0,
+            ),
+        ];
+        assert_eq!(
+            encode(&families, &BTreeMap::new(), 1_700_000_000_123),
+            concat!(
+                r"hw_gpu_info,name=Tesla\ T4\,\ rev\=a1,odd\ key\==x,path=C:\\gpu,serial=line1\ line2",
+                " value=1 1700000000123000000\n"
+            )
+        );
+    }
+
+    #[test]
+    fn escapes_measurements() {
+        assert_eq!(escape_measurement("a b,c=d"), r"a\ b\,c=d");
+        assert_eq!(escape_measurement("a\\b\nc"), r"a\\b\ c");
+    }
+
+    #[test]
+    fn sorts_tags_and_adds_constant_labels() {
+        let families = [MetricFamily::gauge("hw_cpu_usage_percent", "CPU", "cpu")
+            .with_sample(labels(&[("node", "n1"), ("cluster", "own")]), 12.5)];
+        let const_labels = BTreeMap::from([
+            ("cluster".to_string(), "c1".to_string()),
+            ("az".to_string(), "a".to_string()),
+            ("empty".to_string(), String::new()),
+        ]);
+        assert_eq!(
+            encode(&families, &const_labels, 1_000),
+            "hw_cpu_usage_percent,az=a,cluster=own,node=n1 value=12.5 1000000000\n"
+        );
+    }
+
+    #[test]
+    fn skips_values_line_protocol_cannot_represent() {
+        let families = [
+            MetricFamily::gauge("hw_gpu_power_draw_watts", "Power", "gpu")
+                .with_sample(labels(&[("gpu", "0")]), f64::NAN)
+                .with_sample(labels(&[("gpu", "1")]), f64::INFINITY)
+                .with_sample(labels(&[("gpu", "2")]), 70.0),
+        ];
+        assert_eq!(
+            encode(&families, &BTreeMap::new(), 1_000),
+            "hw_gpu_power_draw_watts,gpu=2 value=70 1000000000\n"
+        );
+    }
+}
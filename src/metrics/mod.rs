@@ -0,0 +1,1666 @@
+use exposition::MetricFamily;
+use filter::MetricSelector;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sysinfo::System;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::{GpuSettings, StalePolicy};
+use crate::error::AppError;
+use crate::inventory::{dmi_info, ChangeDetector, DmiInfo, InventoryCheck, InventoryChecker};
+
+pub mod exposition;
+pub mod filter;
+pub mod gpu_sampler;
+pub mod graphite;
+pub mod influx;
+pub mod nvidia_smi;
+pub mod nvidia_xml;
+
+/// Longest a caller waits for a collection, nvidia-smi stuck in an
+/// uninterruptible state may never return
+const COLLECT_TIMEOUT_SECS: u64 = 30;
+
+/// File under `state_dir` holding the last successful GPU collection
+const GPU_CACHE_FILE: &str = "gpu-cache.json";
+
+/// Cached GPU information to prevent data loss when nvidia-smi hangs or fails
+struct GpuCache {
+    devices: Vec<GpuInfo>,
+    type_counts: HashMap<String, u32>,
+    used_count: usize,
+    driver_version: String,
+    cuda_version: String,
+    last_update: SystemTime,
+    last_success: bool,
+    /// nvidia-smi failures since the last successful query
+    consecutive_failures: u32,
+    /// Data loaded from the state file, no collection has succeeded since
+    from_disk: bool,
+}
+
+impl Default for GpuCache {
+    fn default() -> Self {
+        Self {
+            devices: Vec::new(),
+            type_counts: HashMap::new(),
+            used_count: 0,
+            driver_version: String::new(),
+            cuda_version: String::new(),
+            last_update: SystemTime::now(),
+            last_success: false,
+            consecutive_failures: 0,
+            from_disk: false,
+        }
+    }
+}
+
+impl GpuCache {
+    fn persisted(&self) -> PersistedGpuCache {
+        PersistedGpuCache {
+            timestamp: unix_secs(self.last_update),
+            devices: self.devices.clone(),
+            type_counts: self.type_counts.clone(),
+            used_count: self.used_count,
+            driver_version: self.driver_version.clone(),
+            cuda_version: self.cuda_version.clone(),
+        }
+    }
+
+    /// Take over the data of a previous run, stale until a collection succeeds
+    fn restore(&mut self, persisted: PersistedGpuCache) {
+        self.devices = persisted.devices;
+        self.type_counts = persisted.type_counts;
+        self.used_count = persisted.used_count;
+        self.driver_version = persisted.driver_version;
+        self.cuda_version = persisted.cuda_version;
+        self.last_update = UNIX_EPOCH + Duration::from_secs(persisted.timestamp);
+        self.from_disk = true;
+    }
+}
+
+/// GPU cache as written to `<state_dir>/gpu-cache.json`
+#[derive(Serialize, Deserialize)]
+struct PersistedGpuCache {
+    /// Unix time of the collection, in seconds
+    timestamp: u64,
+    devices: Vec<GpuInfo>,
+    type_counts: HashMap<String, u32>,
+    used_count: usize,
+    #[serde(default)]
+    driver_version: String,
+    #[serde(default)]
+    cuda_version: String,
+}
+
+/// Outcome of a shared collection, `None` until it finishes
+type CollectResult = Option<Result<Arc<NodeMetrics>, String>>;
+
+/// Collection currently running, joined by concurrent callers
+type InFlight = Mutex<Option<watch::Receiver<CollectResult>>>;
+
+static IN_FLIGHT: InFlight = Mutex::new(None);
+
+/// Set by `load_gpu_cache` when a state directory is configured
+static GPU_CACHE_PATH: OnceLock<PathBuf> = OnceLock::new();
+static GPU_SETTINGS: OnceLock<GpuSettings> = OnceLock::new();
+
+lazy_static::lazy_static! {
+    static ref GPU_CACHE: RwLock<GpuCache> = RwLock::new(GpuCache::default());
+    /// Persistent System object for accurate CPU usage calculation
+    /// sysinfo requires multiple refreshes to calculate CPU usage delta
+    static ref SYSTEM: RwLock<System> = RwLock::new(System::new());
+}
+
+/// Values the GPU does not report (`[N/A]`, `[Not Supported]`) are `None`
+/// rather than 0, so they are left out instead of exported as fake readings
+/// Fields missing from a cache written by an older version keep their defaults
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(default)]
+pub struct GpuInfo {
+    pub index: u32,
+    pub name: String,
+    pub uuid: String,
+    /// e.g. `00000000:07:00.0`
+    pub pci_bus_id: String,
+    pub serial: Option<String>,
+    pub vbios_version: Option<String>,
+    /// `Enabled` or `Disabled`
+    pub persistence_mode: Option<String>,
+    /// `Default`, `Exclusive_Process` or `Prohibited`
+    pub compute_mode: Option<String>,
+    pub memory_total_mb: Option<u64>,
+    pub memory_used_mb: Option<u64>,
+    pub memory_free_mb: Option<u64>,
+    pub utilization_percent: Option<u32>,
+    /// Memory controller (bandwidth) utilization
+    pub memory_utilization_percent: Option<u32>,
+    /// NVENC utilization
+    pub encoder_utilization_percent: Option<u32>,
+    /// NVDEC utilization
+    pub decoder_utilization_percent: Option<u32>,
+    pub temperature_celsius: Option<u32>,
+    pub power_draw_watts: Option<u32>,
+    pub power_limit_watts: Option<u32>,
+    pub fan_speed_percent: Option<u32>,
+    pub graphics_clock_mhz: Option<u32>,
+    pub sm_clock_mhz: Option<u32>,
+    pub memory_clock_mhz: Option<u32>,
+    /// Current ECC mode, `Enabled` or `Disabled`
+    pub ecc_mode: Option<String>,
+    /// Corrected (single bit) ECC errors since the driver was loaded
+    pub ecc_corrected_errors: Option<u64>,
+    /// Uncorrected (double bit) ECC errors since the driver was loaded
+    pub ecc_uncorrected_errors: Option<u64>,
+    pub processes: Vec<GpuProcess>,
+}
+
+impl GpuInfo {
+    /// Whether each optional field was reported, for `hw_gpu_field_supported`
+    fn supported_fields(&self) -> [(&'static str, bool); 21] {
+        [
+            ("serial", self.serial.is_some()),
+            ("vbios_version", self.vbios_version.is_some()),
+            ("persistence_mode", self.persistence_mode.is_some()),
+            ("compute_mode", self.compute_mode.is_some()),
+            ("memory_total_mb", self.memory_total_mb.is_some()),
+            ("memory_used_mb", self.memory_used_mb.is_some()),
+            ("memory_free_mb", self.memory_free_mb.is_some()),
+            ("utilization_percent", self.utilization_percent.is_some()),
+            (
+                "memory_utilization_percent",
+                self.memory_utilization_percent.is_some(),
+            ),
+            (
+                "encoder_utilization_percent",
+                self.encoder_utilization_percent.is_some(),
+            ),
+            (
+                "decoder_utilization_percent",
+                self.decoder_utilization_percent.is_some(),
+            ),
+            ("temperature_celsius", self.temperature_celsius.is_some()),
+            ("power_draw_watts", self.power_draw_watts.is_some()),
+            ("power_limit_watts", self.power_limit_watts.is_some()),
+            ("fan_speed_percent", self.fan_speed_percent.is_some()),
+            ("graphics_clock_mhz", self.graphics_clock_mhz.is_some()),
+            ("sm_clock_mhz", self.sm_clock_mhz.is_some()),
+            ("memory_clock_mhz", self.memory_clock_mhz.is_some()),
+            ("ecc_mode", self.ecc_mode.is_some()),
+            ("ecc_corrected_errors", self.ecc_corrected_errors.is_some()),
+            (
+                "ecc_uncorrected_errors",
+                self.ecc_uncorrected_errors.is_some(),
+            ),
+        ]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct GpuProcess {
+    pub pid: u32,
+    /// `C` for compute, `G` for graphics, `C+G` for both
+    #[serde(rename = "type")]
+    pub process_type: String,
+    pub name: String,
+    pub used_memory_mb: Option<u64>,
+}
+
+impl GpuProcess {
+    fn is_compute(&self) -> bool {
+        self.process_type.contains('C')
+    }
+}
+
+/// Extracts a per-device metric value from a GPU, `None` when unavailable
+type GpuValueFn = fn(&GpuInfo) -> Option<f64>;
+
+#[derive(Debug, Serialize, Clone)]
+pub struct NodeMetrics {
+    pub hostname: String,
+    pub node: String,
+    pub os_name: String,
+    pub os_version: String,
+    pub kernel_version: String,
+    pub uptime_secs: u64,
+    pub cpu_cores: usize,
+    pub cpu_threads: usize,
+    pub cpu_model: String,
+    pub cpu_usage_percent: f32,
+    pub cpu_used_cores: f32,
+    pub memory_total_bytes: u64,
+    pub memory_used_bytes: u64,
+    pub memory_available_bytes: u64,
+    pub memory_usage_percent: f32,
+    pub gpu_count: usize,
+    pub gpu_used_count: usize,
+    pub gpu_devices: Vec<GpuInfo>,
+    pub gpu_type_counts: HashMap<String, u32>,
+    /// NVIDIA driver version, empty without GPUs
+    pub gpu_driver_version: String,
+    /// Highest CUDA version the driver supports
+    pub gpu_cuda_version: String,
+    /// GPU data served from the cache instead of a successful nvidia-smi query
+    pub gpu_data_stale: bool,
+    /// Cached GPU data older than `gpu.cache_max_age_secs`
+    pub gpu_data_expired: bool,
+    /// nvidia-smi queries failed in a row as of this collection
+    #[serde(skip)]
+    pub nvidia_smi_failures: u32,
+    /// Unix time of the last successful GPU collection, possibly from a
+    /// previous run
+    pub gpu_last_success_timestamp: Option<u64>,
+    /// Utilization and power summaries from `gpu.sampling`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub gpu_sample_stats: Vec<gpu_sampler::GpuSampleStats>,
+    /// System, board and BIOS identification
+    pub dmi: DmiInfo,
+    /// Constant labels applied to every exported series
+    pub labels: BTreeMap<String, String>,
+    /// Comparison with the expected hardware, empty when none applies
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub inventory_checks: Vec<InventoryCheck>,
+    /// Hardware changes per component since the exporter started
+    #[serde(skip)]
+    pub inventory_changes: Vec<(&'static str, u64)>,
+}
+
+impl NodeMetrics {
+    pub fn collect() -> Self {
+        Self::collect_with_gpus(collect_gpu_info)
+    }
+
+    fn collect_with_gpus(collect_gpus: impl FnOnce() -> GpuCollection) -> Self {
+        // Use persistent System object for accurate CPU usage calculation
+        // sysinfo calculates CPU usage by comparing current vs previous refresh
+        // The lock is released before nvidia-smi runs, which may hang
+        let mut sys = SYSTEM.write().unwrap();
+        sys.refresh_memory();
+        sys.refresh_cpu_all();
+
+        let memory_total = sys.total_memory();
+        let memory_used = sys.used_memory();
+        let memory_available = sys.available_memory();
+        let memory_usage_percent = if memory_total > 0 {
+            (memory_used as f32 / memory_total as f32) * 100.0
+        } else {
+            0.0
+        };
+
+        let cpu_model = sys
+            .cpus()
+            .first()
+            .map(|cpu| cpu.brand().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+
+        // Calculate CPU used cores: (usage_percent / 100) * total_threads
+        let cpu_cores = sys.physical_core_count().unwrap_or(0);
+        let cpu_threads = sys.cpus().len();
+        let cpu_usage_percent = sys.global_cpu_usage();
+        let cpu_used_cores = (cpu_usage_percent / 100.0) * cpu_threads as f32;
+        drop(sys);
+
+        let gpus = collect_gpus().with_stale_policy(gpu_settings().stale_policy);
+        let gpu_count = gpus.devices.len();
+
+        // Get host OS information from mounted /host/etc/os-release
+        let (os_name, os_version) = get_host_os_info();
+
+        NodeMetrics {
+            hostname: System::host_name().unwrap_or_else(|| "unknown".to_string()),
+            node: node_name(),
+            os_name,
+            os_version,
+            kernel_version: System::kernel_version().unwrap_or_else(|| "unknown".to_string()),
+            uptime_secs: System::uptime(),
+            cpu_cores,
+            cpu_threads,
+            cpu_model,
+            cpu_usage_percent,
+            cpu_used_cores,
+            memory_total_bytes: memory_total,
+            memory_used_bytes: memory_used,
+            memory_available_bytes: memory_available,
+            memory_usage_percent,
+            gpu_count,
+            gpu_used_count: gpus.used_count,
+            gpu_devices: gpus.devices,
+            gpu_type_counts: gpus.type_counts,
+            // The kernel module still reports the driver when nvidia-smi fails
+            gpu_driver_version: if gpus.driver_version.is_empty() {
+                nvidia_kernel_driver_version().unwrap_or_default()
+            } else {
+                gpus.driver_version
+            },
+            gpu_cuda_version: gpus.cuda_version,
+            gpu_data_stale: gpus.stale,
+            gpu_data_expired: gpus.expired,
+            nvidia_smi_failures: nvidia_smi_consecutive_failures(),
+            gpu_last_success_timestamp: gpus.last_success.map(unix_secs),
+            gpu_sample_stats: gpu_sampler::summaries(),
+            dmi: dmi_info().clone(),
+            labels: BTreeMap::new(),
+            inventory_checks: Vec::new(),
+            inventory_changes: Vec::new(),
+        }
+    }
+
+    /// Collect on the blocking thread pool, concurrent callers share the
+    /// collection already in flight instead of starting their own
+    pub async fn collect_shared() -> Result<Self, AppError> {
+        Self::collect_shared_with(
+            &IN_FLIGHT,
+            NodeMetrics::collect,
+            Duration::from_secs(COLLECT_TIMEOUT_SECS),
+        )
+        .await
+    }
+
+    async fn collect_shared_with(
+        in_flight: &'static InFlight,
+        collect: fn() -> NodeMetrics,
+        timeout: Duration,
+    ) -> Result<Self, AppError> {
+        let mut result = {
+            let mut running = in_flight.lock().unwrap();
+            match running.as_ref() {
+                Some(result) => result.clone(),
+                None => {
+                    let (sender, result) = watch::channel(None);
+                    *running = Some(result.clone());
+                    // Finishes even when every caller gives up waiting
+                    tokio::spawn(async move {
+                        let metrics = tokio::task::spawn_blocking(collect)
+                            .await
+                            .map(Arc::new)
+                            .map_err(|e| e.to_string());
+                        in_flight.lock().unwrap().take();
+                        let _ = sender.send(Some(metrics));
+                    });
+                    result
+                }
+            }
+        };
+
+        let finished = tokio::time::timeout(timeout, result.wait_for(Option::is_some))
+            .await
+            .map_err(|_| {
+                AppError::Metrics(format!(
+                    "collection did not finish within {}s",
+                    timeout.as_secs()
+                ))
+            })?
+            .map_err(|_| AppError::Metrics("collection was aborted".to_string()))?;
+
+        match finished.as_ref() {
+            Some(Ok(metrics)) => Ok(metrics.as_ref().clone()),
+            Some(Err(e)) => Err(AppError::Metrics(e.clone())),
+            None => Err(AppError::Metrics("collection was aborted".to_string())),
+        }
+    }
+
+    /// Attach the configured constant labels
+    pub fn with_labels(mut self, labels: &BTreeMap<String, String>) -> Self {
+        self.labels = labels.clone();
+        self
+    }
+
+    /// Attach the comparison with the expected hardware
+    pub fn with_inventory_checks(mut self, checker: &InventoryChecker) -> Self {
+        if checker.is_enabled() {
+            self.inventory_checks = checker.check(&self).checks;
+        }
+        self
+    }
+
+    /// Attach the hardware change counts
+    pub fn with_inventory_changes(mut self, changes: &ChangeDetector) -> Self {
+        self.inventory_changes = changes.counts();
+        self
+    }
+
+    /// Build metric families for every metric this exporter knows about
+    pub fn families(&self) -> Vec<MetricFamily> {
+        let mut families = Vec::new();
+        let node_labels = || vec![("node".to_string(), self.node.clone())];
+
+        // Node info
+        families.push(
+            MetricFamily::gauge("hw_node_info", "Node hardware information", "node").with_sample(
+                vec![
+                    ("node".to_string(), self.node.clone()),
+                    ("os".to_string(), self.os_name.clone()),
+                    ("os_version".to_string(), self.os_version.clone()),
+                    ("kernel".to_string(), self.kernel_version.clone()),
+                    ("cpu_model".to_string(), self.cpu_model.clone()),
+                ],
+                1.0,
+            ),
+        );
+
+        // DMI identification, for asset tracking
+        let dmi_labels = self.dmi.labels();
+        if !dmi_labels.is_empty() {
+            let mut labels = node_labels();
+            labels.extend(dmi_labels);
+            families.push(
+                MetricFamily::gauge(
+                    "hw_dmi_info",
+                    "System, board and BIOS identification from DMI",
+                    "node",
+                )
+                .with_sample(labels, 1.0),
+            );
+        }
+
+        // Uptime
+        families.push(
+            MetricFamily::counter("hw_node_uptime_seconds", "Node uptime in seconds", "node")
+                .with_sample(node_labels(), self.uptime_secs as f64),
+        );
+
+        // CPU
+        families.push(
+            MetricFamily::gauge("hw_cpu_cores", "Number of physical CPU cores", "cpu")
+                .with_sample(node_labels(), self.cpu_cores as f64),
+        );
+
+        families.push(
+            MetricFamily::gauge("hw_cpu_threads", "Number of CPU threads", "cpu")
+                .with_sample(node_labels(), self.cpu_threads as f64),
+        );
+
+        families.push(
+            MetricFamily::gauge("hw_cpu_usage_percent", "CPU usage percentage", "cpu")
+                .with_sample(node_labels(), round2(self.cpu_usage_percent)),
+        );
+
+        // CPU used cores: calculated as (usage_percent / 100) * total_threads
+        families.push(
+            MetricFamily::gauge(
+                "hw_cpu_used_cores",
+                "Number of CPU cores currently in use",
+                "cpu",
+            )
+            .with_sample(node_labels(), round2(self.cpu_used_cores)),
+        );
+
+        // Memory
+        families.push(
+            MetricFamily::gauge("hw_memory_total_bytes", "Total memory in bytes", "memory")
+                .with_sample(node_labels(), self.memory_total_bytes as f64),
+        );
+
+        families.push(
+            MetricFamily::gauge("hw_memory_used_bytes", "Used memory in bytes", "memory")
+                .with_sample(node_labels(), self.memory_used_bytes as f64),
+        );
+
+        families.push(
+            MetricFamily::gauge(
+                "hw_memory_available_bytes",
+                "Available memory in bytes",
+                "memory",
+            )
+            .with_sample(node_labels(), self.memory_available_bytes as f64),
+        );
+
+        families.push(
+            MetricFamily::gauge(
+                "hw_memory_usage_percent",
+                "Memory usage percentage",
+                "memory",
+            )
+            .with_sample(node_labels(), round2(self.memory_usage_percent)),
+        );
+
+        // GPU metrics only for nodes with GPUs
+        if self.gpu_count > 0 {
+            // GPU total count per node
+            families.push(
+                MetricFamily::gauge("hw_gpu_count", "Total number of GPUs per node", "gpu")
+                    .with_sample(node_labels(), self.gpu_count as f64),
+            );
+
+            // GPU used count (GPUs with running compute processes)
+            families.push(
+                MetricFamily::gauge(
+                    "hw_gpu_used_count",
+                    "Number of GPUs currently in use per node",
+                    "gpu",
+                )
+                .with_sample(node_labels(), self.gpu_used_count as f64),
+            );
+
+            // GPU type counts per node
+            let mut family = MetricFamily::gauge(
+                "hw_gpu_type_count",
+                "Number of GPUs by type per node",
+                "gpu",
+            );
+            for (gpu_type, count) in &self.gpu_type_counts {
+                let mut labels = node_labels();
+                labels.push(("gpu_type".to_string(), gpu_type.clone()));
+                family.add_sample(labels, *count as f64);
+            }
+            families.push(family);
+
+            families.push(
+                MetricFamily::gauge(
+                    "hw_gpu_data_stale",
+                    "Whether GPU metrics come from cached data because nvidia-smi failed",
+                    "gpu",
+                )
+                .with_sample(node_labels(), if self.gpu_data_stale { 1.0 } else { 0.0 }),
+            );
+
+            if let Some(timestamp) = self.gpu_last_success_timestamp {
+                families.push(
+                    MetricFamily::gauge(
+                        "hw_gpu_last_successful_collection_timestamp_seconds",
+                        "Unix time of the last successful nvidia-smi query",
+                        "gpu",
+                    )
+                    .with_sample(node_labels(), timestamp as f64),
+                );
+            }
+        }
+
+        // Driver info, also when nvidia-smi is broken but the module is loaded
+        if !self.gpu_driver_version.is_empty() {
+            let mut labels = node_labels();
+            labels.push((
+                "driver_version".to_string(),
+                self.gpu_driver_version.clone(),
+            ));
+            labels.push(("cuda_version".to_string(), self.gpu_cuda_version.clone()));
+            families.push(
+                MetricFamily::gauge("hw_gpu_driver_info", "NVIDIA driver information", "gpu")
+                    .with_sample(labels, 1.0),
+            );
+        }
+
+        // GPU device details
+        let gpu_families: [(&str, &str, GpuValueFn); 11] = [
+            (
+                "hw_gpu_memory_total_bytes",
+                "GPU total memory in bytes",
+                |gpu| gpu.memory_total_mb.map(|mb| (mb * 1024 * 1024) as f64),
+            ),
+            (
+                "hw_gpu_memory_used_bytes",
+                "GPU used memory in bytes",
+                |gpu| gpu.memory_used_mb.map(|mb| (mb * 1024 * 1024) as f64),
+            ),
+            (
+                "hw_gpu_memory_free_bytes",
+                "GPU free memory in bytes",
+                |gpu| gpu.memory_free_mb.map(|mb| (mb * 1024 * 1024) as f64),
+            ),
+            (
+                "hw_gpu_utilization_percent",
+                "GPU utilization percentage",
+                |gpu| gpu.utilization_percent.map(f64::from),
+            ),
+            (
+                "hw_gpu_memory_utilization_percent",
+                "GPU memory controller utilization percentage",
+                |gpu| gpu.memory_utilization_percent.map(f64::from),
+            ),
+            (
+                "hw_gpu_encoder_utilization_percent",
+                "GPU encoder (NVENC) utilization percentage",
+                |gpu| gpu.encoder_utilization_percent.map(f64::from),
+            ),
+            (
+                "hw_gpu_decoder_utilization_percent",
+                "GPU decoder (NVDEC) utilization percentage",
+                |gpu| gpu.decoder_utilization_percent.map(f64::from),
+            ),
+            (
+                "hw_gpu_temperature_celsius",
+                "GPU temperature in Celsius",
+                |gpu| gpu.temperature_celsius.map(f64::from),
+            ),
+            (
+                "hw_gpu_power_draw_watts",
+                "GPU power draw in watts",
+                |gpu| gpu.power_draw_watts.map(f64::from),
+            ),
+            (
+                "hw_gpu_power_limit_watts",
+                "GPU power limit in watts",
+                |gpu| gpu.power_limit_watts.map(f64::from),
+            ),
+            (
+                "hw_gpu_fan_speed_percent",
+                "GPU fan speed percentage",
+                |gpu| gpu.fan_speed_percent.map(f64::from),
+            ),
+        ];
+
+        for (name, help, value) in gpu_families {
+            let mut family = MetricFamily::gauge(name, help, "gpu");
+            for gpu in &self.gpu_devices {
+                if let Some(value) = value(gpu) {
+                    family.add_sample(self.gpu_labels(gpu), value);
+                }
+            }
+            families.push(family);
+        }
+
+        // Sampled summaries, kept apart from the instantaneous values so
+        // aggregations over those are not mixed with the stat series
+        let mut utilization = MetricFamily::gauge(
+            "hw_gpu_utilization_sampled_percent",
+            "GPU utilization over the sampling window by stat",
+            "gpu",
+        );
+        let mut power_draw = MetricFamily::gauge(
+            "hw_gpu_power_draw_sampled_watts",
+            "GPU power draw in watts over the sampling window by stat",
+            "gpu",
+        );
+        for stats in &self.gpu_sample_stats {
+            let Some(gpu) = self.gpu_devices.iter().find(|gpu| gpu.index == stats.index) else {
+                continue;
+            };
+            for (family, summary) in [
+                (&mut utilization, &stats.utilization_percent),
+                (&mut power_draw, &stats.power_draw_watts),
+            ] {
+                let Some(summary) = summary else {
+                    continue;
+                };
+                for (stat, value) in summary.stats() {
+                    let mut labels = self.gpu_labels(gpu);
+                    labels.push(("stat".to_string(), stat.to_string()));
+                    family.add_sample(labels, value);
+                }
+            }
+        }
+        families.push(utilization);
+        families.push(power_draw);
+
+        if !self.gpu_devices.is_empty() {
+            let mut family = MetricFamily::gauge("hw_gpu_info", "GPU device information", "gpu");
+            for gpu in &self.gpu_devices {
+                let mut labels = self.gpu_labels(gpu);
+                for (name, value) in [
+                    ("vbios_version", &gpu.vbios_version),
+                    ("serial", &gpu.serial),
+                    ("persistence_mode", &gpu.persistence_mode),
+                    ("compute_mode", &gpu.compute_mode),
+                ] {
+                    labels.push((name.to_string(), value.clone().unwrap_or_default()));
+                }
+                labels.push(("pci_bus_id".to_string(), gpu.pci_bus_id.clone()));
+                family.add_sample(labels, 1.0);
+            }
+            families.push(family);
+        }
+
+        // Unavailable values are left out above, tell them apart from
+        // missing devices
+        if !self.gpu_devices.is_empty() {
+            let mut family = MetricFamily::gauge(
+                "hw_gpu_field_supported",
+                "Whether the GPU reports a field, 0 when nvidia-smi shows N/A or Not Supported",
+                "gpu",
+            );
+            for gpu in &self.gpu_devices {
+                for (field, supported) in gpu.supported_fields() {
+                    let mut labels = self.gpu_labels(gpu);
+                    labels.push(("field".to_string(), field.to_string()));
+                    family.add_sample(labels, if supported { 1.0 } else { 0.0 });
+                }
+            }
+            families.push(family);
+        }
+
+        // Expected hardware checks, 1 when the collected value differs
+        if !self.inventory_checks.is_empty() {
+            let mut family = MetricFamily::gauge(
+                "hw_inventory_mismatch",
+                "Whether a hardware property differs from the expected inventory",
+                "inventory",
+            );
+            for check in &self.inventory_checks {
+                let mut labels = node_labels();
+                labels.push(("component".to_string(), check.component.to_string()));
+                labels.push(("expected".to_string(), check.expected.clone()));
+                labels.push(("actual".to_string(), check.actual.clone()));
+                family.add_sample(labels, if check.matches { 0.0 } else { 1.0 });
+            }
+            families.push(family);
+        }
+
+        if !self.inventory_changes.is_empty() {
+            let mut family = MetricFamily::counter(
+                "hw_inventory_changes_total",
+                "Hardware changes detected since the exporter started",
+                "inventory",
+            );
+            for (component, count) in &self.inventory_changes {
+                let mut labels = node_labels();
+                labels.push(("component".to_string(), component.to_string()));
+                family.add_sample(labels, *count as f64);
+            }
+            families.push(family);
+        }
+
+        families
+    }
+
+    /// Metric families allowed by the configured selector
+    pub fn selected_families(&self, selector: &MetricSelector) -> Vec<MetricFamily> {
+        self.families()
+            .into_iter()
+            .filter(|family| selector.allows(&family.name, family.collector))
+            .collect()
+    }
+
+    /// Labels identifying a GPU device, marked stale once cached data expired
+    fn gpu_labels(&self, gpu: &GpuInfo) -> Vec<(String, String)> {
+        let mut labels = vec![
+            ("node".to_string(), self.node.clone()),
+            ("gpu_index".to_string(), gpu.index.to_string()),
+            ("gpu_name".to_string(), gpu.name.clone()),
+            ("gpu_uuid".to_string(), gpu.uuid.clone()),
+        ];
+        if self.gpu_data_expired {
+            labels.push(("stale".to_string(), "true".to_string()));
+        }
+        labels
+    }
+}
+
+#[cfg(test)]
+impl NodeMetrics {
+    /// Collection querying `nvidia_smi` as if an NVIDIA driver were loaded
+    pub fn collect_with_nvidia_smi(nvidia_smi: &str) -> Self {
+        Self::collect_with_gpus(|| query_gpu_info(Some(nvidia_smi.to_string())))
+    }
+}
+
+/// Round a percentage-style value to two decimal places
+fn round2(value: f32) -> f64 {
+    (value as f64 * 100.0).round() / 100.0
+}
+
+/// Parse /etc/os-release file to get OS name and version
+/// Returns (os_name, os_version)
+fn parse_os_release(path: &str) -> Option<(String, String)> {
+    let content = fs::read_to_string(path).ok()?;
+
+    let mut name = None;
+    let mut version = None;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if let Some(value) = line.strip_prefix("NAME=") {
+            name = Some(value.trim_matches('"').to_string());
+        } else if let Some(value) = line.strip_prefix("VERSION_ID=") {
+            version = Some(value.trim_matches('"').to_string());
+        }
+
+        if name.is_some() && version.is_some() {
+            break;
+        }
+    }
+
+    match (name, version) {
+        (Some(n), Some(v)) => Some((n, v)),
+        _ => None,
+    }
+}
+
+/// Get host OS information, trying host mount first, then falling back to container OS
+fn get_host_os_info() -> (String, String) {
+    // Try to read from host mount first
+    if let Some((name, version)) = parse_os_release("/host/etc/os-release") {
+        return (name, version);
+    }
+
+    // Fallback to sysinfo for container OS
+    let os_name = System::name().unwrap_or_else(|| "unknown".to_string());
+    let os_version = System::os_version().unwrap_or_else(|| "unknown".to_string());
+
+    (os_name, os_version)
+}
+
+/// Node name from the NODE_NAME env variable, falling back to the hostname
+pub fn node_name() -> String {
+    std::env::var("NODE_NAME")
+        .ok()
+        .or_else(System::host_name)
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Check if NVIDIA GPU hardware is present
+/// Uses /host/proc/driver/nvidia/version which is mounted from host
+pub fn has_nvidia_gpu() -> bool {
+    let path = "/host/proc/driver/nvidia/version";
+    let exists = std::path::Path::new(path).exists();
+    if exists {
+        info!("NVIDIA GPU driver detected at {}", path);
+    }
+    exists
+}
+
+/// Driver version of the loaded NVIDIA kernel module, e.g. from
+/// `NVRM version: NVIDIA UNIX x86_64 Kernel Module  535.104.05  Sat Aug 19 ...`
+fn nvidia_kernel_driver_version() -> Option<String> {
+    let content = fs::read_to_string("/host/proc/driver/nvidia/version").ok()?;
+    let line = content
+        .lines()
+        .find(|line| line.starts_with("NVRM version:"))?;
+    line.split_whitespace()
+        .find(|word| word.contains('.') && word.chars().all(|c| c.is_ascii_digit() || c == '.'))
+        .map(str::to_string)
+}
+
+/// GPU data of one collection
+#[derive(Default)]
+struct GpuCollection {
+    devices: Vec<GpuInfo>,
+    type_counts: HashMap<String, u32>,
+    used_count: usize,
+    driver_version: String,
+    cuda_version: String,
+    /// Served from the cache rather than a successful query
+    stale: bool,
+    /// Cache older than the configured max age
+    expired: bool,
+    last_success: Option<SystemTime>,
+}
+
+impl GpuCollection {
+    /// Expired cached data is discarded under the drop policy, keeping only
+    /// the flags that tell it was dropped
+    fn with_stale_policy(mut self, policy: StalePolicy) -> Self {
+        if self.expired && policy == StalePolicy::Drop {
+            self.devices.clear();
+            self.type_counts.clear();
+            self.used_count = 0;
+        }
+        self
+    }
+}
+
+/// Collect GPU information using nvidia-smi command
+/// Uses caching to prevent data loss when nvidia-smi hangs or fails
+fn collect_gpu_info() -> GpuCollection {
+    // Early return if no NVIDIA GPU hardware detected
+    if !has_nvidia_gpu() {
+        info!("No NVIDIA GPU hardware detected, skipping GPU metrics collection");
+        return GpuCollection::default();
+    }
+
+    query_gpu_info(nvidia_smi::find())
+}
+
+/// Query the GPUs with `nvidia_smi`, falling back to the cache when it is
+/// missing or fails
+fn query_gpu_info(nvidia_smi: Option<String>) -> GpuCollection {
+    let Some(path) = nvidia_smi else {
+        info!("nvidia-smi not found, skipping GPU metrics collection");
+        record_nvidia_smi_failure();
+        return GpuCollection::default();
+    };
+
+    // A single XML query reports every field, including the processes
+    let timeout = Duration::from_secs(gpu_settings().timeout_secs);
+    let log = match nvidia_smi::run(&path, &["-q", "-x"], timeout)
+        .map(|output| nvidia_xml::parse(&output))
+    {
+        Some(Ok(log)) => log,
+        Some(Err(e)) => {
+            warn!(
+                "Failed to parse nvidia-smi XML output: {}, using cached data",
+                e
+            );
+            record_nvidia_smi_failure();
+            return get_cached_gpu_info();
+        }
+        None => {
+            warn!("Failed to get GPU metrics from nvidia-smi, using cached data");
+            record_nvidia_smi_failure();
+            return get_cached_gpu_info();
+        }
+    };
+    let gpu_devices = log.gpus;
+
+    if gpu_devices.is_empty() {
+        warn!("nvidia-smi returned no GPU data, using cached data");
+        record_nvidia_smi_failure();
+        return get_cached_gpu_info();
+    }
+
+    // Count GPU types
+    let mut gpu_type_counts: HashMap<String, u32> = HashMap::new();
+    for gpu in &gpu_devices {
+        *gpu_type_counts.entry(gpu.name.clone()).or_insert(0) += 1;
+    }
+
+    // GPUs with running compute processes
+    let gpu_used_count = gpu_devices
+        .iter()
+        .filter(|gpu| gpu.processes.iter().any(GpuProcess::is_compute))
+        .count();
+
+    info!(
+        "Collected metrics for {} GPU(s), {} in use",
+        gpu_devices.len(),
+        gpu_used_count
+    );
+
+    // Update cache with successful data
+    if let Ok(mut cache) = GPU_CACHE.write() {
+        cache.devices = gpu_devices.clone();
+        cache.type_counts = gpu_type_counts.clone();
+        cache.used_count = gpu_used_count;
+        cache.driver_version = log.driver_version.clone();
+        cache.cuda_version = log.cuda_version.clone();
+        cache.last_update = SystemTime::now();
+        cache.last_success = true;
+        cache.consecutive_failures = 0;
+        cache.from_disk = false;
+        save_gpu_cache(&cache);
+    }
+
+    GpuCollection {
+        devices: gpu_devices,
+        type_counts: gpu_type_counts,
+        used_count: gpu_used_count,
+        driver_version: log.driver_version,
+        cuda_version: log.cuda_version,
+        stale: false,
+        expired: false,
+        last_success: Some(SystemTime::now()),
+    }
+}
+
+fn record_nvidia_smi_failure() {
+    if let Ok(mut cache) = GPU_CACHE.write() {
+        cache.last_success = false;
+        cache.consecutive_failures += 1;
+    }
+}
+
+/// Number of GPU collections in a row where nvidia-smi was missing or failed
+pub fn nvidia_smi_consecutive_failures() -> u32 {
+    GPU_CACHE
+        .read()
+        .map(|cache| cache.consecutive_failures)
+        .unwrap_or(0)
+}
+
+/// Apply the `[gpu]` settings, defaults are used until this is called
+pub fn configure_gpu(settings: &GpuSettings) {
+    let _ = GPU_SETTINGS.set(settings.clone());
+}
+
+fn gpu_settings() -> &'static GpuSettings {
+    GPU_SETTINGS.get_or_init(GpuSettings::default)
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Load the GPU cache persisted by a previous run, so a restart while
+/// nvidia-smi hangs keeps reporting the last known GPUs, marked stale
+pub fn load_gpu_cache(state_dir: Option<&str>) {
+    let Some(dir) = state_dir else {
+        return;
+    };
+    let path = GPU_CACHE_PATH.get_or_init(|| Path::new(dir).join(GPU_CACHE_FILE));
+
+    let Some(persisted) = read_gpu_cache(path) else {
+        return;
+    };
+    if let Ok(mut cache) = GPU_CACHE.write() {
+        cache.restore(persisted);
+    }
+}
+
+fn read_gpu_cache(path: &Path) -> Option<PersistedGpuCache> {
+    let content = match fs::read(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let persisted: PersistedGpuCache = match serde_json::from_slice(&content) {
+        Ok(persisted) => persisted,
+        Err(e) => {
+            warn!("Ignoring invalid GPU cache {}: {}", path.display(), e);
+            return None;
+        }
+    };
+
+    info!(
+        "Loaded cached data for {} GPU(s) from {}",
+        persisted.devices.len(),
+        path.display()
+    );
+    Some(persisted)
+}
+
+/// Persist the cache after a successful collection, when a state directory
+/// is configured
+fn save_gpu_cache(cache: &GpuCache) {
+    let Some(path) = GPU_CACHE_PATH.get() else {
+        return;
+    };
+    if let Err(e) = write_gpu_cache(path, &cache.persisted()) {
+        warn!("Failed to write {}: {}", path.display(), e);
+    }
+}
+
+/// Write through a temporary file so a crash never leaves a truncated cache
+fn write_gpu_cache(path: &Path, persisted: &PersistedGpuCache) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(persisted)?)?;
+    fs::rename(&tmp, path)
+}
+
+/// Get cached GPU info, expired once older than `gpu.cache_max_age_secs`
+fn get_cached_gpu_info() -> GpuCollection {
+    if let Ok(cache) = GPU_CACHE.read() {
+        cached_gpu_info(&cache, gpu_settings().cache_max_age_secs)
+    } else {
+        warn!("Failed to read GPU cache");
+        GpuCollection::default()
+    }
+}
+
+fn cached_gpu_info(cache: &GpuCache, max_age_secs: u64) -> GpuCollection {
+    let age_secs = cache.last_update.elapsed().unwrap_or_default().as_secs();
+    let expired = age_secs > max_age_secs;
+
+    if cache.devices.is_empty() {
+        warn!("No cached GPU data available");
+        return GpuCollection::default();
+    }
+
+    if expired {
+        warn!(
+            "GPU cache data expired ({}s old, max {}s), applying {:?} policy",
+            age_secs,
+            max_age_secs,
+            gpu_settings().stale_policy
+        );
+    } else {
+        info!(
+            "Using cached GPU data ({}s old) for {} GPU(s)",
+            age_secs,
+            cache.devices.len()
+        );
+    }
+    if cache.from_disk {
+        warn!("No GPU collection has succeeded since startup, serving data from the previous run");
+    }
+
+    GpuCollection {
+        devices: cache.devices.clone(),
+        type_counts: cache.type_counts.clone(),
+        used_count: cache.used_count,
+        driver_version: cache.driver_version.clone(),
+        cuda_version: cache.cuda_version.clone(),
+        stale: true,
+        expired,
+        last_success: Some(cache.last_update),
+    }
+}
+
+// Keep the old struct for backward compatibility
+#[derive(Debug, Serialize)]
+pub struct SystemMetrics {
+    pub cpu_usage: f32,
+    pub memory_total: u64,
+    pub memory_used: u64,
+    pub memory_usage_percent: f32,
+    pub hostname: String,
+    pub os_name: String,
+    pub os_version: String,
+    pub uptime: u64,
+    /// Constant labels, as on the other endpoints
+    pub labels: BTreeMap<String, String>,
+}
+
+impl From<NodeMetrics> for SystemMetrics {
+    fn from(node: NodeMetrics) -> Self {
+        SystemMetrics {
+            cpu_usage: node.cpu_usage_percent,
+            memory_total: node.memory_total_bytes,
+            memory_used: node.memory_used_bytes,
+            memory_usage_percent: node.memory_usage_percent,
+            hostname: node.hostname,
+            os_name: node.os_name,
+            os_version: node.os_version,
+            uptime: node.uptime_secs,
+            labels: node.labels,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn gpu(index: u32, name: &str) -> GpuInfo {
+        GpuInfo {
+            index,
+            name: name.to_string(),
+            uuid: format!("GPU-{}", index),
+            ..Default::default()
+        }
+    }
+
+    fn cache(last_update: SystemTime) -> GpuCache {
+        GpuCache {
+            devices: vec![
+                gpu(0, "NVIDIA H100 80GB HBM3"),
+                gpu(1, "NVIDIA H100 80GB HBM3"),
+            ],
+            type_counts: HashMap::from([("NVIDIA H100 80GB HBM3".to_string(), 2)]),
+            used_count: 1,
+            driver_version: "550.54.15".to_string(),
+            cuda_version: "12.4".to_string(),
+            last_update,
+            last_success: true,
+            consecutive_failures: 0,
+            from_disk: false,
+        }
+    }
+
+    fn sample(families: &[MetricFamily], name: &str) -> Option<f64> {
+        families
+            .iter()
+            .find(|family| family.name == name)
+            .map(|family| family.samples[0].value)
+    }
+
+    #[test]
+    fn gpu_query_runs_without_the_system_lock() {
+        let (entered, wait_entered) = mpsc::channel();
+        let (release, wait_release) = mpsc::channel::<()>();
+        let hanging = thread::spawn(move || {
+            NodeMetrics::collect_with_gpus(move || {
+                entered.send(()).unwrap();
+                wait_release.recv().unwrap();
+                GpuCollection::default()
+            })
+        });
+        wait_entered.recv().unwrap();
+
+        // Another collection must not wait for the hanging GPU query
+        let (done, wait_done) = mpsc::channel();
+        thread::spawn(move || {
+            NodeMetrics::collect_with_gpus(GpuCollection::default);
+            done.send(()).unwrap();
+        });
+        let finished = wait_done.recv_timeout(Duration::from_secs(10));
+
+        release.send(()).unwrap();
+        hanging.join().unwrap();
+        assert!(finished.is_ok());
+    }
+
+    static COLLECTIONS: AtomicUsize = AtomicUsize::new(0);
+
+    fn slow_collect() -> NodeMetrics {
+        COLLECTIONS.fetch_add(1, Ordering::SeqCst);
+        thread::sleep(Duration::from_millis(300));
+        NodeMetrics::collect_with_gpus(GpuCollection::default)
+    }
+
+    #[tokio::test]
+    async fn concurrent_callers_share_one_collection() {
+        static IN_FLIGHT: InFlight = Mutex::new(None);
+        let before = COLLECTIONS.load(Ordering::SeqCst);
+
+        let callers: Vec<_> = (0..4)
+            .map(|_| {
+                tokio::spawn(NodeMetrics::collect_shared_with(
+                    &IN_FLIGHT,
+                    slow_collect,
+                    Duration::from_secs(10),
+                ))
+            })
+            .collect();
+        for caller in callers {
+            assert!(caller.await.unwrap().is_ok());
+        }
+
+        assert_eq!(COLLECTIONS.load(Ordering::SeqCst) - before, 1);
+        assert!(IN_FLIGHT.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn timed_out_collection_is_not_joined_once_finished() {
+        static IN_FLIGHT: InFlight = Mutex::new(None);
+
+        let result =
+            NodeMetrics::collect_shared_with(&IN_FLIGHT, slow_collect, Duration::from_millis(50))
+                .await;
+        assert!(matches!(result, Err(AppError::Metrics(e)) if e.contains("did not finish")));
+        // Still running, later callers join it
+        assert!(IN_FLIGHT.lock().unwrap().is_some());
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(IN_FLIGHT.lock().unwrap().is_none());
+        let result =
+            NodeMetrics::collect_shared_with(&IN_FLIGHT, slow_collect, Duration::from_secs(10))
+                .await;
+        assert!(result.is_ok());
+    }
+
+    /// Exposition of the GPUs an nvidia-smi fixture reports
+    fn fixture_exposition(xml: &str) -> String {
+        let log = nvidia_xml::parse(xml).unwrap();
+        let mut metrics = NodeMetrics::collect_with_gpus(move || GpuCollection {
+            devices: log.gpus,
+            driver_version: log.driver_version,
+            cuda_version: log.cuda_version,
+            ..Default::default()
+        });
+        metrics.node = "gpu-a-1".to_string();
+        metrics.gpu_sample_stats.clear();
+        exposition::encode_text(&metrics.families(), &BTreeMap::new())
+    }
+
+    /// Series of one family, without the HELP and TYPE lines
+    fn series<'a>(text: &'a str, name: &str) -> Vec<&'a str> {
+        text.lines()
+            .filter(|line| {
+                line.strip_prefix(name)
+                    .is_some_and(|rest| rest.starts_with('{') || rest.starts_with(' '))
+            })
+            .collect()
+    }
+
+    const R470: &str = include_str!("../../tests/fixtures/nvidia-smi-r470.xml");
+    const R535: &str = include_str!("../../tests/fixtures/nvidia-smi-r535.xml");
+    const R550: &str = include_str!("../../tests/fixtures/nvidia-smi-r550.xml");
+
+    #[test]
+    fn exports_driver_info_from_fixtures() {
+        for (xml, driver, cuda) in [
+            (R470, "470.82.01", "11.4"),
+            (R535, "535.129.03", "12.2"),
+            (R550, "550.54.15", "12.4"),
+        ] {
+            assert_eq!(
+                series(&fixture_exposition(xml), "hw_gpu_driver_info"),
+                [format!(
+                    "hw_gpu_driver_info{{node=\"gpu-a-1\",driver_version=\"{}\",cuda_version=\"{}\"}} 1",
+                    driver, cuda
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn exports_gpu_info_from_fixtures() {
+        assert_eq!(
+            series(&fixture_exposition(R470), "hw_gpu_info"),
+            [concat!(
+                "hw_gpu_info{node=\"gpu-a-1\",gpu_index=\"0\",gpu_name=\"Tesla V100-PCIE-32GB\",",
+                "gpu_uuid=\"GPU-5a1c2d3e-0000-1111-2222-333344445555\",vbios_version=\"88.00.43.00.01\",",
+                "serial=\"0323218012345\",persistence_mode=\"Enabled\",compute_mode=\"Default\",",
+                "pci_bus_id=\"00000000:3B:00.0\"} 1"
+            )]
+        );
+        assert_eq!(
+            series(&fixture_exposition(R535), "hw_gpu_info"),
+            [
+                concat!(
+                    "hw_gpu_info{node=\"gpu-a-1\",gpu_index=\"0\",gpu_name=\"NVIDIA A100-SXM4-80GB\",",
+                    "gpu_uuid=\"GPU-aaaa\",vbios_version=\"92.00.45.00.05\",serial=\"1324021000001\",",
+                    "persistence_mode=\"Enabled\",compute_mode=\"Default\",pci_bus_id=\"00000000:07:00.0\"} 1"
+                ),
+                concat!(
+                    "hw_gpu_info{node=\"gpu-a-1\",gpu_index=\"1\",gpu_name=\"NVIDIA A100-SXM4-80GB\",",
+                    "gpu_uuid=\"GPU-bbbb\",vbios_version=\"92.00.45.00.05\",serial=\"1324021000002\",",
+                    "persistence_mode=\"Disabled\",compute_mode=\"Exclusive_Process\",",
+                    "pci_bus_id=\"00000000:0F:00.0\"} 1"
+                ),
+            ]
+        );
+        // The serial is [N/A] on this board, left empty rather than dropped
+        assert_eq!(
+            series(&fixture_exposition(R550), "hw_gpu_info"),
+            [concat!(
+                "hw_gpu_info{node=\"gpu-a-1\",gpu_index=\"0\",gpu_name=\"NVIDIA H100 80GB HBM3\",",
+                "gpu_uuid=\"GPU-cccc\",vbios_version=\"96.00.74.00.11\",serial=\"\",",
+                "persistence_mode=\"Enabled\",compute_mode=\"Default\",pci_bus_id=\"00000000:18:00.0\"} 1"
+            )]
+        );
+    }
+
+    /// `(gpu_index, value)` of each series of a per-GPU family
+    fn gpu_values(text: &str, name: &str) -> Vec<(u32, f64)> {
+        series(text, name)
+            .into_iter()
+            .map(|line| {
+                let index = line.split("gpu_index=\"").nth(1).unwrap();
+                let index = index[..index.find('"').unwrap()].parse().unwrap();
+                let value = line.rsplit(' ').next().unwrap().parse().unwrap();
+                (index, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exports_fan_codec_and_memory_controller_families_from_fixtures() {
+        let fan = "hw_gpu_fan_speed_percent";
+        let encoder = "hw_gpu_encoder_utilization_percent";
+        let decoder = "hw_gpu_decoder_utilization_percent";
+        let memory = "hw_gpu_memory_utilization_percent";
+
+        // Passive V100 without a fan, idle codecs report 0
+        let r470 = fixture_exposition(R470);
+        assert!(!r470.contains(fan));
+        assert_eq!(gpu_values(&r470, encoder), [(0, 0.0)]);
+        assert_eq!(gpu_values(&r470, decoder), [(0, 0.0)]);
+        assert_eq!(
+            series(&r470, memory),
+            [concat!(
+                "hw_gpu_memory_utilization_percent{node=\"gpu-a-1\",gpu_index=\"0\",",
+                "gpu_name=\"Tesla V100-PCIE-32GB\",",
+                "gpu_uuid=\"GPU-5a1c2d3e-0000-1111-2222-333344445555\"} 40"
+            )]
+        );
+
+        // A100 codecs are N/A, the second GPU does not report memory_util
+        let r535 = fixture_exposition(R535);
+        assert!(!r535.contains(fan));
+        assert!(!r535.contains(encoder));
+        assert!(!r535.contains(decoder));
+        assert_eq!(gpu_values(&r535, memory), [(0, 0.0)]);
+
+        let r550 = fixture_exposition(R550);
+        assert_eq!(gpu_values(&r550, fan), [(0, 35.0)]);
+        assert!(!r550.contains(encoder));
+        assert!(!r550.contains(decoder));
+        assert_eq!(gpu_values(&r550, memory), [(0, 3.0)]);
+    }
+
+    #[test]
+    fn sampled_summaries_are_separate_families() {
+        let summary = |min, avg, max, p95| gpu_sampler::SampleSummary {
+            min,
+            avg,
+            max,
+            p95,
+            samples: 15,
+        };
+        let mut metrics = NodeMetrics::collect_with_gpus(|| GpuCollection {
+            devices: vec![GpuInfo {
+                utilization_percent: Some(40),
+                power_draw_watts: Some(210),
+                ..gpu(0, "NVIDIA H100 80GB HBM3")
+            }],
+            ..Default::default()
+        });
+        metrics.node = "gpu-a-1".to_string();
+        metrics.gpu_sample_stats = vec![
+            gpu_sampler::GpuSampleStats {
+                index: 0,
+                utilization_percent: Some(summary(0.0, 55.0, 100.0, 100.0)),
+                power_draw_watts: None,
+            },
+            // No longer reported by nvidia-smi
+            gpu_sampler::GpuSampleStats {
+                index: 7,
+                utilization_percent: Some(summary(1.0, 1.0, 1.0, 1.0)),
+                power_draw_watts: None,
+            },
+        ];
+        let text = exposition::encode_text(&metrics.families(), &BTreeMap::new());
+
+        assert_eq!(gpu_values(&text, "hw_gpu_utilization_percent"), [(0, 40.0)]);
+        assert_eq!(gpu_values(&text, "hw_gpu_power_draw_watts"), [(0, 210.0)]);
+        let labels = "node=\"gpu-a-1\",gpu_index=\"0\",gpu_name=\"NVIDIA H100 80GB HBM3\",gpu_uuid=\"GPU-0\"";
+        assert_eq!(
+            series(&text, "hw_gpu_utilization_sampled_percent"),
+            [
+                format!(
+                    "hw_gpu_utilization_sampled_percent{{{},stat=\"min\"}} 0",
+                    labels
+                ),
+                format!(
+                    "hw_gpu_utilization_sampled_percent{{{},stat=\"avg\"}} 55",
+                    labels
+                ),
+                format!(
+                    "hw_gpu_utilization_sampled_percent{{{},stat=\"max\"}} 100",
+                    labels
+                ),
+                format!(
+                    "hw_gpu_utilization_sampled_percent{{{},stat=\"p95\"}} 100",
+                    labels
+                ),
+            ]
+        );
+        assert!(!text.contains("hw_gpu_power_draw_sampled_watts"));
+    }
+
+    #[test]
+    fn unsupported_fields_are_null_and_not_exported() {
+        let mut metrics = NodeMetrics::collect_with_gpus(|| GpuCollection {
+            devices: vec![GpuInfo {
+                temperature_celsius: Some(35),
+                power_draw_watts: Some(0),
+                ..gpu(0, "NVIDIA H100 80GB HBM3")
+            }],
+            ..Default::default()
+        });
+        metrics.node = "gpu-a-1".to_string();
+
+        let json = serde_json::to_value(&metrics).unwrap();
+        let device = &json["gpu_devices"][0];
+        assert_eq!(device["fan_speed_percent"], serde_json::Value::Null);
+        assert_eq!(device["power_limit_watts"], serde_json::Value::Null);
+        assert_eq!(device["power_draw_watts"], 0);
+
+        let text = exposition::encode_text(&metrics.families(), &BTreeMap::new());
+        assert!(!text.contains("hw_gpu_fan_speed_percent"));
+        assert!(!text.contains("hw_gpu_power_limit_watts"));
+        // A reading of 0 is still a reading
+        assert!(text.contains(
+            "hw_gpu_power_draw_watts{node=\"gpu-a-1\",gpu_index=\"0\",gpu_name=\"NVIDIA H100 80GB HBM3\",gpu_uuid=\"GPU-0\"} 0\n"
+        ));
+
+        let supported = metrics
+            .families()
+            .into_iter()
+            .find(|f| f.name == "hw_gpu_field_supported")
+            .unwrap();
+        let field = |name: &str| {
+            supported
+                .samples
+                .iter()
+                .find(|s| s.labels.contains(&("field".to_string(), name.to_string())))
+                .map(|s| s.value)
+        };
+        assert_eq!(field("fan_speed_percent"), Some(0.0));
+        assert_eq!(field("power_draw_watts"), Some(1.0));
+        // Not part of the default selection
+        let selected = metrics.selected_families(&MetricSelector::default());
+        assert!(selected.iter().all(|f| f.name != "hw_gpu_field_supported"));
+    }
+
+    #[test]
+    fn gpu_cache_is_written_atomically_and_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join(GPU_CACHE_FILE);
+        let written = cache(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+
+        write_gpu_cache(&path, &written.persisted()).unwrap();
+
+        assert!(path.exists());
+        assert!(!path.with_extension("json.tmp").exists());
+
+        // As after a restart
+        let mut restored = GpuCache::default();
+        restored.restore(read_gpu_cache(&path).unwrap());
+        assert!(restored.from_disk);
+        assert_eq!(restored.devices, written.devices);
+        assert_eq!(restored.type_counts, written.type_counts);
+        assert_eq!(restored.used_count, 1);
+        assert_eq!(restored.driver_version, "550.54.15");
+        assert_eq!(restored.cuda_version, "12.4");
+        assert_eq!(restored.last_update, written.last_update);
+
+        // A later write replaces the previous cache
+        let mut updated = written;
+        updated.devices.truncate(1);
+        write_gpu_cache(&path, &updated.persisted()).unwrap();
+        assert_eq!(read_gpu_cache(&path).unwrap().devices.len(), 1);
+    }
+
+    #[test]
+    fn missing_or_invalid_gpu_cache_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(GPU_CACHE_FILE);
+        assert!(read_gpu_cache(&path).is_none());
+
+        fs::write(&path, "{\"timestamp\":").unwrap();
+        assert!(read_gpu_cache(&path).is_none());
+    }
+
+    #[test]
+    fn cache_from_an_older_version_loads_with_defaults() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(GPU_CACHE_FILE);
+        fs::write(
+            &path,
+            r#"{"timestamp":1700000000,"devices":[{"index":0,"name":"Tesla V100-SXM2-32GB"}],"type_counts":{},"used_count":0}"#,
+        )
+        .unwrap();
+
+        let persisted = read_gpu_cache(&path).unwrap();
+        assert_eq!(persisted.driver_version, "");
+        assert_eq!(persisted.devices[0].name, "Tesla V100-SXM2-32GB");
+        assert_eq!(persisted.devices[0].memory_total_mb, None);
+    }
+
+    #[test]
+    fn cached_gpus_are_reported_stale() {
+        let restored = cache(SystemTime::now());
+
+        let gpus = cached_gpu_info(&restored, 300);
+        assert!(gpus.stale);
+        assert!(!gpus.expired);
+        assert_eq!(gpus.devices.len(), 2);
+
+        let metrics = NodeMetrics::collect_with_gpus(move || gpus);
+        assert!(metrics.gpu_data_stale);
+        assert_eq!(metrics.gpu_count, 2);
+        let families = metrics.families();
+        assert_eq!(sample(&families, "hw_gpu_data_stale"), Some(1.0));
+        assert_eq!(sample(&families, "hw_gpu_count"), Some(2.0));
+
+        let expired = cache(SystemTime::now() - Duration::from_secs(301));
+        assert!(cached_gpu_info(&expired, 300).expired);
+    }
+
+    fn expired() -> GpuCollection {
+        cached_gpu_info(&cache(SystemTime::now() - Duration::from_secs(301)), 300)
+    }
+
+    #[test]
+    fn drop_policy_discards_expired_gpus() {
+        let dropped = expired().with_stale_policy(StalePolicy::Drop);
+        assert!(dropped.devices.is_empty());
+        assert!(dropped.type_counts.is_empty());
+        assert_eq!(dropped.used_count, 0);
+        assert!(dropped.stale && dropped.expired);
+        assert_eq!(dropped.driver_version, "550.54.15");
+
+        let metrics = NodeMetrics::collect_with_gpus(move || dropped);
+        assert_eq!(metrics.gpu_count, 0);
+        assert!(metrics.gpu_devices.is_empty());
+        assert!(metrics.gpu_data_expired);
+        let families = metrics.families();
+        let exported: Vec<&str> = families
+            .iter()
+            .filter(|f| f.collector == "gpu" && !f.samples.is_empty())
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(exported, ["hw_gpu_driver_info"]);
+
+        // Data within the max age is kept under either policy
+        let fresh = cached_gpu_info(&cache(SystemTime::now()), 300);
+        assert_eq!(fresh.with_stale_policy(StalePolicy::Drop).devices.len(), 2);
+    }
+
+    #[test]
+    fn mark_policy_labels_expired_devices_stale() {
+        let marked = expired().with_stale_policy(StalePolicy::Mark);
+        assert_eq!(marked.devices.len(), 2);
+
+        let mut metrics = NodeMetrics::collect_with_gpus(move || marked);
+        metrics.gpu_devices[0].temperature_celsius = Some(41);
+        assert_eq!(metrics.gpu_count, 2);
+        let families = metrics.families();
+
+        let temperature = families
+            .iter()
+            .find(|f| f.name == "hw_gpu_temperature_celsius")
+            .unwrap();
+        assert!(temperature.samples[0]
+            .labels
+            .contains(&("stale".to_string(), "true".to_string())));
+        let count = families.iter().find(|f| f.name == "hw_gpu_count").unwrap();
+        assert_eq!(count.samples[0].value, 2.0);
+        assert!(count.samples[0]
+            .labels
+            .iter()
+            .all(|(name, _)| name != "stale"));
+
+        // Not expired yet
+        let fresh =
+            NodeMetrics::collect_with_gpus(|| cached_gpu_info(&cache(SystemTime::now()), 300));
+        assert!(fresh
+            .families()
+            .iter()
+            .flat_map(|f| &f.samples)
+            .all(|s| s.labels.iter().all(|(name, _)| name != "stale")));
+    }
+
+    #[test]
+    fn gpu_data_stale_is_only_exported_for_gpu_nodes() {
+        let fresh = NodeMetrics::collect_with_gpus(|| GpuCollection {
+            devices: vec![gpu(0, "NVIDIA A100-SXM4-80GB")],
+            ..Default::default()
+        });
+        assert_eq!(sample(&fresh.families(), "hw_gpu_data_stale"), Some(0.0));
+
+        // Nothing cached, as on nodes without GPUs
+        let empty = cached_gpu_info(&GpuCache::default(), 300);
+        assert!(!empty.stale);
+        let metrics = NodeMetrics::collect_with_gpus(move || empty);
+        assert_eq!(metrics.gpu_count, 0);
+        assert_eq!(sample(&metrics.families(), "hw_gpu_data_stale"), None);
+    }
+}
@@ -0,0 +1,259 @@
+use std::io::Read;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+use tracing::{info, warn};
+
+use super::gpu_settings;
+
+/// Path to nvidia-smi binary
+/// Prefer container paths (injected by NVIDIA Container Toolkit) over host-mounted paths
+/// Using host-mounted binaries causes glibc version mismatch issues
+const NVIDIA_SMI_PATHS: &[&str] = &[
+    "/usr/bin/nvidia-smi",       // Injected by NVIDIA Container Toolkit
+    "/usr/local/bin/nvidia-smi", // Alternative container path
+    "/host/usr/bin/nvidia-smi",  // Host-mounted fallback (may not work due to glibc mismatch)
+];
+
+/// Libraries for a host-mounted nvidia-smi when `gpu.library_path` is unset
+const HOST_LIBRARY_PATH: &str = "/host/nvidia-libs:/usr/lib/x86_64-linux-gnu:/usr/lib";
+
+/// How long to wait for output and exit after the process group was killed,
+/// a process stuck in the driver may not die until the driver lets go
+const KILL_GRACE: Duration = Duration::from_secs(1);
+
+/// Find nvidia-smi binary path, the configured one or the first default
+/// that exists
+pub fn find() -> Option<String> {
+    if let Some(path) = &gpu_settings().nvidia_smi_path {
+        if Path::new(path).exists() {
+            return Some(path.clone());
+        }
+        warn!("Configured nvidia-smi {} does not exist", path);
+        return None;
+    }
+
+    for path in NVIDIA_SMI_PATHS {
+        if Path::new(path).exists() {
+            info!("Found nvidia-smi at {}", path);
+            return Some(path.to_string());
+        }
+    }
+    warn!("nvidia-smi not found in any of: {:?}", NVIDIA_SMI_PATHS);
+    None
+}
+
+/// Execute nvidia-smi with timeout protection
+/// Returns None if the command fails, times out or prints anything but UTF-8
+pub fn run(nvidia_smi: &str, args: &[&str], timeout: Duration) -> Option<String> {
+    let mut child = match command(nvidia_smi, args).spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            warn!("Failed to run {}: {}", nvidia_smi, e);
+            return None;
+        }
+    };
+    let pid = child.id() as libc::pid_t;
+
+    // Read both pipes while waiting, a full pipe would block nvidia-smi
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    // The waiter reaps the process even when it outlives the timeout
+    let (exited, exit) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = exited.send(child.wait());
+    });
+
+    let status = match exit.recv_timeout(timeout) {
+        Ok(status) => status,
+        Err(_) => {
+            warn!(
+                "nvidia-smi timed out after {:?}, killing process group {}",
+                timeout, pid
+            );
+            kill_group(pid);
+            if exit.recv_timeout(KILL_GRACE).is_err() {
+                warn!("nvidia-smi (pid {}) did not exit after SIGKILL", pid);
+            }
+            return None;
+        }
+    };
+
+    let status = match status {
+        Ok(status) => status,
+        Err(e) => {
+            warn!("Failed to wait for nvidia-smi: {}", e);
+            return None;
+        }
+    };
+    let stdout = stdout.recv_timeout(KILL_GRACE).unwrap_or_default();
+    let stderr = stderr.recv_timeout(KILL_GRACE).unwrap_or_default();
+
+    if !status.success() {
+        let stdout = String::from_utf8_lossy(&stdout);
+        let stderr = String::from_utf8_lossy(&stderr);
+        warn!(
+            "nvidia-smi failed with {}. Path: {}, stdout: {}, stderr: {}",
+            status,
+            nvidia_smi,
+            or_empty(stdout.trim()),
+            or_empty(stderr.trim())
+        );
+        return None;
+    }
+
+    match String::from_utf8(stdout) {
+        Ok(output) => Some(output),
+        Err(_) => {
+            warn!("nvidia-smi output is not valid UTF-8");
+            None
+        }
+    }
+}
+
+/// nvidia-smi with piped output in its own process group, so a timeout
+/// also kills anything it forked
+pub fn command(nvidia_smi: &str, args: &[&str]) -> Command {
+    let mut cmd = Command::new(nvidia_smi);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .process_group(0);
+
+    // Container-injected nvidia-smi (from NVIDIA Container Toolkit) has its own libraries
+    let library_path = gpu_settings().library_path.as_deref().or_else(|| {
+        nvidia_smi
+            .starts_with("/host/")
+            .then_some(HOST_LIBRARY_PATH)
+    });
+    if let Some(library_path) = library_path {
+        let value = match std::env::var("LD_LIBRARY_PATH") {
+            Ok(existing) if !existing.is_empty() => format!("{}:{}", library_path, existing),
+            _ => library_path.to_string(),
+        };
+        cmd.env("LD_LIBRARY_PATH", value);
+    }
+    cmd
+}
+
+/// SIGKILL the process group of a child started by [`command`]
+pub fn kill_group(pid: libc::pid_t) {
+    // SAFETY: signals the process group created for this child only
+    unsafe { libc::kill(-pid, libc::SIGKILL) };
+}
+
+/// Read a child pipe to the end on its own thread
+fn read_pipe(pipe: Option<impl Read + Send + 'static>) -> mpsc::Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    if let Some(mut pipe) = pipe {
+        thread::spawn(move || {
+            let mut buffer = Vec::new();
+            let _ = pipe.read_to_end(&mut buffer);
+            let _ = sender.send(buffer);
+        });
+    }
+    receiver
+}
+
+fn or_empty(s: &str) -> &str {
+    if s.is_empty() {
+        "(empty)"
+    } else {
+        s
+    }
+}
+
+/// Executable shell script in `dir` standing in for nvidia-smi
+#[cfg(test)]
+pub fn fake(dir: &tempfile::TempDir, body: &str) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = dir.path().join("nvidia-smi");
+    std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::nvidia_xml;
+    use std::time::Instant;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    /// Live (not zombie) processes in a process group
+    fn group_members(pgid: libc::pid_t) -> Vec<String> {
+        std::fs::read_dir("/proc")
+            .unwrap()
+            .filter_map(|entry| std::fs::read_to_string(entry.ok()?.path().join("stat")).ok())
+            .filter(|stat| {
+                // Fields after the parenthesised command: state, ppid, pgrp
+                let fields: Vec<&str> = stat
+                    .rsplit_once(')')
+                    .map_or("", |(_, rest)| rest)
+                    .split_whitespace()
+                    .collect();
+                fields.len() > 2 && fields[0] != "Z" && fields[2] == pgid.to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn timeout_kills_the_process_group() {
+        // SAFETY: getpgrp has no preconditions
+        assert!(!group_members(unsafe { libc::getpgrp() }).is_empty());
+        let dir = tempfile::tempdir().unwrap();
+        let pid_file = dir.path().join("pid");
+        let nvidia_smi = fake(
+            &dir,
+            &format!("echo $$ > {}\nsleep 60 &\nsleep 60", pid_file.display()),
+        );
+
+        let started = Instant::now();
+        assert_eq!(run(&nvidia_smi, &["-q", "-x"], TIMEOUT), None);
+        assert!(started.elapsed() < TIMEOUT + KILL_GRACE + Duration::from_millis(500));
+
+        let pgid: libc::pid_t = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        // The forked sleep is reaped by init shortly after the kill
+        let deadline = Instant::now() + Duration::from_secs(2);
+        while !group_members(pgid).is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        assert_eq!(group_members(pgid), Vec::<String>::new());
+    }
+
+    #[test]
+    fn failed_exit_returns_none() {
+        let dir = tempfile::tempdir().unwrap();
+        let nvidia_smi = fake(
+            &dir,
+            "echo '<?xml version=\"1.0\" ?>'\necho 'Unable to determine the device handle' >&2\nexit 3",
+        );
+        assert_eq!(run(&nvidia_smi, &["-q", "-x"], TIMEOUT), None);
+    }
+
+    #[test]
+    fn invalid_output_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let nvidia_smi = fake(&dir, r"printf '<nvidia_smi_log>\377\376</nvidia_smi_log>'");
+        assert_eq!(run(&nvidia_smi, &["-q", "-x"], TIMEOUT), None);
+
+        let dir = tempfile::tempdir().unwrap();
+        let nvidia_smi = fake(
+            &dir,
+            "echo 'NVIDIA-SMI has failed because it could not communicate with the NVIDIA driver.'",
+        );
+        let output = run(&nvidia_smi, &["-q", "-x"], TIMEOUT).unwrap();
+        assert!(nvidia_xml::parse(&output).is_err());
+    }
+}
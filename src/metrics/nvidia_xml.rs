@@ -0,0 +1,402 @@
+use roxmltree::{Document, Node, ParsingOptions};
+use std::collections::BTreeMap;
+
+use super::{GpuInfo, GpuProcess};
+
+/// Everything one `nvidia-smi -q -x` invocation reports
+#[derive(Debug, Default)]
+pub struct NvidiaSmiLog {
+    pub driver_version: String,
+    pub cuda_version: String,
+    pub gpus: Vec<GpuInfo>,
+}
+
+/// Parse the output of `nvidia-smi -q -x`
+/// Element names moved between driver releases, e.g. `power_readings` became
+/// `gpu_power_readings` in R530 and the ECC counters were split by memory
+/// type, so several locations are tried for those values
+pub fn parse(xml: &str) -> Result<NvidiaSmiLog, String> {
+    // The output declares an external DTD, which is never fetched
+    let options = ParsingOptions {
+        allow_dtd: true,
+        ..ParsingOptions::default()
+    };
+    let document = Document::parse_with_options(xml, options).map_err(|e| e.to_string())?;
+    let root = document.root_element();
+    if !root.has_tag_name("nvidia_smi_log") {
+        return Err(format!(
+            "unexpected root element <{}>",
+            root.tag_name().name()
+        ));
+    }
+
+    let gpus = root
+        .children()
+        .filter(|node| node.has_tag_name("gpu"))
+        .enumerate()
+        .map(|(index, gpu)| parse_gpu(index as u32, gpu))
+        .collect();
+
+    Ok(NvidiaSmiLog {
+        driver_version: text(root, "driver_version").unwrap_or_default(),
+        cuda_version: text(root, "cuda_version").unwrap_or_default(),
+        gpus,
+    })
+}
+
+/// nvidia-smi numbers GPUs in the order it lists them
+fn parse_gpu(index: u32, gpu: Node) -> GpuInfo {
+    let power_draw = first_number(
+        gpu,
+        &[
+            "gpu_power_readings/power_draw",
+            "gpu_power_readings/average_power_draw",
+            "gpu_power_readings/instant_power_draw",
+            "power_readings/power_draw",
+        ],
+    );
+    let power_limit = first_number(
+        gpu,
+        &[
+            "gpu_power_readings/current_power_limit",
+            "gpu_power_readings/power_limit",
+            "power_readings/power_limit",
+        ],
+    );
+
+    GpuInfo {
+        index,
+        name: text(gpu, "product_name").unwrap_or_default(),
+        uuid: text(gpu, "uuid").unwrap_or_default(),
+        pci_bus_id: gpu
+            .attribute("id")
+            .map(str::to_string)
+            .or_else(|| text(gpu, "pci/pci_bus_id"))
+            .unwrap_or_default(),
+        serial: text(gpu, "serial"),
+        vbios_version: text(gpu, "vbios_version"),
+        persistence_mode: text(gpu, "persistence_mode"),
+        compute_mode: text(gpu, "compute_mode"),
+        memory_total_mb: number(gpu, "fb_memory_usage/total").map(|n| n as u64),
+        memory_used_mb: number(gpu, "fb_memory_usage/used").map(|n| n as u64),
+        memory_free_mb: number(gpu, "fb_memory_usage/free").map(|n| n as u64),
+        utilization_percent: number(gpu, "utilization/gpu_util").map(|n| n as u32),
+        memory_utilization_percent: number(gpu, "utilization/memory_util").map(|n| n as u32),
+        encoder_utilization_percent: number(gpu, "utilization/encoder_util").map(|n| n as u32),
+        decoder_utilization_percent: number(gpu, "utilization/decoder_util").map(|n| n as u32),
+        temperature_celsius: number(gpu, "temperature/gpu_temp").map(|n| n as u32),
+        power_draw_watts: power_draw.map(|n| n as u32),
+        power_limit_watts: power_limit.map(|n| n as u32),
+        fan_speed_percent: number(gpu, "fan_speed").map(|n| n as u32),
+        graphics_clock_mhz: number(gpu, "clocks/graphics_clock").map(|n| n as u32),
+        sm_clock_mhz: number(gpu, "clocks/sm_clock").map(|n| n as u32),
+        memory_clock_mhz: number(gpu, "clocks/mem_clock").map(|n| n as u32),
+        ecc_mode: text(gpu, "ecc_mode/current_ecc"),
+        ecc_corrected_errors: ecc_errors(gpu, Ecc::Corrected),
+        ecc_uncorrected_errors: ecc_errors(gpu, Ecc::Uncorrected),
+        processes: processes(gpu),
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Ecc {
+    Corrected,
+    Uncorrected,
+}
+
+/// Volatile ECC error count since the driver was loaded
+/// Up to R470 the totals are `single_bit/total` and `double_bit/total`;
+/// later drivers report per memory type counters such as `dram_correctable`
+/// or `sram_uncorrectable`, which are summed. A breakdown such as
+/// `sram_uncorrectable_parity` only counts when its memory type has no total
+fn ecc_errors(gpu: Node, kind: Ecc) -> Option<u64> {
+    let volatile = child(gpu, "ecc_errors/volatile")?;
+
+    let legacy = match kind {
+        Ecc::Corrected => "single_bit/total",
+        Ecc::Uncorrected => "double_bit/total",
+    };
+    if child(volatile, legacy).is_some() {
+        return number(volatile, legacy).map(|n| n as u64);
+    }
+
+    let suffix = match kind {
+        Ecc::Corrected => "_correctable",
+        Ecc::Uncorrected => "_uncorrectable",
+    };
+    let counters: BTreeMap<&str, u64> = volatile
+        .children()
+        .filter(|node| node.is_element())
+        .filter_map(|node| {
+            let value = parse_number(node.text()?)?;
+            Some((node.tag_name().name(), value as u64))
+        })
+        .collect();
+
+    let counted: Vec<u64> = counters
+        .iter()
+        .filter(|(name, _)| match name.split_once(suffix) {
+            Some((_, "")) => true,
+            Some((memory, breakdown)) if breakdown.starts_with('_') => {
+                !counters.contains_key(format!("{}{}", memory, suffix).as_str())
+            }
+            _ => false,
+        })
+        .map(|(_, value)| *value)
+        .collect();
+    (!counted.is_empty()).then(|| counted.iter().sum())
+}
+
+/// Processes using the GPU, compute (`C`), graphics (`G`) or both (`C+G`)
+fn processes(gpu: Node) -> Vec<GpuProcess> {
+    let Some(processes) = child(gpu, "processes") else {
+        return Vec::new();
+    };
+
+    processes
+        .children()
+        .filter(|node| node.has_tag_name("process_info"))
+        .filter_map(|process| {
+            Some(GpuProcess {
+                pid: number(process, "pid")? as u32,
+                process_type: text(process, "type").unwrap_or_default(),
+                name: text(process, "process_name").unwrap_or_default(),
+                used_memory_mb: number(process, "used_memory").map(|n| n as u64),
+            })
+        })
+        .collect()
+}
+
+/// Descendant element at a `/` separated path of tag names
+fn child<'a, 'input>(node: Node<'a, 'input>, path: &str) -> Option<Node<'a, 'input>> {
+    path.split('/').try_fold(node, |node, name| {
+        node.children().find(|child| child.has_tag_name(name))
+    })
+}
+
+/// Element text, `None` when missing or reported as unavailable
+/// (`N/A`, `[N/A]`, `[Not Supported]`, `Unknown Error`, ...)
+fn text(node: Node, path: &str) -> Option<String> {
+    let value = child(node, path)?.text()?.trim();
+    let unavailable = value.is_empty()
+        || value.starts_with('[')
+        || value.eq_ignore_ascii_case("N/A")
+        || value.eq_ignore_ascii_case("Not Supported")
+        || value.contains("Error");
+    (!unavailable).then(|| value.to_string())
+}
+
+/// Leading number of a value such as `81920 MiB`, `45 %`, `61.23 W` or `33 C`
+fn number(node: Node, path: &str) -> Option<f64> {
+    parse_number(&text(node, path)?)
+}
+
+fn first_number(node: Node, paths: &[&str]) -> Option<f64> {
+    paths.iter().find_map(|path| number(node, path))
+}
+
+fn parse_number(value: &str) -> Option<f64> {
+    value.split_whitespace().next()?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: u32, process_type: &str, name: &str, used_memory_mb: u64) -> GpuProcess {
+        GpuProcess {
+            pid,
+            process_type: process_type.to_string(),
+            name: name.to_string(),
+            used_memory_mb: Some(used_memory_mb),
+        }
+    }
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn parses_r470() {
+        let log = parse(include_str!("../../tests/fixtures/nvidia-smi-r470.xml")).unwrap();
+        assert_eq!(log.driver_version, "470.82.01");
+        assert_eq!(log.cuda_version, "11.4");
+        assert_eq!(
+            log.gpus,
+            vec![GpuInfo {
+                index: 0,
+                name: "Tesla V100-PCIE-32GB".to_string(),
+                uuid: "GPU-5a1c2d3e-0000-1111-2222-333344445555".to_string(),
+                pci_bus_id: "00000000:3B:00.0".to_string(),
+                serial: some("0323218012345"),
+                vbios_version: some("88.00.43.00.01"),
+                persistence_mode: some("Enabled"),
+                compute_mode: some("Default"),
+                memory_total_mb: Some(32510),
+                memory_used_mb: Some(12000),
+                memory_free_mb: Some(20510),
+                utilization_percent: Some(87),
+                memory_utilization_percent: Some(40),
+                encoder_utilization_percent: Some(0),
+                decoder_utilization_percent: Some(0),
+                temperature_celsius: Some(61),
+                power_draw_watts: Some(201),
+                power_limit_watts: Some(250),
+                fan_speed_percent: None,
+                graphics_clock_mhz: Some(1380),
+                sm_clock_mhz: Some(1380),
+                memory_clock_mhz: Some(877),
+                ecc_mode: some("Enabled"),
+                ecc_corrected_errors: Some(2),
+                ecc_uncorrected_errors: Some(1),
+                processes: vec![process(4242, "C", "python3 train.py, resume", 11990)],
+            }]
+        );
+    }
+
+    #[test]
+    fn parses_r535() {
+        let log = parse(include_str!("../../tests/fixtures/nvidia-smi-r535.xml")).unwrap();
+        assert_eq!(log.driver_version, "535.129.03");
+        assert_eq!(log.cuda_version, "12.2");
+        assert_eq!(
+            log.gpus,
+            vec![
+                GpuInfo {
+                    index: 0,
+                    name: "NVIDIA A100-SXM4-80GB".to_string(),
+                    uuid: "GPU-aaaa".to_string(),
+                    pci_bus_id: "00000000:07:00.0".to_string(),
+                    serial: some("1324021000001"),
+                    vbios_version: some("92.00.45.00.05"),
+                    persistence_mode: some("Enabled"),
+                    compute_mode: some("Default"),
+                    memory_total_mb: Some(81920),
+                    memory_used_mb: Some(4),
+                    memory_free_mb: Some(81275),
+                    utilization_percent: Some(0),
+                    memory_utilization_percent: Some(0),
+                    encoder_utilization_percent: None,
+                    decoder_utilization_percent: None,
+                    temperature_celsius: Some(33),
+                    power_draw_watts: Some(61),
+                    power_limit_watts: Some(400),
+                    fan_speed_percent: None,
+                    graphics_clock_mhz: Some(210),
+                    sm_clock_mhz: Some(210),
+                    memory_clock_mhz: Some(1593),
+                    ecc_mode: some("Enabled"),
+                    ecc_corrected_errors: Some(4),
+                    ecc_uncorrected_errors: Some(2),
+                    processes: Vec::new(),
+                },
+                GpuInfo {
+                    index: 1,
+                    name: "NVIDIA A100-SXM4-80GB".to_string(),
+                    uuid: "GPU-bbbb".to_string(),
+                    pci_bus_id: "00000000:0F:00.0".to_string(),
+                    serial: some("1324021000002"),
+                    vbios_version: some("92.00.45.00.05"),
+                    persistence_mode: some("Disabled"),
+                    compute_mode: some("Exclusive_Process"),
+                    memory_total_mb: Some(81920),
+                    memory_used_mb: Some(40000),
+                    memory_free_mb: Some(41280),
+                    utilization_percent: Some(99),
+                    memory_utilization_percent: None,
+                    encoder_utilization_percent: None,
+                    decoder_utilization_percent: None,
+                    temperature_celsius: Some(71),
+                    power_draw_watts: Some(380),
+                    power_limit_watts: Some(400),
+                    fan_speed_percent: None,
+                    graphics_clock_mhz: Some(1410),
+                    sm_clock_mhz: Some(1410),
+                    memory_clock_mhz: Some(1593),
+                    ecc_mode: some("Enabled"),
+                    ecc_corrected_errors: Some(0),
+                    ecc_uncorrected_errors: Some(0),
+                    processes: vec![
+                        process(777, "G", "Xorg", 4),
+                        process(778, "C+G", "render", 39990),
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_r550() {
+        let log = parse(include_str!("../../tests/fixtures/nvidia-smi-r550.xml")).unwrap();
+        assert_eq!(log.driver_version, "550.54.15");
+        assert_eq!(log.cuda_version, "12.4");
+        assert_eq!(
+            log.gpus,
+            vec![GpuInfo {
+                index: 0,
+                name: "NVIDIA H100 80GB HBM3".to_string(),
+                uuid: "GPU-cccc".to_string(),
+                pci_bus_id: "00000000:18:00.0".to_string(),
+                serial: None,
+                vbios_version: some("96.00.74.00.11"),
+                persistence_mode: some("Enabled"),
+                compute_mode: some("Default"),
+                memory_total_mb: Some(81559),
+                memory_used_mb: Some(0),
+                memory_free_mb: Some(81231),
+                utilization_percent: Some(12),
+                memory_utilization_percent: Some(3),
+                encoder_utilization_percent: None,
+                decoder_utilization_percent: None,
+                temperature_celsius: Some(29),
+                // average_power_draw is preferred over instant_power_draw
+                power_draw_watts: Some(70),
+                power_limit_watts: Some(700),
+                fan_speed_percent: Some(35),
+                graphics_clock_mhz: Some(1980),
+                sm_clock_mhz: Some(1980),
+                memory_clock_mhz: Some(2619),
+                ecc_mode: some("Enabled"),
+                ecc_corrected_errors: Some(5),
+                // sram_uncorrectable_parity + sram_uncorrectable_secded
+                ecc_uncorrected_errors: Some(3),
+                processes: Vec::new(),
+            }]
+        );
+    }
+
+    #[test]
+    fn keeps_commas_in_product_names() {
+        let log = parse(
+            r#"<?xml version="1.0" ?>
+<nvidia_smi_log>
+    <gpu id="00000000:01:00.0">
+        <product_name>NVIDIA RTX 6000 Ada Generation, 48GB</product_name>
+        <uuid>GPU-dddd</uuid>
+        <utilization><gpu_util>5 %</gpu_util></utilization>
+    </gpu>
+</nvidia_smi_log>"#,
+        )
+        .unwrap();
+        assert_eq!(log.gpus.len(), 1);
+        assert_eq!(log.gpus[0].name, "NVIDIA RTX 6000 Ada Generation, 48GB");
+        assert_eq!(log.gpus[0].uuid, "GPU-dddd");
+        assert_eq!(log.gpus[0].utilization_percent, Some(5));
+    }
+    #[test]
+    fn prefers_ecc_totals_over_their_breakdown() {
+        let log = parse(include_str!(
+            "../../tests/fixtures/nvidia-smi-ecc-breakdown.xml"
+        ))
+        .unwrap();
+
+        let errors: Vec<_> = log
+            .gpus
+            .iter()
+            .map(|gpu| (gpu.ecc_corrected_errors, gpu.ecc_uncorrected_errors))
+            .collect();
+        // sram_uncorrectable + dram_uncorrectable, then the SRAM breakdown
+        // summed while its total is unavailable
+        assert_eq!(errors, [(Some(6), Some(7)), (Some(0), Some(7))]);
+    }
+}
@@ -0,0 +1,441 @@
+use reqwest::RequestBuilder;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Notify};
+use tracing::{error, info, warn};
+
+use crate::collector::Snapshot;
+use crate::config::HttpAuthSettings;
+
+pub mod otlp;
+pub mod pushgateway;
+pub mod remote_write;
+pub mod socket;
+pub mod statsd;
+
+/// Build an HTTP client for push targets
+pub fn build_client(timeout_secs: u64) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .timeout(Duration::from_secs(timeout_secs))
+        .user_agent(concat!("system-info-exporter/", env!("CARGO_PKG_VERSION")))
+        .build()
+}
+
+/// Attach configured credentials and extra headers to a request
+pub fn apply_auth(mut request: RequestBuilder, auth: &HttpAuthSettings) -> RequestBuilder {
+    if let Some(token) = &auth.bearer_token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(basic) = &auth.basic_auth {
+        request = request.basic_auth(&basic.username, Some(&basic.password));
+    }
+    for (name, value) in &auth.headers {
+        request = request.header(name, value);
+    }
+    request
+}
+
+/// Whether a failed push is worth retrying: server errors and rate limiting
+pub fn is_retryable(status: reqwest::StatusCode) -> bool {
+    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+}
+
+/// Exponential backoff between retries, reset after a successful send
+#[derive(Debug)]
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            current: min,
+        }
+    }
+
+    /// Delay before the next attempt, doubling up to the maximum
+    pub fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+        delay
+    }
+
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
+
+/// FIFO queue that drops the oldest entry once full
+#[derive(Debug)]
+pub struct BoundedQueue<T> {
+    items: VecDeque<T>,
+    capacity: usize,
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            items: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Append an item, returning the evicted oldest one when the queue was full
+    pub fn push(&mut self, item: T) -> Option<T> {
+        let evicted = if self.items.len() >= self.capacity {
+            self.items.pop_front()
+        } else {
+            None
+        };
+        self.items.push_back(item);
+        evicted
+    }
+
+    pub fn front(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+}
+
+/// An HTTP endpoint receiving one encoded request body per snapshot
+pub struct HttpTarget {
+    /// Human readable name used in logs
+    pub name: &'static str,
+    pub url: String,
+    pub headers: Vec<(&'static str, &'static str)>,
+    pub auth: HttpAuthSettings,
+    pub timeout_secs: u64,
+    pub queue_capacity: usize,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+enum PushError {
+    Retryable(String),
+    Fatal(String),
+}
+
+/// Encode every snapshot with `encode` and POST it to `target`
+/// Encoded requests are queued so collection never waits on the network;
+/// during an outage the queue keeps the newest `queue_capacity` requests and
+/// the front request is retried with exponential backoff
+pub fn spawn_http_push<F>(
+    target: HttpTarget,
+    snapshots: broadcast::Receiver<Arc<Snapshot>>,
+    encode: F,
+) -> anyhow::Result<()>
+where
+    F: Fn(&Snapshot) -> Vec<u8> + Send + 'static,
+{
+    let client = build_client(target.timeout_secs)?;
+    let queue = Arc::new(Mutex::new(BoundedQueue::new(target.queue_capacity)));
+    let notify = Arc::new(Notify::new());
+
+    info!("{} enabled, pushing to {}", target.name, target.url);
+
+    tokio::spawn(enqueue(
+        target.name,
+        snapshots,
+        encode,
+        queue.clone(),
+        notify.clone(),
+    ));
+    tokio::spawn(send_loop(target, client, queue, notify));
+
+    Ok(())
+}
+
+async fn enqueue<F>(
+    name: &'static str,
+    mut snapshots: broadcast::Receiver<Arc<Snapshot>>,
+    encode: F,
+    queue: Arc<Mutex<BoundedQueue<Vec<u8>>>>,
+    notify: Arc<Notify>,
+) where
+    F: Fn(&Snapshot) -> Vec<u8>,
+{
+    loop {
+        let snapshot = match snapshots.recv().await {
+            Ok(snapshot) => snapshot,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("{} skipped {} snapshot(s)", name, skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+
+        let payload = encode(&snapshot);
+        let evicted = queue.lock().unwrap().push(payload);
+        if evicted.is_some() {
+            warn!("{} queue full, dropped oldest request", name);
+        }
+        notify.notify_one();
+    }
+}
+
+async fn send_loop(
+    target: HttpTarget,
+    client: reqwest::Client,
+    queue: Arc<Mutex<BoundedQueue<Vec<u8>>>>,
+    notify: Arc<Notify>,
+) {
+    let mut backoff = Backoff::new(target.min_backoff, target.max_backoff);
+
+    loop {
+        let payload = queue.lock().unwrap().front().cloned();
+        let Some(payload) = payload else {
+            notify.notified().await;
+            continue;
+        };
+
+        match send(&client, &target, payload).await {
+            Ok(()) => {
+                queue.lock().unwrap().pop_front();
+                backoff.reset();
+            }
+            Err(PushError::Retryable(msg)) => {
+                let delay = backoff.next_delay();
+                let pending = queue.lock().unwrap().len();
+                warn!(
+                    "{} failed: {}, retrying in {:?} ({} request(s) queued)",
+                    target.name, msg, delay, pending
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(PushError::Fatal(msg)) => {
+                error!("{} rejected, dropping request: {}", target.name, msg);
+                queue.lock().unwrap().pop_front();
+            }
+        }
+    }
+}
+
+async fn send(
+    client: &reqwest::Client,
+    target: &HttpTarget,
+    payload: Vec<u8>,
+) -> Result<(), PushError> {
+    let mut request = client.post(&target.url).body(payload);
+    for (name, value) in &target.headers {
+        request = request.header(*name, *value);
+    }
+
+    let response = apply_auth(request, &target.auth)
+        .send()
+        .await
+        .map_err(|e| PushError::Retryable(e.to_string()))?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    let msg = format!("HTTP {}: {}", status, body.trim());
+    if is_retryable(status) {
+        Err(PushError::Retryable(msg))
+    } else {
+        Err(PushError::Fatal(msg))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Bytes;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::Router;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::Instant;
+    use tokio::sync::mpsc;
+
+    /// Endpoint answering each request with `respond(body)`, forwards the
+    /// body and the answer once responded
+    async fn receiver<R>(respond: R) -> (String, mpsc::UnboundedReceiver<(String, StatusCode)>)
+    where
+        R: Fn(&str) -> StatusCode + Clone + Send + Sync + 'static,
+    {
+        let (sender, requests) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/push",
+            post(move |body: Bytes| async move {
+                let body = String::from_utf8_lossy(&body).to_string();
+                let status = respond(&body);
+                let _ = sender.send((body, status));
+                status
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/push", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    fn target(url: String, queue_capacity: usize) -> HttpTarget {
+        HttpTarget {
+            name: "test push",
+            url,
+            headers: vec![],
+            auth: HttpAuthSettings::default(),
+            timeout_secs: 5,
+            queue_capacity,
+            min_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(80),
+        }
+    }
+
+    /// Start pushing to `target`, each snapshot is encoded as its sequence number
+    fn start(target: HttpTarget) -> broadcast::Sender<Arc<Snapshot>> {
+        let (snapshots, receiver) = broadcast::channel(16);
+        let sequence = AtomicUsize::new(0);
+        spawn_http_push(target, receiver, move |_| {
+            (sequence.fetch_add(1, Ordering::SeqCst) + 1)
+                .to_string()
+                .into_bytes()
+        })
+        .unwrap();
+        snapshots
+    }
+
+    async fn next(
+        requests: &mut mpsc::UnboundedReceiver<(String, StatusCode)>,
+    ) -> (String, StatusCode) {
+        tokio::time::timeout(Duration::from_secs(10), requests.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    #[test]
+    fn bounded_queue_evicts_oldest_first() {
+        let mut queue = BoundedQueue::new(2);
+        assert_eq!(queue.push(1), None);
+        assert_eq!(queue.push(2), None);
+        assert_eq!(queue.push(3), Some(1));
+        assert_eq!(queue.push(4), Some(2));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(queue.pop_front(), Some(3));
+        assert_eq!(queue.front(), Some(&4));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_maximum() {
+        let mut backoff = Backoff::new(Duration::from_millis(100), Duration::from_millis(300));
+        let delays: Vec<_> = (0..4).map(|_| backoff.next_delay().as_millis()).collect();
+        assert_eq!(delays, [100, 200, 300, 300]);
+        backoff.reset();
+        assert_eq!(backoff.next_delay(), Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn retries_server_errors_with_backoff() {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let (url, mut requests) = receiver({
+            let attempts = attempts.clone();
+            move |_: &str| match attempts.fetch_add(1, Ordering::SeqCst) {
+                0 => StatusCode::SERVICE_UNAVAILABLE,
+                1 => StatusCode::TOO_MANY_REQUESTS,
+                _ => StatusCode::OK,
+            }
+        })
+        .await;
+        let snapshots = start(target(url, 4));
+        snapshots
+            .send(Arc::new(Snapshot::with_families(vec![], &[])))
+            .unwrap();
+
+        let mut sent = Vec::new();
+        for _ in 0..3 {
+            let (body, status) = next(&mut requests).await;
+            sent.push((body, status, Instant::now()));
+        }
+        let bodies: Vec<_> = sent
+            .iter()
+            .map(|(body, status, _)| (body.as_str(), *status))
+            .collect();
+        assert_eq!(
+            bodies,
+            [
+                ("1", StatusCode::SERVICE_UNAVAILABLE),
+                ("1", StatusCode::TOO_MANY_REQUESTS),
+                ("1", StatusCode::OK),
+            ]
+        );
+        assert!(sent[1].2 - sent[0].2 >= Duration::from_millis(20));
+        assert!(sent[2].2 - sent[1].2 >= Duration::from_millis(40));
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn drops_requests_rejected_with_client_errors() {
+        let (url, mut requests) = receiver(|body: &str| match body {
+            "1" => StatusCode::BAD_REQUEST,
+            _ => StatusCode::NO_CONTENT,
+        })
+        .await;
+        let snapshots = start(target(url, 4));
+        let snapshot = Arc::new(Snapshot::with_families(vec![], &[]));
+
+        snapshots.send(snapshot.clone()).unwrap();
+        assert_eq!(
+            next(&mut requests).await,
+            ("1".to_string(), StatusCode::BAD_REQUEST)
+        );
+        snapshots.send(snapshot).unwrap();
+        assert_eq!(
+            next(&mut requests).await,
+            ("2".to_string(), StatusCode::NO_CONTENT)
+        );
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn full_queue_drops_oldest_requests_during_an_outage() {
+        let up = Arc::new(AtomicBool::new(false));
+        let (url, mut requests) = receiver({
+            let up = up.clone();
+            move |_: &str| {
+                if up.load(Ordering::SeqCst) {
+                    StatusCode::OK
+                } else {
+                    StatusCode::BAD_GATEWAY
+                }
+            }
+        })
+        .await;
+        let snapshots = start(target(url, 2));
+        let snapshot = Arc::new(Snapshot::with_families(vec![], &[]));
+        for _ in 0..4 {
+            snapshots.send(snapshot.clone()).unwrap();
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        up.store(true, Ordering::SeqCst);
+
+        let mut delivered = Vec::new();
+        while delivered.len() < 2 {
+            let (body, status) = next(&mut requests).await;
+            if status == StatusCode::OK {
+                delivered.push(body);
+            }
+        }
+        assert_eq!(delivered, ["3", "4"]);
+    }
+}
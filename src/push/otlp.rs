@@ -0,0 +1,611 @@
+use prost::Message;
+use serde::{Serialize, Serializer};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+use super::{spawn_http_push, HttpTarget};
+use crate::collector::Snapshot;
+use crate::config::{OtlpProtocol, OtlpSettings};
+use crate::metrics::exposition::{MetricFamily, MetricType};
+
+/// `AggregationTemporality.AGGREGATION_TEMPORALITY_CUMULATIVE`
+const AGGREGATION_TEMPORALITY_CUMULATIVE: i32 = 2;
+
+/// Counters accumulating since boot rather than since the exporter started
+const BOOT_COUNTERS: &[&str] = &["hw_node_uptime_seconds"];
+
+static EXPORTER_START: OnceLock<u64> = OnceLock::new();
+
+/// Start time of exporter-lifetime counters, pinned when the export starts
+fn exporter_start_unix_nano() -> u64 {
+    *EXPORTER_START.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0)
+    })
+}
+
+// Subset of opentelemetry.proto.collector.metrics.v1 needed for gauges and sums.
+// The serde attributes follow the OTLP/JSON mapping: lowerCamelCase field
+// names, enums as integers and 64-bit integers as decimal strings.
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportMetricsServiceRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub resource: Option<Resource>,
+    #[prost(message, repeated, tag = "2")]
+    pub scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    #[prost(message, repeated, tag = "1")]
+    pub attributes: Vec<KeyValue>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeMetrics {
+    #[prost(message, optional, tag = "1")]
+    pub scope: Option<InstrumentationScope>,
+    #[prost(message, repeated, tag = "2")]
+    pub metrics: Vec<Metric>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InstrumentationScope {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metric {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub description: String,
+    #[prost(string, tag = "3")]
+    pub unit: String,
+    #[prost(oneof = "MetricData", tags = "5, 7")]
+    #[serde(flatten)]
+    pub data: Option<MetricData>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MetricData {
+    #[prost(message, tag = "5")]
+    Gauge(Gauge),
+    #[prost(message, tag = "7")]
+    Sum(Sum),
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Gauge {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Sum {
+    #[prost(message, repeated, tag = "1")]
+    pub data_points: Vec<NumberDataPoint>,
+    #[prost(int32, tag = "2")]
+    pub aggregation_temporality: i32,
+    #[prost(bool, tag = "3")]
+    pub is_monotonic: bool,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NumberDataPoint {
+    #[prost(message, repeated, tag = "7")]
+    pub attributes: Vec<KeyValue>,
+    #[prost(fixed64, tag = "2")]
+    #[serde(serialize_with = "serialize_u64_string")]
+    pub start_time_unix_nano: u64,
+    #[prost(fixed64, tag = "3")]
+    #[serde(serialize_with = "serialize_u64_string")]
+    pub time_unix_nano: u64,
+    #[prost(oneof = "NumberValue", tags = "4")]
+    #[serde(flatten)]
+    pub value: Option<NumberValue>,
+}
+
+/// Kept as a oneof so zero values are still encoded on the wire
+#[derive(Clone, PartialEq, prost::Oneof, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum NumberValue {
+    #[prost(double, tag = "4")]
+    AsDouble(f64),
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyValue {
+    #[prost(string, tag = "1")]
+    pub key: String,
+    #[prost(message, optional, tag = "2")]
+    pub value: Option<AnyValue>,
+}
+
+#[derive(Clone, PartialEq, Message, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnyValue {
+    #[prost(oneof = "Value", tags = "1")]
+    #[serde(flatten)]
+    pub value: Option<Value>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Value {
+    #[prost(string, tag = "1")]
+    StringValue(String),
+}
+
+fn serialize_u64_string<S: Serializer>(value: &u64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+fn key_value(key: &str, value: &str) -> KeyValue {
+    KeyValue {
+        key: key.to_string(),
+        value: Some(AnyValue {
+            value: Some(Value::StringValue(value.to_string())),
+        }),
+    }
+}
+
+/// UCUM unit derived from the metric name suffix
+fn unit_for(name: &str) -> &'static str {
+    const UNITS: &[(&str, &str)] = &[
+        ("_bytes", "By"),
+        ("_seconds", "s"),
+        ("_percent", "%"),
+        ("_celsius", "Cel"),
+        ("_watts", "W"),
+    ];
+    UNITS
+        .iter()
+        .find(|(suffix, _)| name.ends_with(suffix))
+        .map(|(_, unit)| *unit)
+        .unwrap_or("1")
+}
+
+/// Resource attributes describing the node, following the host, k8s and os
+/// semantic conventions. Constant labels are added as-is
+fn resource_attributes(snapshot: &Snapshot) -> Vec<KeyValue> {
+    let metrics = &snapshot.metrics;
+    let mut attributes = vec![
+        key_value("service.name", env!("CARGO_PKG_NAME")),
+        key_value("service.version", env!("CARGO_PKG_VERSION")),
+        key_value("host.name", &metrics.hostname),
+        key_value("k8s.node.name", &metrics.node),
+        key_value("os.type", std::env::consts::OS),
+        key_value(
+            "os.description",
+            &format!("{} {}", metrics.os_name, metrics.os_version),
+        ),
+        key_value("host.arch", std::env::consts::ARCH),
+    ];
+    for (name, value) in &metrics.labels {
+        attributes.push(key_value(name, value));
+    }
+    attributes
+}
+
+/// Map a metric family to an OTel gauge, or a cumulative monotonic sum for
+/// counters. The `node` label moves to the `k8s.node.name` resource attribute
+fn to_metric(family: &MetricFamily, time_unix_nano: u64, start_time_unix_nano: u64) -> Metric {
+    let data_points: Vec<NumberDataPoint> = family
+        .samples
+        .iter()
+        .map(|sample| NumberDataPoint {
+            attributes: sample
+                .labels
+                .iter()
+                .filter(|(name, _)| name != "node")
+                .map(|(name, value)| key_value(name, value))
+                .collect(),
+            start_time_unix_nano: match family.metric_type {
+                MetricType::Counter => start_time_unix_nano,
+                MetricType::Gauge => 0,
+            },
+            time_unix_nano,
+            value: Some(NumberValue::AsDouble(sample.value)),
+        })
+        .collect();
+
+    let data = match family.metric_type {
+        MetricType::Gauge => MetricData::Gauge(Gauge { data_points }),
+        MetricType::Counter => MetricData::Sum(Sum {
+            data_points,
+            aggregation_temporality: AGGREGATION_TEMPORALITY_CUMULATIVE,
+            is_monotonic: true,
+        }),
+    };
+
+    Metric {
+        name: family.name.clone(),
+        description: family.help.clone(),
+        unit: unit_for(&family.name).to_string(),
+        data: Some(data),
+    }
+}
+
+/// Convert a snapshot into an OTLP export request
+pub fn build_export_request(snapshot: &Snapshot) -> ExportMetricsServiceRequest {
+    let time_unix_nano = snapshot.timestamp_ms() as u64 * 1_000_000;
+    let boot_time_unix_nano =
+        time_unix_nano.saturating_sub(snapshot.metrics.uptime_secs * 1_000_000_000);
+
+    let metrics = snapshot
+        .families
+        .iter()
+        .filter(|family| !family.samples.is_empty())
+        .map(|family| {
+            let start_time_unix_nano = if BOOT_COUNTERS.contains(&family.name.as_str()) {
+                boot_time_unix_nano
+            } else {
+                exporter_start_unix_nano()
+            };
+            to_metric(family, time_unix_nano, start_time_unix_nano)
+        })
+        .collect();
+
+    ExportMetricsServiceRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Some(Resource {
+                attributes: resource_attributes(snapshot),
+            }),
+            scope_metrics: vec![ScopeMetrics {
+                scope: Some(InstrumentationScope {
+                    name: env!("CARGO_PKG_NAME").to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                }),
+                metrics,
+            }],
+        }],
+    }
+}
+
+/// Start exporting snapshots to the OTLP/HTTP metrics endpoint
+pub fn spawn(
+    settings: OtlpSettings,
+    snapshots: broadcast::Receiver<Arc<Snapshot>>,
+) -> anyhow::Result<()> {
+    let protocol = settings.protocol;
+    // Pin the start time before the first snapshot arrives
+    exporter_start_unix_nano();
+    let content_type = match protocol {
+        OtlpProtocol::HttpProtobuf => "application/x-protobuf",
+        OtlpProtocol::HttpJson => "application/json",
+    };
+
+    let target = HttpTarget {
+        name: "OTLP export",
+        url: settings.endpoint,
+        headers: vec![("Content-Type", content_type)],
+        auth: settings.auth,
+        timeout_secs: settings.timeout_secs,
+        queue_capacity: settings.queue_capacity,
+        min_backoff: Duration::from_millis(settings.min_backoff_ms),
+        max_backoff: Duration::from_secs(settings.max_backoff_secs),
+    };
+
+    spawn_http_push(target, snapshots, move |snapshot| {
+        let request = build_export_request(snapshot);
+        match protocol {
+            OtlpProtocol::HttpProtobuf => request.encode_to_vec(),
+            OtlpProtocol::HttpJson => serde_json::to_vec(&request)
+                .expect("OTLP request contains only serializable values"),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Independent copy of opentelemetry/proto/metrics/v1 and common/v1 with
+    /// the reference field numbers, including fields the exporter never sets
+    mod reference {
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct ExportMetricsServiceRequest {
+            #[prost(message, repeated, tag = "1")]
+            pub resource_metrics: Vec<ResourceMetrics>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct ResourceMetrics {
+            #[prost(message, optional, tag = "1")]
+            pub resource: Option<Resource>,
+            #[prost(message, repeated, tag = "2")]
+            pub scope_metrics: Vec<ScopeMetrics>,
+            #[prost(string, tag = "3")]
+            pub schema_url: String,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Resource {
+            #[prost(message, repeated, tag = "1")]
+            pub attributes: Vec<KeyValue>,
+            #[prost(uint32, tag = "2")]
+            pub dropped_attributes_count: u32,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct ScopeMetrics {
+            #[prost(message, optional, tag = "1")]
+            pub scope: Option<InstrumentationScope>,
+            #[prost(message, repeated, tag = "2")]
+            pub metrics: Vec<Metric>,
+            #[prost(string, tag = "3")]
+            pub schema_url: String,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct InstrumentationScope {
+            #[prost(string, tag = "1")]
+            pub name: String,
+            #[prost(string, tag = "2")]
+            pub version: String,
+            #[prost(message, repeated, tag = "3")]
+            pub attributes: Vec<KeyValue>,
+            #[prost(uint32, tag = "4")]
+            pub dropped_attributes_count: u32,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Metric {
+            #[prost(string, tag = "1")]
+            pub name: String,
+            #[prost(string, tag = "2")]
+            pub description: String,
+            #[prost(string, tag = "3")]
+            pub unit: String,
+            #[prost(message, optional, tag = "5")]
+            pub gauge: Option<Gauge>,
+            #[prost(message, optional, tag = "7")]
+            pub sum: Option<Sum>,
+            #[prost(message, repeated, tag = "12")]
+            pub metadata: Vec<KeyValue>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Gauge {
+            #[prost(message, repeated, tag = "1")]
+            pub data_points: Vec<NumberDataPoint>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct Sum {
+            #[prost(message, repeated, tag = "1")]
+            pub data_points: Vec<NumberDataPoint>,
+            #[prost(enumeration = "AggregationTemporality", tag = "2")]
+            pub aggregation_temporality: i32,
+            #[prost(bool, tag = "3")]
+            pub is_monotonic: bool,
+        }
+
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, prost::Enumeration)]
+        pub enum AggregationTemporality {
+            Unspecified = 0,
+            Delta = 1,
+            Cumulative = 2,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct NumberDataPoint {
+            #[prost(message, repeated, tag = "7")]
+            pub attributes: Vec<KeyValue>,
+            #[prost(fixed64, tag = "2")]
+            pub start_time_unix_nano: u64,
+            #[prost(fixed64, tag = "3")]
+            pub time_unix_nano: u64,
+            #[prost(double, optional, tag = "4")]
+            pub as_double: Option<f64>,
+            #[prost(sfixed64, optional, tag = "6")]
+            pub as_int: Option<i64>,
+            #[prost(uint32, tag = "8")]
+            pub flags: u32,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct KeyValue {
+            #[prost(string, tag = "1")]
+            pub key: String,
+            #[prost(message, optional, tag = "2")]
+            pub value: Option<AnyValue>,
+        }
+
+        #[derive(Clone, PartialEq, prost::Message)]
+        pub struct AnyValue {
+            #[prost(string, optional, tag = "1")]
+            pub string_value: Option<String>,
+            #[prost(bool, optional, tag = "2")]
+            pub bool_value: Option<bool>,
+            #[prost(int64, optional, tag = "3")]
+            pub int_value: Option<i64>,
+            #[prost(double, optional, tag = "4")]
+            pub double_value: Option<f64>,
+        }
+    }
+
+    const TIME_UNIX_NANO: u64 = 1_700_000_000_123_000_000;
+
+    fn node() -> (String, String) {
+        ("node".to_string(), "n1".to_string())
+    }
+
+    fn snapshot() -> Snapshot {
+        Snapshot::with_families(
+            vec![
+                MetricFamily::gauge("hw_cpu_usage_percent", "CPU usage", "cpu")
+                    .with_sample(vec![node()], 0.0),
+                MetricFamily::counter("hw_node_uptime_seconds", "Node uptime", "node")
+                    .with_sample(vec![node()], 3600.0),
+                MetricFamily::counter("hw_inventory_changes_total", "Changes", "inventory")
+                    .with_sample(
+                        vec![node(), ("component".to_string(), "gpu".to_string())],
+                        2.0,
+                    ),
+                MetricFamily::gauge("hw_gpu_count", "No samples", "gpu"),
+            ],
+            &[("cluster", "c1")],
+        )
+    }
+
+    fn attributes(attributes: &[reference::KeyValue]) -> Vec<(&str, &str)> {
+        attributes
+            .iter()
+            .map(|kv| {
+                let value = kv.value.as_ref().and_then(|v| v.string_value.as_deref());
+                (kv.key.as_str(), value.unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn protobuf_decodes_with_reference_field_numbers() {
+        let snapshot = snapshot();
+        let bytes = build_export_request(&snapshot).encode_to_vec();
+        let request = reference::ExportMetricsServiceRequest::decode(bytes.as_slice()).unwrap();
+
+        let [resource_metrics] = request.resource_metrics.as_slice() else {
+            panic!("expected one resource");
+        };
+        let resource = attributes(&resource_metrics.resource.as_ref().unwrap().attributes);
+        assert!(resource.contains(&("k8s.node.name", snapshot.metrics.node.as_str())));
+        assert!(resource.contains(&("service.name", "system-info-exporter")));
+        assert!(resource.contains(&("cluster", "c1")));
+
+        let [scope_metrics] = resource_metrics.scope_metrics.as_slice() else {
+            panic!("expected one scope");
+        };
+        let scope = scope_metrics.scope.as_ref().unwrap();
+        assert_eq!(scope.name, "system-info-exporter");
+        assert_eq!(scope.version, env!("CARGO_PKG_VERSION"));
+
+        let [usage, uptime, changes] = scope_metrics.metrics.as_slice() else {
+            panic!("expected three metrics, families without samples are skipped");
+        };
+
+        assert_eq!(usage.name, "hw_cpu_usage_percent");
+        assert_eq!(usage.description, "CPU usage");
+        assert_eq!(usage.unit, "%");
+        assert!(usage.sum.is_none());
+        let point = &usage.gauge.as_ref().unwrap().data_points[0];
+        assert_eq!(point.as_double, Some(0.0));
+        assert_eq!(point.as_int, None);
+        assert_eq!(point.start_time_unix_nano, 0);
+        assert_eq!(point.time_unix_nano, TIME_UNIX_NANO);
+        assert!(attributes(&point.attributes).is_empty());
+
+        assert_eq!(uptime.name, "hw_node_uptime_seconds");
+        assert_eq!(uptime.unit, "s");
+        let sum = uptime.sum.as_ref().unwrap();
+        assert_eq!(
+            sum.aggregation_temporality,
+            reference::AggregationTemporality::Cumulative as i32
+        );
+        assert!(sum.is_monotonic);
+        assert_eq!(sum.data_points[0].as_double, Some(3600.0));
+        assert_eq!(
+            sum.data_points[0].start_time_unix_nano,
+            TIME_UNIX_NANO - snapshot.metrics.uptime_secs * 1_000_000_000
+        );
+
+        assert_eq!(changes.name, "hw_inventory_changes_total");
+        assert_eq!(changes.unit, "1");
+        let point = &changes.sum.as_ref().unwrap().data_points[0];
+        assert_eq!(point.as_double, Some(2.0));
+        assert_eq!(point.start_time_unix_nano, exporter_start_unix_nano());
+        assert_eq!(attributes(&point.attributes), [("component", "gpu")]);
+    }
+
+    #[test]
+    fn exporter_start_stays_fixed() {
+        let first = build_export_request(&snapshot());
+        std::thread::sleep(Duration::from_millis(5));
+        let second = build_export_request(&snapshot());
+        let start = |request: &ExportMetricsServiceRequest| {
+            let metric = &request.resource_metrics[0].scope_metrics[0].metrics[2];
+            match &metric.data {
+                Some(MetricData::Sum(sum)) => sum.data_points[0].start_time_unix_nano,
+                _ => panic!("expected a sum"),
+            }
+        };
+        assert_eq!(start(&first), start(&second));
+    }
+
+    #[test]
+    fn json_follows_the_otlp_json_mapping() {
+        let json = serde_json::to_value(build_export_request(&snapshot())).unwrap();
+        let scope_metrics = &json["resourceMetrics"][0]["scopeMetrics"][0];
+        assert_eq!(scope_metrics["scope"]["name"], "system-info-exporter");
+
+        let metrics = scope_metrics["metrics"].as_array().unwrap();
+        assert_eq!(metrics.len(), 3);
+        assert_eq!(
+            metrics[0],
+            serde_json::json!({
+                "name": "hw_cpu_usage_percent",
+                "description": "CPU usage",
+                "unit": "%",
+                "gauge": {
+                    "dataPoints": [{
+                        "attributes": [],
+                        "startTimeUnixNano": "0",
+                        "timeUnixNano": "1700000000123000000",
+                        "asDouble": 0.0,
+                    }],
+                },
+            })
+        );
+        assert_eq!(
+            metrics[2],
+            serde_json::json!({
+                "name": "hw_inventory_changes_total",
+                "description": "Changes",
+                "unit": "1",
+                "sum": {
+                    "dataPoints": [{
+                        "attributes": [{"key": "component", "value": {"stringValue": "gpu"}}],
+                        "startTimeUnixNano": exporter_start_unix_nano().to_string(),
+                        "timeUnixNano": "1700000000123000000",
+                        "asDouble": 2.0,
+                    }],
+                    "aggregationTemporality": 2,
+                    "isMonotonic": true,
+                },
+            })
+        );
+
+        let resource = json["resourceMetrics"][0]["resource"]["attributes"]
+            .as_array()
+            .unwrap();
+        assert!(resource.contains(&serde_json::json!({
+            "key": "cluster",
+            "value": {"stringValue": "c1"},
+        })));
+    }
+}
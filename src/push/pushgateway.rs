@@ -0,0 +1,365 @@
+use base64::engine::general_purpose::URL_SAFE;
+use base64::Engine;
+use reqwest::{Client, Method, Url};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{error, info, warn};
+
+use super::{apply_auth, build_client, is_retryable, Backoff};
+use crate::collector::Collector;
+use crate::config::{PushMethod, PushgatewaySettings};
+use crate::metrics::exposition::encode_text;
+
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(10);
+
+/// Build `<base>/metrics/job/<job>/node/<node>/<label>/<value>...`
+/// Values that are empty or contain `/` use the `<label>@base64` form
+fn group_url(
+    base: &str,
+    job: &str,
+    node: &str,
+    labels: &BTreeMap<String, String>,
+) -> anyhow::Result<Url> {
+    if job.is_empty() {
+        anyhow::bail!("pushgateway job must not be empty");
+    }
+    if let Some(reserved) = ["job", "node"].iter().find(|l| labels.contains_key(**l)) {
+        anyhow::bail!("pushgateway grouping label {} is reserved", reserved);
+    }
+
+    let mut url = Url::parse(base)?;
+    {
+        let mut segments = url
+            .path_segments_mut()
+            .map_err(|_| anyhow::anyhow!("invalid pushgateway url: {}", base))?;
+        segments.pop_if_empty().push("metrics");
+
+        let grouping = [("job", job), ("node", node)]
+            .into_iter()
+            .chain(labels.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+        for (name, value) in grouping {
+            if value.is_empty() {
+                segments.push(&format!("{}@base64", name)).push("=");
+            } else if value.contains('/') {
+                segments
+                    .push(&format!("{}@base64", name))
+                    .push(&URL_SAFE.encode(value));
+            } else {
+                segments.push(name).push(value);
+            }
+        }
+    }
+
+    Ok(url)
+}
+
+/// Send one request, retrying server errors and connection failures
+async fn send(
+    client: &Client,
+    settings: &PushgatewaySettings,
+    method: Method,
+    url: &Url,
+    body: Option<String>,
+) -> bool {
+    let mut backoff = Backoff::new(MIN_RETRY_DELAY, MAX_RETRY_DELAY);
+    let attempts = settings.max_attempts.max(1);
+
+    for attempt in 1..=attempts {
+        let mut request = apply_auth(client.request(method.clone(), url.clone()), &settings.auth);
+        if let Some(body) = &body {
+            request = request
+                .header("Content-Type", "text/plain; version=0.0.4")
+                .body(body.clone());
+        }
+
+        match request.send().await {
+            Ok(response) if response.status().is_success() => return true,
+            Ok(response) if !is_retryable(response.status()) => {
+                let status = response.status();
+                let text = response.text().await.unwrap_or_default();
+                error!(
+                    "Pushgateway {} {} rejected with {}: {}",
+                    method,
+                    url,
+                    status,
+                    text.trim()
+                );
+                return false;
+            }
+            Ok(response) => warn!(
+                "Pushgateway {} {} failed with {} (attempt {}/{})",
+                method,
+                url,
+                response.status(),
+                attempt,
+                attempts
+            ),
+            Err(e) => warn!(
+                "Pushgateway {} {} failed: {} (attempt {}/{})",
+                method, url, e, attempt, attempts
+            ),
+        }
+
+        if attempt < attempts {
+            tokio::time::sleep(backoff.next_delay()).await;
+        }
+    }
+
+    false
+}
+
+/// Collect `count` times, pushing each collection to the Pushgateway group of
+/// this node, and optionally delete the group at the end. Returns whether
+/// every request succeeded; a failed collection stops the run but the group
+/// is still deleted before its error is returned
+pub async fn run(
+    settings: &PushgatewaySettings,
+    collector: &Collector,
+    count: u32,
+    interval: Duration,
+) -> anyhow::Result<bool> {
+    let Some(base) = &settings.url else {
+        anyhow::bail!("pushgateway url is not configured, set [pushgateway] url or --url");
+    };
+    let client = build_client(settings.timeout_secs)?;
+    let method = match settings.method {
+        PushMethod::Put => Method::PUT,
+        PushMethod::Post => Method::POST,
+    };
+
+    let mut url: Option<Url> = None;
+    let mut success = true;
+    let mut collect_error = None;
+
+    for i in 0..count.max(1) {
+        let snapshot = match collector.collect_once().await {
+            Ok(snapshot) => snapshot,
+            Err(e) if url.is_some() => {
+                error!("Collection {}/{} failed: {}", i + 1, count.max(1), e);
+                collect_error = Some(e);
+                break;
+            }
+            Err(e) => return Err(e),
+        };
+        let target = match &url {
+            Some(target) => target,
+            None => url.insert(group_url(
+                base,
+                &settings.job,
+                &snapshot.metrics.node,
+                &settings.grouping_labels,
+            )?),
+        };
+
+        let body = encode_text(&snapshot.families, &snapshot.metrics.labels);
+        if send(&client, settings, method.clone(), target, Some(body)).await {
+            info!("Pushed collection {}/{} to {}", i + 1, count.max(1), target);
+        } else {
+            success = false;
+        }
+
+        if i + 1 < count {
+            tokio::select! {
+                _ = tokio::time::sleep(interval) => {}
+                _ = tokio::signal::ctrl_c() => {
+                    warn!("Interrupted, stopping after {} collection(s)", i + 1);
+                    break;
+                }
+            }
+        }
+    }
+
+    if settings.delete_on_exit {
+        if let Some(target) = &url {
+            if send(&client, settings, Method::DELETE, target, None).await {
+                info!("Deleted group {}", target);
+            } else {
+                success = false;
+            }
+        }
+    }
+
+    match collect_error {
+        Some(e) => Err(e),
+        None => Ok(success),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+    use crate::inventory::{ChangeDetector, InventoryChecker};
+    use crate::metrics::filter::MetricSelector;
+    use axum::body::Bytes;
+    use axum::http::{StatusCode, Uri};
+    use axum::Router;
+    use std::sync::{Arc, Mutex};
+
+    type Requests = Arc<Mutex<Vec<(Method, String, String)>>>;
+
+    fn labels(labels: &[(&str, &str)]) -> BTreeMap<String, String> {
+        labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn group_url_appends_the_grouping_key() {
+        let url = group_url(
+            "http://pushgateway:9091",
+            "hw",
+            "node-1",
+            &labels(&[("zone", "a"), ("cluster", "c1")]),
+        )
+        .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://pushgateway:9091/metrics/job/hw/node/node-1/cluster/c1/zone/a"
+        );
+
+        let url = group_url(
+            "http://pushgateway:9091/prefix/",
+            "hw",
+            "n",
+            &BTreeMap::new(),
+        );
+        assert_eq!(
+            url.unwrap().as_str(),
+            "http://pushgateway:9091/prefix/metrics/job/hw/node/n"
+        );
+    }
+
+    #[test]
+    fn group_url_base64_encodes_slashes_and_empty_values() {
+        let url = group_url(
+            "http://pushgateway:9091",
+            "hw/batch",
+            "node-1",
+            &labels(&[("path", "/var/tmp"), ("rack", "")]),
+        )
+        .unwrap();
+        assert_eq!(
+            url.as_str(),
+            "http://pushgateway:9091/metrics/job@base64/aHcvYmF0Y2g=/node/node-1\
+             /path@base64/L3Zhci90bXA=/rack@base64/="
+        );
+    }
+
+    #[test]
+    fn group_url_escapes_other_characters() {
+        let url = group_url("http://pushgateway:9091", "hw", "node 1", &BTreeMap::new());
+        assert_eq!(
+            url.unwrap().as_str(),
+            "http://pushgateway:9091/metrics/job/hw/node/node%201"
+        );
+    }
+
+    #[test]
+    fn group_url_rejects_invalid_groups() {
+        assert!(group_url("http://pushgateway:9091", "", "n", &BTreeMap::new()).is_err());
+        assert!(group_url(
+            "http://pushgateway:9091",
+            "hw",
+            "n",
+            &labels(&[("node", "x")])
+        )
+        .is_err());
+        assert!(group_url(
+            "http://pushgateway:9091",
+            "hw",
+            "n",
+            &labels(&[("job", "x")])
+        )
+        .is_err());
+        assert!(group_url("not a url", "hw", "n", &BTreeMap::new()).is_err());
+    }
+
+    /// Pushgateway stand-in recording every request, answering `status`
+    async fn pushgateway(status: StatusCode) -> (String, Requests) {
+        let requests = Requests::default();
+        let recorded = requests.clone();
+        let app = Router::new().fallback(move |method: Method, uri: Uri, body: Bytes| async move {
+            let body = String::from_utf8_lossy(&body).to_string();
+            recorded
+                .lock()
+                .unwrap()
+                .push((method, uri.path().to_string(), body));
+            status
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    async fn push(settings: PushgatewaySettings, count: u32) -> bool {
+        let app = Settings::default();
+        let collector = Collector::new(
+            &app,
+            Arc::new(MetricSelector::default()),
+            Arc::new(InventoryChecker::from_settings(&app.inventory, &app.kubernetes).unwrap()),
+            Arc::new(ChangeDetector::new(None)),
+        );
+        run(&settings, &collector, count, Duration::from_millis(10))
+            .await
+            .unwrap()
+    }
+
+    fn settings(url: String, method: PushMethod, delete_on_exit: bool) -> PushgatewaySettings {
+        PushgatewaySettings {
+            url: Some(url),
+            job: "hw".to_string(),
+            method,
+            delete_on_exit,
+            max_attempts: 1,
+            ..PushgatewaySettings::default()
+        }
+    }
+
+    fn methods(requests: &Requests) -> Vec<Method> {
+        let requests = requests.lock().unwrap();
+        let path = &requests[0].1;
+        assert!(path.starts_with("/metrics/job/hw/node/"), "{path}");
+        assert!(requests.iter().all(|(_, p, _)| p == path));
+        requests
+            .iter()
+            .map(|(method, _, _)| method.clone())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn puts_each_collection_then_deletes_the_group() {
+        let (url, requests) = pushgateway(StatusCode::OK).await;
+        assert!(push(settings(url, PushMethod::Put, true), 2).await);
+
+        assert_eq!(
+            methods(&requests),
+            [Method::PUT, Method::PUT, Method::DELETE]
+        );
+        let requests = requests.lock().unwrap();
+        assert!(requests[0]
+            .2
+            .contains("# TYPE hw_node_uptime_seconds counter"));
+        assert!(requests[2].2.is_empty());
+    }
+
+    #[tokio::test]
+    async fn posts_and_keeps_the_group() {
+        let (url, requests) = pushgateway(StatusCode::ACCEPTED).await;
+        assert!(push(settings(url, PushMethod::Post, false), 1).await);
+        assert_eq!(methods(&requests), [Method::POST]);
+    }
+
+    #[tokio::test]
+    async fn deletes_the_group_after_rejected_pushes() {
+        let (url, requests) = pushgateway(StatusCode::BAD_REQUEST).await;
+        assert!(!push(settings(url, PushMethod::Put, true), 2).await);
+        assert_eq!(
+            methods(&requests),
+            [Method::PUT, Method::PUT, Method::DELETE]
+        );
+    }
+}
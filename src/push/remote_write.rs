@@ -0,0 +1,252 @@
+use prost::Message;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use super::{spawn_http_push, HttpTarget};
+use crate::collector::Snapshot;
+use crate::config::RemoteWriteSettings;
+use crate::metrics::exposition::{series_labels, MetricType};
+
+/// `prometheus.WriteRequest` from the remote-write 1.0 protocol
+#[derive(Clone, PartialEq, Message)]
+pub struct WriteRequest {
+    #[prost(message, repeated, tag = "1")]
+    pub timeseries: Vec<TimeSeries>,
+    #[prost(message, repeated, tag = "3")]
+    pub metadata: Vec<MetricMetadata>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct TimeSeries {
+    #[prost(message, repeated, tag = "1")]
+    pub labels: Vec<Label>,
+    #[prost(message, repeated, tag = "2")]
+    pub samples: Vec<Sample>,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Label {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub value: String,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct Sample {
+    #[prost(double, tag = "1")]
+    pub value: f64,
+    #[prost(int64, tag = "2")]
+    pub timestamp: i64,
+}
+
+#[derive(Clone, PartialEq, Message)]
+pub struct MetricMetadata {
+    /// 1 = counter, 2 = gauge
+    #[prost(int32, tag = "1")]
+    pub r#type: i32,
+    #[prost(string, tag = "2")]
+    pub metric_family_name: String,
+    #[prost(string, tag = "4")]
+    pub help: String,
+}
+
+/// Convert a snapshot into a remote-write request
+/// Labels of each series are sorted by name as the protocol requires
+pub fn build_write_request(snapshot: &Snapshot) -> WriteRequest {
+    let timestamp = snapshot.timestamp_ms();
+    let mut request = WriteRequest::default();
+
+    for family in snapshot.families.iter().filter(|f| !f.samples.is_empty()) {
+        for sample in &family.samples {
+            let mut labels: Vec<Label> = std::iter::once(("__name__", family.name.as_str()))
+                .chain(series_labels(sample, &snapshot.metrics.labels))
+                .map(|(name, value)| Label {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                })
+                .collect();
+            labels.sort_by(|a, b| a.name.cmp(&b.name));
+
+            request.timeseries.push(TimeSeries {
+                labels,
+                samples: vec![Sample {
+                    value: sample.value,
+                    timestamp,
+                }],
+            });
+        }
+
+        request.metadata.push(MetricMetadata {
+            r#type: match family.metric_type {
+                MetricType::Counter => 1,
+                MetricType::Gauge => 2,
+            },
+            metric_family_name: family.name.clone(),
+            help: family.help.clone(),
+        });
+    }
+
+    request
+}
+
+/// Protobuf encode and snappy (block format) compress a write request
+pub fn encode(request: &WriteRequest) -> Vec<u8> {
+    let raw = request.encode_to_vec();
+    snap::raw::Encoder::new()
+        .compress_vec(&raw)
+        .expect("snappy compression of an in-memory buffer cannot fail")
+}
+
+/// Start pushing snapshots to the remote-write endpoint
+pub fn spawn(
+    settings: RemoteWriteSettings,
+    snapshots: broadcast::Receiver<Arc<Snapshot>>,
+) -> anyhow::Result<()> {
+    let target = HttpTarget {
+        name: "Remote write",
+        url: settings.url,
+        headers: vec![
+            ("Content-Encoding", "snappy"),
+            ("Content-Type", "application/x-protobuf"),
+            ("X-Prometheus-Remote-Write-Version", "0.1.0"),
+        ],
+        auth: settings.auth,
+        timeout_secs: settings.timeout_secs,
+        queue_capacity: settings.queue_capacity,
+        min_backoff: Duration::from_millis(settings.min_backoff_ms),
+        max_backoff: Duration::from_secs(settings.max_backoff_secs),
+    };
+
+    spawn_http_push(target, snapshots, |snapshot| {
+        encode(&build_write_request(snapshot))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::HttpAuthSettings;
+    use crate::metrics::exposition::MetricFamily;
+    use axum::body::Bytes;
+    use axum::http::HeaderMap;
+    use axum::routing::post;
+    use axum::Router;
+    use tokio::sync::mpsc;
+
+    /// Receiver standing in for Prometheus, forwards each request
+    async fn receiver() -> (String, mpsc::UnboundedReceiver<(HeaderMap, Bytes)>) {
+        let (sender, requests) = mpsc::unbounded_channel();
+        let app = Router::new().route(
+            "/api/v1/write",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let _ = sender.send((headers, body));
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/api/v1/write", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, requests)
+    }
+
+    fn label(name: &str, value: &str) -> Label {
+        Label {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn pushes_snappy_protobuf_write_request() {
+        let (url, mut requests) = receiver().await;
+        let settings = RemoteWriteSettings {
+            url,
+            timeout_secs: 5,
+            queue_capacity: 4,
+            min_backoff_ms: 10,
+            max_backoff_secs: 1,
+            auth: HttpAuthSettings {
+                bearer_token: Some("secret".to_string()),
+                ..Default::default()
+            },
+        };
+        let (snapshots, receiver) = broadcast::channel(4);
+        spawn(settings, receiver).unwrap();
+
+        let families = vec![
+            MetricFamily::gauge("hw_cpu_usage_percent", "CPU usage", "cpu")
+                .with_sample(vec![("node".to_string(), "n1".to_string())], 12.5),
+            MetricFamily::counter("hw_gpu_xid_errors_total", "Xid errors", "gpu").with_sample(
+                vec![
+                    ("node".to_string(), "n1".to_string()),
+                    ("gpu".to_string(), "0".to_string()),
+                ],
+                3.0,
+            ),
+            MetricFamily::gauge("hw_empty", "No samples", "node"),
+        ];
+        snapshots
+            .send(Arc::new(Snapshot::with_families(
+                families,
+                &[("cluster", "c1")],
+            )))
+            .unwrap();
+
+        let (headers, body) = tokio::time::timeout(Duration::from_secs(10), requests.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(headers["content-encoding"], "snappy");
+        assert_eq!(headers["content-type"], "application/x-protobuf");
+        assert_eq!(headers["x-prometheus-remote-write-version"], "0.1.0");
+        assert_eq!(headers["authorization"], "Bearer secret");
+
+        let raw = snap::raw::Decoder::new().decompress_vec(&body).unwrap();
+        let request = WriteRequest::decode(raw.as_slice()).unwrap();
+
+        assert_eq!(
+            request.timeseries,
+            vec![
+                TimeSeries {
+                    labels: vec![
+                        label("__name__", "hw_cpu_usage_percent"),
+                        label("cluster", "c1"),
+                        label("node", "n1"),
+                    ],
+                    samples: vec![Sample {
+                        value: 12.5,
+                        timestamp: 1_700_000_000_123,
+                    }],
+                },
+                TimeSeries {
+                    labels: vec![
+                        label("__name__", "hw_gpu_xid_errors_total"),
+                        label("cluster", "c1"),
+                        label("gpu", "0"),
+                        label("node", "n1"),
+                    ],
+                    samples: vec![Sample {
+                        value: 3.0,
+                        timestamp: 1_700_000_000_123,
+                    }],
+                },
+            ]
+        );
+        assert_eq!(
+            request.metadata,
+            vec![
+                MetricMetadata {
+                    r#type: 2,
+                    metric_family_name: "hw_cpu_usage_percent".to_string(),
+                    help: "CPU usage".to_string(),
+                },
+                MetricMetadata {
+                    r#type: 1,
+                    metric_family_name: "hw_gpu_xid_errors_total".to_string(),
+                    help: "Xid errors".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,95 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::collector::Snapshot;
+use crate::config::{SocketPushSettings, Transport};
+
+/// Keep datagrams below a typical Ethernet MTU to avoid IP fragmentation
+const MAX_UDP_PAYLOAD: usize = 1400;
+
+/// Encode every snapshot as newline separated text and send it to a TCP or
+/// UDP listener. Failed sends are logged and the next collection is sent as
+/// usual, line protocols have no acknowledgement to retry against
+pub fn spawn_line_push<F>(
+    name: &'static str,
+    settings: SocketPushSettings,
+    mut snapshots: broadcast::Receiver<Arc<Snapshot>>,
+    encode: F,
+) where
+    F: Fn(&Snapshot) -> String + Send + 'static,
+{
+    info!(
+        "{} push enabled, sending to {} over {:?}",
+        name, settings.address, settings.transport
+    );
+
+    tokio::spawn(async move {
+        loop {
+            let snapshot = match snapshots.recv().await {
+                Ok(snapshot) => snapshot,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("{} push skipped {} snapshot(s)", name, skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            let payload = encode(&snapshot);
+            let timeout = Duration::from_secs(settings.timeout_secs);
+            let result = match settings.transport {
+                Transport::Tcp => {
+                    tokio::time::timeout(timeout, send_tcp(&settings.address, &payload)).await
+                }
+                Transport::Udp => {
+                    tokio::time::timeout(timeout, send_udp(&settings.address, &payload)).await
+                }
+            };
+
+            match result {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => warn!("{} push to {} failed: {}", name, settings.address, e),
+                Err(_) => warn!("{} push to {} timed out", name, settings.address),
+            }
+        }
+    });
+}
+
+async fn send_tcp(address: &str, payload: &str) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(address).await?;
+    stream.write_all(payload.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Send whole lines per datagram so receivers never see a split line
+async fn send_udp(address: &str, payload: &str) -> std::io::Result<()> {
+    let target = tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| std::io::Error::other(format!("could not resolve {}", address)))?;
+    let bind_addr = if target.is_ipv4() {
+        "0.0.0.0:0"
+    } else {
+        "[::]:0"
+    };
+    let socket = UdpSocket::bind(bind_addr).await?;
+    socket.connect(target).await?;
+
+    let mut datagram = String::new();
+    for line in payload.split_inclusive('\n') {
+        if !datagram.is_empty() && datagram.len() + line.len() > MAX_UDP_PAYLOAD {
+            socket.send(datagram.as_bytes()).await?;
+            datagram.clear();
+        }
+        datagram.push_str(line);
+    }
+    if !datagram.is_empty() {
+        socket.send(datagram.as_bytes()).await?;
+    }
+
+    Ok(())
+}
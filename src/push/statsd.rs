@@ -0,0 +1,296 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use tokio::net::{UdpSocket, UnixDatagram};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{info, warn};
+
+use crate::collector::Snapshot;
+use crate::config::{StatsdFlavor, StatsdSettings};
+use crate::metrics::exposition::{format_value, series_labels};
+
+/// Datadog's recommended payload limits, below the MTU for UDP
+const DEFAULT_UDP_PACKET_SIZE: usize = 1432;
+const DEFAULT_UNIX_PACKET_SIZE: usize = 8192;
+
+/// Render the families of a snapshot as StatsD gauges, one line per series
+/// Counters are sent as gauges of their running total. There is no client
+/// side sampling: agents never scale gauges by `|@<rate>`, so a dropped
+/// series would simply be missing
+pub fn encode(snapshot: &Snapshot, settings: &StatsdSettings) -> Vec<String> {
+    let mut lines = Vec::new();
+
+    for family in &snapshot.families {
+        for sample in &family.samples {
+            if !sample.value.is_finite() {
+                continue;
+            }
+
+            let mut line = String::new();
+            if !settings.prefix.is_empty() {
+                line.push_str(&sanitize_name(&settings.prefix));
+                line.push('.');
+            }
+            line.push_str(&sanitize_name(&family.name));
+
+            if settings.flavor == StatsdFlavor::Statsd {
+                // Plain StatsD has no tags, keep per-device series apart by path
+                for (_, value) in &sample.labels {
+                    line.push('.');
+                    line.push_str(&sanitize_name(value).replace('.', "_"));
+                }
+            }
+
+            let _ = write!(line, ":{}|g", format_value(sample.value));
+
+            if settings.flavor == StatsdFlavor::Dogstatsd {
+                let tags: Vec<String> = series_labels(sample, &snapshot.metrics.labels)
+                    .into_iter()
+                    .filter(|(_, value)| !value.is_empty())
+                    .map(|(name, value)| format!("{}:{}", sanitize_tag(name), sanitize_tag(value)))
+                    .collect();
+                if !tags.is_empty() {
+                    let _ = write!(line, "|#{}", tags.join(","));
+                }
+            }
+
+            lines.push(line);
+        }
+    }
+
+    lines
+}
+
+/// Metric names cannot contain the `:` and `|` separators or `@`
+fn sanitize_name(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ':' | '|' | '@' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Tags are comma separated and end the line, so `,`, `|` and newlines are replaced
+fn sanitize_tag(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            ',' | '|' | '\n' | '\r' => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Pack lines into newline separated datagrams no larger than `max_size`
+/// A single line longer than the limit is still sent on its own
+fn pack(lines: &[String], max_size: usize) -> Vec<String> {
+    let mut packets = Vec::new();
+    let mut packet = String::new();
+
+    for line in lines {
+        if !packet.is_empty() && packet.len() + 1 + line.len() > max_size {
+            packets.push(std::mem::take(&mut packet));
+        }
+        if !packet.is_empty() {
+            packet.push('\n');
+        }
+        packet.push_str(line);
+    }
+    if !packet.is_empty() {
+        packets.push(packet);
+    }
+
+    packets
+}
+
+enum Target {
+    Udp(String),
+    Unix(String),
+}
+
+enum Socket {
+    Udp(UdpSocket),
+    Unix(UnixDatagram, String),
+}
+
+impl Target {
+    fn parse(address: &str) -> Self {
+        match address.strip_prefix("unix://") {
+            Some(path) => Target::Unix(path.to_string()),
+            None => Target::Udp(address.to_string()),
+        }
+    }
+
+    async fn connect(&self) -> std::io::Result<Socket> {
+        match self {
+            Target::Udp(address) => {
+                let target = tokio::net::lookup_host(address.as_str())
+                    .await?
+                    .next()
+                    .ok_or_else(|| {
+                        std::io::Error::other(format!("could not resolve {}", address))
+                    })?;
+                let bind_addr = if target.is_ipv4() {
+                    "0.0.0.0:0"
+                } else {
+                    "[::]:0"
+                };
+                let socket = UdpSocket::bind(bind_addr).await?;
+                socket.connect(target).await?;
+                Ok(Socket::Udp(socket))
+            }
+            Target::Unix(path) => Ok(Socket::Unix(UnixDatagram::unbound()?, path.clone())),
+        }
+    }
+}
+
+impl Socket {
+    async fn send(&self, packet: &str) -> std::io::Result<()> {
+        match self {
+            Socket::Udp(socket) => socket.send(packet.as_bytes()).await.map(|_| ()),
+            Socket::Unix(socket, path) => socket.send_to(packet.as_bytes(), path).await.map(|_| ()),
+        }
+    }
+}
+
+/// Start emitting snapshots to a StatsD / DogStatsD agent
+/// The socket is reopened after a failed send, StatsD is fire and forget so
+/// nothing is retried
+pub fn spawn(
+    settings: StatsdSettings,
+    mut snapshots: broadcast::Receiver<Arc<Snapshot>>,
+) -> anyhow::Result<()> {
+    let target = Target::parse(&settings.address);
+    let max_packet_size = settings.max_packet_size.unwrap_or(match target {
+        Target::Udp(_) => DEFAULT_UDP_PACKET_SIZE,
+        Target::Unix(_) => DEFAULT_UNIX_PACKET_SIZE,
+    });
+
+    info!(
+        "StatsD push enabled, sending to {} as {:?}",
+        settings.address, settings.flavor
+    );
+
+    tokio::spawn(async move {
+        let mut socket: Option<Socket> = None;
+
+        loop {
+            let snapshot = match snapshots.recv().await {
+                Ok(snapshot) => snapshot,
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!("StatsD push skipped {} snapshot(s)", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+
+            if socket.is_none() {
+                match target.connect().await {
+                    Ok(connected) => socket = Some(connected),
+                    Err(e) => {
+                        warn!("StatsD push to {} failed: {}", settings.address, e);
+                        continue;
+                    }
+                }
+            }
+            let Some(connected) = &socket else {
+                continue;
+            };
+
+            let lines = encode(&snapshot, &settings);
+            for packet in pack(&lines, max_packet_size) {
+                if let Err(e) = connected.send(&packet).await {
+                    warn!("StatsD push to {} failed: {}", settings.address, e);
+                    socket = None;
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::exposition::MetricFamily;
+    use std::time::Duration;
+
+    fn settings(address: &str, flavor: StatsdFlavor) -> StatsdSettings {
+        StatsdSettings {
+            address: address.to_string(),
+            prefix: "hw".to_string(),
+            flavor,
+            max_packet_size: None,
+        }
+    }
+
+    fn snapshot() -> Snapshot {
+        let families = vec![
+            MetricFamily::gauge("hw_gpu_temperature_celsius", "Temperature", "gpu").with_sample(
+                vec![
+                    ("node".to_string(), "n1".to_string()),
+                    ("gpu".to_string(), "0".to_string()),
+                    ("uuid".to_string(), "GPU-a.b".to_string()),
+                ],
+                41.5,
+            ),
+            MetricFamily::counter("hw_gpu_xid_errors_total", "Xid errors", "gpu").with_sample(
+                vec![
+                    ("node".to_string(), "n1".to_string()),
+                    ("gpu".to_string(), "0".to_string()),
+                ],
+                3.0,
+            ),
+            MetricFamily::gauge("hw_cpu_usage_percent", "CPU usage", "cpu")
+                .with_sample(vec![("node".to_string(), "n1".to_string())], f64::NAN),
+        ];
+        Snapshot::with_families(families, &[("cluster", "c,1")])
+    }
+
+    /// Push one snapshot to a local UDP listener and return the datagram
+    async fn receive(flavor: StatsdFlavor) -> String {
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let (snapshots, receiver) = broadcast::channel(4);
+        spawn(settings(&address, flavor), receiver).unwrap();
+        snapshots.send(Arc::new(snapshot())).unwrap();
+
+        let mut buffer = [0u8; 2048];
+        let size = tokio::time::timeout(Duration::from_secs(10), listener.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        String::from_utf8(buffer[..size].to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn sends_dogstatsd_lines() {
+        assert_eq!(
+            receive(StatsdFlavor::Dogstatsd).await,
+            "hw.hw_gpu_temperature_celsius:41.5|g|#node:n1,gpu:0,uuid:GPU-a.b,cluster:c_1\n\
+             hw.hw_gpu_xid_errors_total:3|g|#node:n1,gpu:0,cluster:c_1"
+        );
+    }
+
+    #[tokio::test]
+    async fn sends_statsd_lines() {
+        assert_eq!(
+            receive(StatsdFlavor::Statsd).await,
+            "hw.hw_gpu_temperature_celsius.n1.0.GPU-a_b:41.5|g\n\
+             hw.hw_gpu_xid_errors_total.n1.0:3|g"
+        );
+    }
+
+    #[test]
+    fn packs_lines_up_to_max_size() {
+        let lines: Vec<String> = ["a:1|g", "b:2|g", "c:3|g"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+        assert_eq!(pack(&lines, 11), vec!["a:1|g\nb:2|g", "c:3|g"]);
+        assert_eq!(pack(&lines, 3), vec!["a:1|g", "b:2|g", "c:3|g"]);
+    }
+}